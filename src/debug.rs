@@ -1,6 +1,7 @@
 mod debug_cube;
 mod debug_cube_net;
 mod debug_instance;
+mod debug_renderer;
 
 pub use debug_cube::{
   DebugCube,
@@ -14,3 +15,7 @@ pub use debug_instance::{
   DebugInstance,
   DebugInstanceRaw
 };
+pub use debug_renderer::{
+  DebugRenderer,
+  DebugLineVertex
+};
@@ -0,0 +1,146 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::graphics::Texture;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugLineVertex {
+  pub position: [f32; 3],
+  pub color: [f32; 4],
+}
+
+// World space debug lines (raycasts, normals, bounds). Lines are queued
+// during the frame, drawn as a line list in the main pass over the scene's
+// models and cleared once the frame is presented
+pub struct DebugRenderer {
+  vertices: Vec<DebugLineVertex>,
+  pipeline: wgpu::RenderPipeline,
+  vertex_buffer: wgpu::Buffer,
+  vertex_capacity: usize,
+  vertex_count: u32,
+}
+
+impl DebugRenderer {
+  pub fn new(
+    device: &wgpu::Device,
+    color_format: wgpu::TextureFormat,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+  ) -> DebugRenderer {
+    let layout = device.create_pipeline_layout(
+      &wgpu::PipelineLayoutDescriptor {
+        label: Some("Debug line pipeline layout"),
+        bind_group_layouts: &[camera_bind_group_layout],
+        push_constant_ranges: &[],
+      }
+    );
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Debug line shader"),
+      source: wgpu::ShaderSource::Wgsl(include_str!("debug_line.wgsl").into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Debug line pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[wgpu::VertexBufferLayout {
+          array_stride: std::mem::size_of::<DebugLineVertex>() as wgpu::BufferAddress,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4],
+        }],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format: color_format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::LineList,
+        cull_mode: None,
+        ..Default::default()
+      },
+      // depth tested against the scene but never written, so lines
+      // don't hide each other
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    let vertex_capacity = 2 * 256;
+    let vertex_buffer = create_vertex_buffer(device, vertex_capacity);
+
+    Self {
+      vertices: Vec::new(),
+      pipeline,
+      vertex_buffer,
+      vertex_capacity,
+      vertex_count: 0,
+    }
+  }
+
+  pub fn draw_line(&mut self, a: Point3<f32>, b: Point3<f32>, color: [f32; 4]) {
+    self.vertices.push(DebugLineVertex { position: a.into(), color });
+    self.vertices.push(DebugLineVertex { position: b.into(), color });
+  }
+
+  pub fn draw_ray(&mut self, origin: Point3<f32>, dir: Vector3<f32>, length: f32, color: [f32; 4]) {
+    if dir.magnitude2() == 0. {
+      return;
+    }
+    self.draw_line(origin, origin + dir.normalize() * length, color);
+  }
+
+  // every vertex queued this frame, two per line
+  pub fn vertices(&self) -> &Vec<DebugLineVertex> {
+    &self.vertices
+  }
+
+  pub fn line_count(&self) -> usize {
+    self.vertices.len() / 2
+  }
+
+  pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    if self.vertices.len() > self.vertex_capacity {
+      self.vertex_capacity = self.vertices.len().next_power_of_two();
+      self.vertex_buffer = create_vertex_buffer(device, self.vertex_capacity);
+    }
+    if self.vertices.len() > 0 {
+      queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+    self.vertex_count = self.vertices.len() as u32;
+  }
+
+  pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+    if self.vertex_count == 0 {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.draw(0..self.vertex_count, 0..1);
+  }
+
+  pub fn clear(&mut self) {
+    self.vertices.clear();
+    self.vertex_count = 0;
+  }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+  device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Debug line vertex buffer"),
+    size: (capacity * std::mem::size_of::<DebugLineVertex>()) as wgpu::BufferAddress,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false
+  })
+}
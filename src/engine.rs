@@ -12,5 +12,16 @@ mod state;
 mod events;
 mod util;
 mod collisions;
+mod mouse;
+mod raycasting;
+mod rigid_body;
+mod animation;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(test)]
+mod test_util;
 
-pub use scene::Scene;
\ No newline at end of file
+pub use scene::{DebugStats, Scene, SurfaceRecovery};
+pub use rigid_body::RigidBody;
+#[cfg(feature = "gamepad")]
+pub use gamepad::{translate_gamepad_event, Gamepads};
\ No newline at end of file
@@ -0,0 +1,127 @@
+use cgmath::{InnerSpace, Quaternion, VectorSpace};
+
+use super::transforms::ModelTransform;
+
+// maps linear progress between two keyframes (0 to 1) to eased progress
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+  Linear,
+  EaseIn,
+  EaseOut,
+  EaseInOut,
+  // holds the earlier keyframe until the next one is reached
+  Step,
+}
+
+impl Easing {
+  pub fn apply(&self, t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    match self {
+      Easing::Linear => t,
+      Easing::EaseIn => t * t,
+      Easing::EaseOut => t * (2. - t),
+      Easing::EaseInOut => t * t * (3. - 2. * t),
+      Easing::Step => if t < 1. { 0. } else { 1. },
+    }
+  }
+}
+
+// Keyframed ModelTransform animation. Keyframes are (time in seconds,
+// transform) and are kept sorted by time. Position, rotation, scale, opacity
+// and tint are interpolated, everything else comes from the earlier keyframe
+#[derive(Clone)]
+pub struct AnimationTrack {
+  keyframes: Vec<(f32, ModelTransform)>,
+  easing: Easing,
+  looping: bool,
+  elapsed: f32,
+}
+
+impl AnimationTrack {
+  pub fn new(mut keyframes: Vec<(f32, ModelTransform)>) -> AnimationTrack {
+    keyframes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    Self {
+      keyframes,
+      easing: Easing::Linear,
+      looping: false,
+      elapsed: 0.,
+    }
+  }
+
+  pub fn easing(mut self, easing: Easing) -> AnimationTrack {
+    self.easing = easing;
+    self
+  }
+
+  // looping tracks wrap back to their first keyframe, others hold the last
+  pub fn looping(mut self, looping: bool) -> AnimationTrack {
+    self.looping = looping;
+    self
+  }
+
+  // time of the last keyframe
+  pub fn duration(&self) -> f32 {
+    self.keyframes.last().map(|k| k.0).unwrap_or(0.)
+  }
+
+  pub fn elapsed(&self) -> f32 {
+    self.elapsed
+  }
+
+  pub fn is_finished(&self) -> bool {
+    !self.looping && self.elapsed >= self.duration()
+  }
+
+  pub fn advance(&mut self, dt: f32) {
+    self.elapsed += dt;
+    let duration = self.duration();
+    if self.looping && duration > 0. {
+      self.elapsed %= duration;
+    }
+  }
+
+  // the transform at the track's current time
+  pub fn current(&self) -> Option<ModelTransform> {
+    self.sample(self.elapsed)
+  }
+
+  pub fn sample(&self, time: f32) -> Option<ModelTransform> {
+    let first = self.keyframes.first()?;
+    if time <= first.0 {
+      return Some(first.1.clone());
+    }
+    let next_idx = match self.keyframes.iter().position(|k| k.0 > time) {
+      Some(idx) => idx,
+      None => return self.keyframes.last().map(|k| k.1.clone())
+    };
+    let (start_time, start) = &self.keyframes[next_idx - 1];
+    let (end_time, end) = &self.keyframes[next_idx];
+    let t = self.easing.apply((time - start_time) / (end_time - start_time));
+
+    let mut transform = start.clone();
+    transform.pos = start.pos.lerp(end.pos, t);
+    transform.rot = slerp(start.rot, end.rot, t);
+    transform.opacity = start.opacity + (end.opacity - start.opacity) * t;
+    for (c, (s, e)) in transform.tint.iter_mut().zip(start.tint.iter().zip(end.tint.iter())) {
+      *c = s + (e - s) * t;
+    }
+    transform = transform.scale(start.scale.lerp(end.scale, t));
+    if !transform.instanced {
+      for instance in transform.instances.iter_mut() {
+        instance.position = transform.pos;
+        instance.rotation = transform.rot;
+      }
+    }
+    Some(transform)
+  }
+}
+
+// shortest path slerp. the zero quaternion (Quaternion::new(0., 0., 0., 0.))
+// can't be interpolated, so it just switches halfway
+fn slerp(a: Quaternion<f32>, b: Quaternion<f32>, t: f32) -> Quaternion<f32> {
+  let b = if a.dot(b) < 0. { -b } else { b };
+  if a.magnitude2() == 0. || b.magnitude2() == 0. {
+    return if t < 0.5 { a } else { b };
+  }
+  a.normalize().slerp(b.normalize(), t)
+}
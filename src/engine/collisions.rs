@@ -1,7 +1,9 @@
 mod collider;
 mod sdf_boundary;
 mod collision_manager;
+mod mesh_boundary;
 
 pub use collider::{Collider, Collision, ColliderBoundary};
-pub use collision_manager::CollisionManager;
-pub use sdf_boundary::SdfBoundary;
\ No newline at end of file
+pub use collision_manager::{update_collider_position, CollisionManager};
+pub use sdf_boundary::SdfBoundary;
+pub use mesh_boundary::MeshBoundary;
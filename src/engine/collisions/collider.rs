@@ -1,24 +1,88 @@
-use std::{collections::HashMap, sync::{Arc, Mutex}};
+use std::{cmp::Ordering, collections::HashMap, fmt, sync::{Arc, Mutex}};
 
-use cgmath::{num_traits::abs, EuclideanSpace, Matrix4, Point3, Quaternion, SquareMatrix, Transform, Vector3};
+use cgmath::{num_traits::abs, EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Quaternion, SquareMatrix, Transform, Vector3};
 
 use crate::{engine::{component_store::ComponentKey, transforms::ColliderTransform}, sdf::SdfShape};
 
 pub const NORMAL_TOL: f32 = 0.01;
+const CONTACT_TOL: f32 = 0.01; // incident points this far outside the reference face still count
 
+// Boundaries work entirely in their own local space. Collider carries
+// points from world space into that space (and results back out) with its
+// ColliderTransform, so the transform is applied exactly once
 pub trait ColliderBoundary: Send + Sync {
   fn closest_boundary_pt(&self, pt: Point3<f32>) -> Point3<f32>;
   fn is_interior_point(&self, pt: Point3<f32>) -> bool;
   fn get_boundary_normal(&self, pt: Point3<f32>, tol: f32) -> Option<Vector3<f32>>;
   fn center(&self) -> Point3<f32>;
+  // distance along the (normalized) ray to the first boundary hit
+  fn ray_intersect(&self, origin: Point3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32>;
+  // (min, max) box containing the boundary, if it can provide one
+  fn aabb(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    None
+  }
+  // (min, max) when the boundary is exactly that box. box pairs report
+  // every contact point instead of a single one
+  fn box_bounds(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    None
+  }
+  // points on the boundary for debug drawing, sampled on a grid with
+  // resolution cells along the longest side. only boundaries without
+  // explicit geometry (i.e. sdfs) need these
+  fn surface_samples(&self, _resolution: usize) -> Vec<Point3<f32>> {
+    Vec::new()
+  }
 }
 
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Collision {
   pub colliders: (u32, u32),
   pub loc: Point3<f32>,
-  pub normal: Option<Vector3<f32>>
+  pub normal: Option<Vector3<f32>>,
+  pub depth: f32, // how far loc sits inside the first collider
+  pub is_trigger: bool, // either collider is a trigger
+  // world space contact manifold. box pairs get the clipped overlap of
+  // their touching faces, anything else just loc
+  pub contacts: Vec<Point3<f32>>,
+}
+
+impl Collision {
+  pub fn penetration_depth(&self) -> f32 {
+    self.depth
+  }
+
+  // same colliders, with every point, normal and depth within eps. contacts
+  // are compared in order
+  pub fn approx_eq(&self, other: &Collision, eps: f32) -> bool {
+    let normals_eq = match (self.normal, other.normal) {
+      (Some(a), Some(b)) => (a - b).magnitude() <= eps,
+      (None, None) => true,
+      _ => false
+    };
+    self.colliders == other.colliders
+      && self.is_trigger == other.is_trigger
+      && self.loc.distance(other.loc) <= eps
+      && (self.depth - other.depth).abs() <= eps
+      && normals_eq
+      && self.contacts.len() == other.contacts.len()
+      && self.contacts.iter().zip(other.contacts.iter()).all(|(a, b)| a.distance(*b) <= eps)
+  }
+}
+
+impl fmt::Display for Collision {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Collision {} -> {} at ({:.3}, {:.3}, {:.3})", self.colliders.0, self.colliders.1, self.loc.x, self.loc.y, self.loc.z)?;
+    match self.normal {
+      Some(n) => write!(f, ", normal ({:.3}, {:.3}, {:.3})", n.x, n.y, n.z)?,
+      None => write!(f, ", no normal")?,
+    }
+    write!(f, ", depth {:.3}, {} contacts", self.depth, self.contacts.len())?;
+    if self.is_trigger {
+      write!(f, " (trigger)")?;
+    }
+    Ok(())
+  }
 }
 
 pub struct Collider {
@@ -26,7 +90,9 @@ pub struct Collider {
   underlying: Arc<Mutex<dyn ColliderBoundary>>,
   pub parent: ComponentKey,
   collision_map: HashMap<u32, Collision>,
-  pub transform: ColliderTransform
+  pub transform: ColliderTransform,
+  enabled: bool,
+  is_trigger: bool,
 }
 
 impl Collider {
@@ -41,39 +107,182 @@ impl Collider {
       underlying: Arc::new(Mutex::new(underlying)),
       parent,
       collision_map: HashMap::new(),
-      transform: transform.unwrap_or(ColliderTransform::default(parent))
+      transform: transform.unwrap_or(ColliderTransform::default(parent)),
+      enabled: true,
+      is_trigger: false
     }
   }
 
+  // triggers report collisions like any collider but are never pushed
+  // apart by resolve_penetration
+  pub fn set_trigger(&mut self, is_trigger: bool) {
+    self.is_trigger = is_trigger;
+  }
+
+  pub fn is_trigger(&self) -> bool {
+    self.is_trigger
+  }
+
+  // disabled colliders are skipped by collision checks and raycasts. any
+  // collision they were part of ends on the next trigger_collision_events
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  // closest world space point on the boundary
   pub fn closest_boundary_pt(&self, pt: Point3<f32>) -> Point3<f32> {
-    // needs to transform the point into own coord system and then find closest
-    if let Some(t_mat) = self.transform.to_coord_matrix().invert() {
-      let transformed = t_mat.transform_point(pt);
-      return self.underlying.lock().unwrap().closest_boundary_pt(transformed)
+    let local = self.get_collider_coord_matrix().transform_point(pt);
+    let local_closest = self.underlying.lock().unwrap().closest_boundary_pt(local);
+    self.transform.to_coord_matrix().transform_point(local_closest)
+  }
+
+  // intersects a world space ray with the boundary, returning the distance
+  // along the ray to the hit
+  pub fn ray_intersect(&self, origin: Point3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+    let coord_matrix = self.get_collider_coord_matrix();
+    let local_origin = coord_matrix.transform_point(origin);
+    let local_dir = coord_matrix.transform_vector(dir).normalize();
+    self.underlying.lock().unwrap().ray_intersect(local_origin, local_dir, max_dist)
+  }
+
+  // signed distance estimate from a world space point to the boundary
+  // (negative inside)
+  pub fn boundary_dist(&self, pt: Point3<f32>) -> f32 {
+    let local = self.get_collider_coord_matrix().transform_point(pt);
+    let underlying = self.underlying.lock().unwrap();
+    let dist = local.distance(underlying.closest_boundary_pt(local));
+    if underlying.is_interior_point(local) {
+      return -dist
     }
-    self.underlying.lock().unwrap().closest_boundary_pt(pt)
+    dist
+  }
+
+  // world space center of the boundary: its local center carried through
+  // the collider transform (global when cached, relative otherwise)
+  pub fn center(&self) -> Point3<f32> {
+    let local_center = self.underlying.lock().unwrap().center();
+    self.transform.to_coord_matrix().transform_point(local_center)
   }
 
   pub fn collide(&self, other: &Collider) -> Option<Collision> {
-    let mut center = self.underlying.lock().unwrap().center().to_vec() + self.transform.relative_pos;
-    if let Some(global_transform) = self.transform.get_global_transform() {
-      // println!("Collider global transform: {:?}", global_transform);
-      center = self.underlying.lock().unwrap().center().to_vec() + global_transform.pos;
-    }
-    let closest = other.closest_boundary_pt(Point3::from_vec(center));
-    // closest point has to be transformed into collider space ofc
+    // world space contact candidate on the other boundary
+    let closest = other.closest_boundary_pt(self.center());
     let local_pos = self.get_collider_coord_matrix().transform_point(closest);
-    if self.underlying.lock().unwrap().is_interior_point(local_pos) {
-      let normal = self.underlying.lock().unwrap().get_boundary_normal(closest, NORMAL_TOL);
-      return Some(Collision {
-        loc: closest,
-        normal,
-        colliders: (self.index, other.index)
-      })
+    let (normal, depth) = {
+      let underlying = self.underlying.lock().unwrap();
+      if !underlying.is_interior_point(local_pos) {
+        return None
+      }
+      let local_closest = underlying.closest_boundary_pt(local_pos);
+      let normal = underlying.get_boundary_normal(local_closest, NORMAL_TOL)
+        .map(|n| self.transform.to_coord_matrix().transform_vector(n).normalize());
+      (normal, local_pos.distance(local_closest))
+    };
+    let towards_other = normal.unwrap_or(other.center() - self.center());
+    let contacts = self.box_contacts(other, towards_other)
+      .filter(|contacts| !contacts.is_empty())
+      .unwrap_or(vec![closest]);
+    Some(Collision {
+      loc: closest,
+      normal,
+      colliders: (self.index, other.index),
+      depth,
+      is_trigger: self.is_trigger || other.is_trigger,
+      contacts
+    })
+  }
+
+  // contact points between two box boundaries. the face of self most facing
+  // dir is the reference face and the face of other most opposed to it the
+  // incident face. the incident face is clipped to the sides of the
+  // reference face (sutherland-hodgman), and whatever is left at or below
+  // the reference face is in contact
+  fn box_contacts(&self, other: &Collider, dir: Vector3<f32>) -> Option<Vec<Point3<f32>>> {
+    let own_faces = self.world_box_faces()?;
+    let other_faces = other.world_box_faces()?;
+    let reference = own_faces.iter()
+      .max_by(|a, b| a.normal.dot(dir).partial_cmp(&b.normal.dot(dir)).unwrap_or(Ordering::Equal))?;
+    let incident = other_faces.iter()
+      .min_by(|a, b| a.normal.dot(reference.normal).partial_cmp(&b.normal.dot(reference.normal)).unwrap_or(Ordering::Equal))?;
+
+    let mut polygon = incident.corners.to_vec();
+    for i in 0..4 {
+      let (p, q) = (reference.corners[i], reference.corners[(i + 1) % 4]);
+      // plane through the edge, perpendicular to the face and facing away from it
+      let mut side = reference.normal.cross(q - p);
+      if side.dot(p.midpoint(q) - reference.center) < 0. {
+        side = -side;
+      }
+      polygon = clip_polygon(&polygon, p, side);
+      if polygon.is_empty() {
+        return Some(polygon)
+      }
     }
-    None
+    Some(polygon.into_iter()
+      .filter(|pt| (*pt - reference.center).dot(reference.normal) <= CONTACT_TOL)
+      .collect())
+  }
+
+  // world space faces of a box boundary
+  fn world_box_faces(&self) -> Option<[BoxFace; 6]> {
+    let (min, max) = self.underlying.lock().unwrap().box_bounds()?;
+    let coord_matrix = self.transform.to_coord_matrix();
+    let corner = |x: bool, y: bool, z: bool| coord_matrix.transform_point(Point3::new(
+      if x { max.x } else { min.x },
+      if y { max.y } else { min.y },
+      if z { max.z } else { min.z },
+    ));
+    let box_center = coord_matrix.transform_point(min.midpoint(max));
+    Some(core::array::from_fn(|i| {
+      // faces are ordered -x, +x, -y, +y, -z, +z. the other two axes walk
+      // around the face so consecutive corners share an edge
+      let (axis, side) = (i / 2, i % 2 == 1);
+      let corners = [(false, false), (true, false), (true, true), (false, true)].map(|(u, v)| match axis {
+        0 => corner(side, u, v),
+        1 => corner(u, side, v),
+        _ => corner(u, v, side),
+      });
+      let center = Point3::from_vec(corners.iter().fold(Vector3::new(0., 0., 0.), |sum, c| sum + c.to_vec()) / 4.);
+      let mut normal = (corners[1] - corners[0]).cross(corners[3] - corners[0]).normalize();
+      if normal.dot(center - box_center) < 0. {
+        normal = -normal;
+      }
+      BoxFace {
+        corners,
+        center,
+        normal
+      }
+    }))
+  }
+
+  // world space corners of the boundary's box, for debug drawing. ordered
+  // by bits: x from bit 0, y from bit 1, z from bit 2 (0 = min, 1 = max)
+  pub fn world_aabb_corners(&self) -> Option<[Point3<f32>; 8]> {
+    let (min, max) = self.underlying.lock().unwrap().aabb()?;
+    let coord_matrix = self.transform.to_coord_matrix();
+    Some(core::array::from_fn(|i| {
+      let corner = Point3::new(
+        if i & 1 == 0 { min.x } else { max.x },
+        if i & 2 == 0 { min.y } else { max.y },
+        if i & 4 == 0 { min.z } else { max.z },
+      );
+      coord_matrix.transform_point(corner)
+    }))
   }
 
+  pub fn world_surface_samples(&self, resolution: usize) -> Vec<Point3<f32>> {
+    let coord_matrix = self.transform.to_coord_matrix();
+    self.underlying.lock().unwrap().surface_samples(resolution)
+      .into_iter()
+      .map(|p| coord_matrix.transform_point(p))
+      .collect::<Vec<Point3<f32>>>()
+  }
+
+  // world -> boundary local space
   pub fn get_collider_coord_matrix(&self) -> Matrix4<f32> {
     if let Some(transform_matrix) = self.transform.to_coord_matrix().invert() {
       return transform_matrix
@@ -115,4 +324,76 @@ impl Collider {
   pub fn update_rot(&mut self, new_rot: Quaternion<f32>) {
     self.transform.update_rot(new_rot);
   }
-}
\ No newline at end of file
+}
+
+struct BoxFace {
+  corners: [Point3<f32>; 4],
+  center: Point3<f32>,
+  normal: Vector3<f32>, // outward
+}
+
+// sutherland-hodgman against one plane, keeping the side normal points away from
+fn clip_polygon(polygon: &[Point3<f32>], plane_pt: Point3<f32>, normal: Vector3<f32>) -> Vec<Point3<f32>> {
+  let mut clipped = Vec::new();
+  for (i, &curr) in polygon.iter().enumerate() {
+    let prev = polygon[(i + polygon.len() - 1) % polygon.len()];
+    let curr_dist = (curr - plane_pt).dot(normal);
+    let prev_dist = (prev - plane_pt).dot(normal);
+    if (curr_dist <= 0.) != (prev_dist <= 0.) {
+      let t = prev_dist / (prev_dist - curr_dist);
+      clipped.push(prev + (curr - prev) * t);
+    }
+    if curr_dist <= 0. {
+      clipped.push(curr);
+    }
+  }
+  clipped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn collision() -> Collision {
+    Collision {
+      colliders: (0, 1),
+      loc: Point3::new(1., 2., 3.),
+      normal: Some(Vector3::unit_y()),
+      depth: 0.5,
+      is_trigger: false,
+      contacts: vec![Point3::new(1., 2., 3.), Point3::new(2., 2., 3.)],
+    }
+  }
+
+  #[test]
+  fn approx_eq_allows_small_differences() {
+    let mut other = collision();
+    other.loc.x += 1e-4;
+    other.depth += 1e-4;
+    other.contacts[1].z -= 1e-4;
+    assert!(collision().approx_eq(&other, 1e-3));
+    assert!(!collision().approx_eq(&other, 1e-5));
+  }
+
+  #[test]
+  fn approx_eq_needs_matching_colliders_and_normals() {
+    let mut other = collision();
+    other.colliders = (1, 0);
+    assert!(!collision().approx_eq(&other, 1e-3));
+
+    let mut other = collision();
+    other.normal = None;
+    assert!(!collision().approx_eq(&other, 1e-3));
+    other.normal = Some(-Vector3::unit_y());
+    assert!(!collision().approx_eq(&other, 1e-3));
+  }
+
+  #[test]
+  fn approx_eq_compares_contacts_in_order() {
+    let mut other = collision();
+    other.contacts.reverse();
+    assert!(!collision().approx_eq(&other, 1e-3));
+    other.contacts.pop();
+    assert!(!collision().approx_eq(&other, 1e-3));
+  }
+}
@@ -1,6 +1,6 @@
 use std::{borrow::Borrow, collections::{HashMap, HashSet}, hash::Hash, ops::Index, sync::{Arc, Mutex, RwLock}};
 
-use cgmath::Matrix4;
+use cgmath::{InnerSpace, Matrix4, Quaternion, Vector3};
 
 use crate::engine::{component::Component, component_store::ComponentKey, events::{Event, EventData, EventKey, EventManager}, transform_queue::{apply_quaternion_transform, to_point, to_vec}, transforms::{ColliderTransform, ComponentTransform}, Scene};
 
@@ -26,6 +26,9 @@ pub struct CollisionManager {
   colliding_pairs: HashSet<IndexPair>,
   collisions: Vec<Collision>,
   next_key: u32,
+  static_components: HashSet<ComponentKey>,
+  ongoing_interval: Option<f32>, // min seconds between ongoing events for a pair
+  last_ongoing: HashMap<IndexPair, f32>, // event clock time each pair last sent one
 }
 
 impl CollisionManager {
@@ -36,20 +39,109 @@ impl CollisionManager {
       index_comp_map: HashMap::new(),
       colliding_pairs: HashSet::new(),
       collisions: Vec::new(),
-      next_key: 0
+      next_key: 0,
+      static_components: HashSet::new(),
+      ongoing_interval: None,
+      last_ongoing: HashMap::new(),
     }
   }
 
+  // limits how often CollisionOngoingEvent fires for each colliding pair.
+  // start and end events are always sent right away. None sends ongoing
+  // events every frame
+  pub fn set_ongoing_event_interval(&mut self, interval: Option<instant::Duration>) {
+    self.ongoing_interval = interval.map(|i| i.as_secs_f32());
+  }
+
+  // static components are never moved by resolve_penetration
+  pub fn set_static(&mut self, comp: ComponentKey, is_static: bool) {
+    if is_static {
+      self.static_components.insert(comp);
+    } else {
+      self.static_components.remove(&comp);
+    }
+  }
+
+  // static world colliders (ComponentKey::world()) always are
+  pub fn is_static(&self, comp: &ComponentKey) -> bool {
+    *comp == ComponentKey::world() || self.static_components.contains(comp)
+  }
+
+  // colliders owned by comp. static world colliders aren't kept per
+  // component, so ComponentKey::world() collects them from the index map
+  fn colliders_of(&self, comp: &ComponentKey) -> Vec<Arc<RwLock<Collider>>> {
+    if *comp == ComponentKey::world() {
+      return self.index_comp_map.iter()
+        .filter(|(_, owner)| *owner == comp)
+        .filter_map(|(idx, _)| self.index_collider_map.get(idx).cloned())
+        .collect()
+    }
+    self.comp_collider_map.get(comp).cloned().unwrap_or(Vec::new())
+  }
+
+  // suggested world space position corrections (for c1, c2) that separate
+  // two overlapping components along the contact normal. the correction is
+  // split evenly between dynamic components, and a static component's
+  // share goes to the other one. trigger colliders are ignored. pass
+  // ComponentKey::world() to push a component out of static geometry.
+  // returns None if they aren't colliding
+  pub fn resolve_penetration(&self, c1: ComponentKey, c2: ComponentKey) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let colliders_1 = self.colliders_of(&c1);
+    let colliders_2 = self.colliders_of(&c2);
+
+    // deepest overlap between any pair of their colliders, as the
+    // direction c2 needs to move away from c1 and the distance
+    let mut deepest: Option<(Vector3<f32>, f32)> = None;
+    for col1 in colliders_1.iter() {
+      for col2 in colliders_2.iter() {
+        let col1 = col1.read().unwrap();
+        let col2 = col2.read().unwrap();
+        if !col1.is_enabled() || !col2.is_enabled() || col1.is_trigger() || col2.is_trigger() {
+          continue;
+        }
+        let (collision, sign) = match col1.collide(&col2) {
+          Some(collision) => (collision, 1.),
+          None => match col2.collide(&col1) {
+            Some(collision) => (collision, -1.),
+            None => continue
+          }
+        };
+        // the normal belongs to the first collider in the collision, so it
+        // points from that collider out towards the other one
+        let fallback = col2.center() - col1.center();
+        let normal = collision.normal
+          .map(|n| n * sign)
+          .filter(|n| n.magnitude2() > 0.)
+          .unwrap_or(if fallback.magnitude2() > 0. { fallback } else { Vector3::unit_y() })
+          .normalize();
+        if deepest.map(|d| collision.depth > d.1).unwrap_or(true) {
+          deepest = Some((normal, collision.depth));
+        }
+      }
+    }
+
+    let (normal, depth) = deepest?;
+    let (share_1, share_2) = match (self.is_static(&c1), self.is_static(&c2)) {
+      (false, false) => (0.5, 0.5),
+      (true, false) => (0., 1.),
+      (false, true) => (1., 0.),
+      (true, true) => (0., 0.),
+    };
+    Some((-normal * depth * share_1, normal * depth * share_2))
+  }
+
   pub fn add_component_collider(
     &mut self, 
     boundary: impl ColliderBoundary + 'static, 
     parent: ComponentKey,
-    transform: Option<ColliderTransform>
+    transform: Option<ColliderTransform>,
+    is_trigger: bool
   ) -> Arc<RwLock<Collider>> {
     let collider_idx = self.next_key;
     self.next_key += 1;
 
-    let collider = Collider::new(collider_idx, boundary, parent.clone(), transform);
+    let mut collider = Collider::new(collider_idx, boundary, parent.clone(), transform);
+    collider.set_trigger(is_trigger);
     let collider_rc = Arc::new(RwLock::new(collider));
     if !self.comp_collider_map.contains_key(&parent) {
       self.comp_collider_map.insert(parent.clone(), Vec::new());
@@ -60,6 +152,34 @@ impl CollisionManager {
     collider_rc
   }
 
+  // a collider placed directly in the world (floors, walls). it never moves
+  // with a component, but collides and is hit by raycasts like any other,
+  // reporting ComponentKey::world() as its component
+  pub fn add_static_collider(
+    &mut self,
+    boundary: impl ColliderBoundary + 'static,
+    pos: Vector3<f32>,
+    rot: Quaternion<f32>
+  ) -> Arc<RwLock<Collider>> {
+    let collider_idx = self.next_key;
+    self.next_key += 1;
+
+    let world = ComponentKey::world();
+    let transform = ColliderTransform::new(world, pos, rot);
+    let collider_rc = Arc::new(RwLock::new(Collider::new(collider_idx, boundary, world, Some(transform))));
+    self.index_collider_map.insert(collider_idx, collider_rc.clone());
+    self.index_comp_map.insert(collider_idx, world);
+    collider_rc
+  }
+
+  pub fn remove_static_collider(&mut self, index: u32) -> Option<Arc<RwLock<Collider>>> {
+    if self.index_comp_map.get(&index) != Some(&ComponentKey::world()) {
+      return None
+    }
+    self.index_comp_map.remove(&index);
+    self.index_collider_map.remove(&index)
+  }
+
   pub fn remove_component_colliders(&mut self, comp: ComponentKey) -> Option<Vec<Arc<RwLock<Collider>>>> {
     if let Some(colliders) = self.comp_collider_map.remove(&comp) {
       for col in colliders.iter() {
@@ -72,17 +192,43 @@ impl CollisionManager {
     None
   }
 
+  pub fn collider_count(&self) -> usize {
+    self.index_collider_map.len()
+  }
+
+  // pairs of colliders found overlapping by the last trigger_collision_events
+  pub fn colliding_pair_count(&self) -> usize {
+    self.colliding_pairs.len()
+  }
+
+  // collisions found by the last trigger_collision_events that involve one
+  // of comp's colliders. when both orderings of a pair collided, only the
+  // one whose first collider belongs to comp is kept
+  pub fn collisions_for(&self, comp: &ComponentKey) -> Vec<Collision> {
+    let owns = |idx: &u32| self.index_comp_map.get(idx) == Some(comp);
+    let mut result = self.collisions.iter()
+      .filter(|c| owns(&c.colliders.0))
+      .cloned()
+      .collect::<Vec<Collision>>();
+    for collision in self.collisions.iter().filter(|c| !owns(&c.colliders.0) && owns(&c.colliders.1)) {
+      let (a, b) = collision.colliders;
+      if !result.iter().any(|r| r.colliders == (b, a)) {
+        result.push(collision.clone());
+      }
+    }
+    result
+  }
+
+  pub fn get_colliders(&self) -> Vec<Arc<RwLock<Collider>>> {
+    self.index_collider_map.values().cloned().collect::<Vec<Arc<RwLock<Collider>>>>()
+  }
+
   pub fn update_collider_positions(&mut self, position_cache: &HashMap<ComponentKey, Matrix4<f32>>) {
     for (key, colliders) in self.comp_collider_map.iter_mut() {
       if position_cache.contains_key(key) {
         let mat = position_cache.get(key).unwrap();
         for collider in colliders {
-          let mut mutex_guard = collider.write().unwrap();
-          let curr_transform = mutex_guard.transform.clone();
-          let new_pos = to_vec(mat.transform_point(to_point(curr_transform.relative_pos)));
-          let new_rot = apply_quaternion_transform(mat, curr_transform.relative_rot);
-          mutex_guard.transform.cache_global_pos(new_pos);
-          mutex_guard.transform.cache_global_rot(new_rot);
+          update_collider_position(collider, mat);
         }
       }
     }
@@ -96,7 +242,12 @@ impl CollisionManager {
           continue;
         }
 
-        let pot_collision = collider_i.read().unwrap().collide(&collider_j.read().unwrap());
+        let collider_i = collider_i.read().unwrap();
+        let collider_j = collider_j.read().unwrap();
+        if !collider_i.is_enabled() || !collider_j.is_enabled() {
+          continue;
+        }
+        let pot_collision = collider_i.collide(&collider_j);
         let index_pair = IndexPair(key_i.clone(), key_j.clone());
         if let Some(collision) = pot_collision {
           if !collisions.contains_key(&index_pair) {
@@ -111,22 +262,33 @@ impl CollisionManager {
     // this event is registered for each pair of components involved in the collision -> this means we need to know which collider index corresponds with which component on registration
     // want to know which collisions are already ongoing, and which ongoing collisions are no longer happening
     let mut new_colliding_pairs: HashSet<IndexPair> = HashSet::new();
+    self.collisions.clear();
     for (index_pair, collision) in collisions {
       if let Some(c1) = self.index_comp_map.get(&index_pair.0) {
         if let Some(c2) = self.index_comp_map.get(&index_pair.1) {
           if c1 == c2 {
             continue;
           }
+          self.collisions.push(collision.clone());
 
-          let co_event_data = EventData::CollisionOngoingEvent { 
-            c1: c1.clone(), 
-            c2: c2.clone(), 
-            collision: collision.clone()
+          // timed on the event manager's clock, so throttling pauses with the scene
+          let now = event_manager.time();
+          let ongoing_due = match self.ongoing_interval {
+            Some(interval) => self.last_ongoing.get(&index_pair).map(|last| now - last >= interval).unwrap_or(true),
+            None => true
           };
-          event_manager.handle_event(Event {
-            key: EventKey::CollisionOngoingEvent,
-            data: co_event_data
-          });
+          if ongoing_due {
+            let co_event_data = EventData::CollisionOngoingEvent { 
+              c1: c1.clone(), 
+              c2: c2.clone(), 
+              collision: collision.clone()
+            };
+            event_manager.handle_event(Event {
+              key: EventKey::CollisionOngoingEvent,
+              data: co_event_data
+            });
+            self.last_ongoing.insert(index_pair.clone(), now);
+          }
           new_colliding_pairs.insert(index_pair.clone());
           if !self.colliding_pairs.contains(&index_pair) {
             let cs_event_data = EventData::CollisionStartEvent { 
@@ -152,7 +314,10 @@ impl CollisionManager {
         let c1 = self.index_comp_map.get(&index_pair.0).unwrap().clone();
         let c2 = self.index_comp_map.get(&index_pair.1).unwrap().clone();
         let collider_keys = (index_pair.0, index_pair.1);
-        let ce_event_data = EventData::CollisionEndEvent { c1, c2, collider_keys };
+        let is_trigger = [index_pair.0, index_pair.1].iter()
+          .filter_map(|idx| self.index_collider_map.get(idx))
+          .any(|col| col.read().unwrap().is_trigger());
+        let ce_event_data = EventData::CollisionEndEvent { c1, c2, collider_keys, is_trigger };
         event_manager.handle_event(Event {
           key: EventKey::CollisionEndEvent,
           data: ce_event_data
@@ -160,11 +325,22 @@ impl CollisionManager {
       }
     }
 
+    self.last_ongoing.retain(|pair, _| new_colliding_pairs.contains(pair));
     self.colliding_pairs = new_colliding_pairs;
   }
 }
 
 
+// caches the collider's world transform given its component's world matrix
+pub fn update_collider_position(collider: &Arc<RwLock<Collider>>, component_world: &Matrix4<f32>) {
+  let mut mutex_guard = collider.write().unwrap();
+  let curr_transform = mutex_guard.transform.clone();
+  let new_pos = to_vec(component_world.transform_point(to_point(curr_transform.relative_pos)));
+  let new_rot = apply_quaternion_transform(component_world, curr_transform.relative_rot);
+  mutex_guard.transform.cache_global_pos(new_pos);
+  mutex_guard.transform.cache_global_rot(new_rot);
+}
+
 pub fn try_collide(col1: &Arc<RwLock<Collider>>, col2: &Arc<RwLock<Collider>>) -> Option<Collision> {
   let col1_unwrapped = col1.read().unwrap();
   let col2_unwrapped = col2.read().unwrap();
@@ -173,4 +349,73 @@ pub fn try_collide(col1: &Arc<RwLock<Collider>>, col2: &Arc<RwLock<Collider>>) -
     return Some(collision)
   }
   None
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::Point3;
+  use crate::engine::test_util::sphere_boundary;
+  use crate::graphics::identity_rotation;
+  use crate::sdf::{CubeSdf, SdfShape, Shape};
+  use crate::engine::collisions::SdfBoundary;
+
+  fn key(index: u32) -> ComponentKey {
+    ComponentKey { index, generation: 0 }
+  }
+
+  fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a - b).magnitude() < 1e-2
+  }
+
+  #[test]
+  fn overlapping_spheres_are_pushed_apart_along_the_normal() {
+    let mut manager = CollisionManager::new();
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0., 0.), 1.), key(1), None, false);
+    manager.add_component_collider(sphere_boundary(Point3::new(1.5, 0., 0.), 1.), key(2), None, false);
+
+    let (move_1, move_2) = manager.resolve_penetration(key(1), key(2)).unwrap();
+    // 0.5 of overlap along x, split evenly
+    assert!(close(move_1, Vector3::new(-0.25, 0., 0.)));
+    assert!(close(move_2, Vector3::new(0.25, 0., 0.)));
+
+    // applying the corrections leaves them just touching
+    let mut separated = CollisionManager::new();
+    separated.add_component_collider(sphere_boundary(Point3::new(0., 0., 0.) + move_1, 0.99), key(1), None, false);
+    separated.add_component_collider(sphere_boundary(Point3::new(1.5, 0., 0.) + move_2, 0.99), key(2), None, false);
+    assert!(separated.resolve_penetration(key(1), key(2)).is_none());
+  }
+
+  #[test]
+  fn static_colliders_take_none_of_the_correction() {
+    let mut manager = CollisionManager::new();
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0., 0.), 1.), key(1), None, false);
+    manager.add_component_collider(sphere_boundary(Point3::new(1.5, 0., 0.), 1.), key(2), None, false);
+    manager.set_static(key(2), true);
+    let (move_1, move_2) = manager.resolve_penetration(key(1), key(2)).unwrap();
+    assert!(close(move_1, Vector3::new(-0.5, 0., 0.)));
+    assert!(close(move_2, Vector3::new(0., 0., 0.)));
+  }
+
+  #[test]
+  fn components_are_pushed_out_of_static_world_colliders() {
+    let mut manager = CollisionManager::new();
+    // floor whose top face is y = 0, and a ball sunk half a unit into it
+    let floor = SdfShape::new(Shape::Cube { center: Point3::new(0., -1., 0.), half_bounds: Vector3::new(10., 1., 10.) }, CubeSdf).unwrap();
+    manager.add_static_collider(SdfBoundary::from_shape(floor), Vector3::new(0., 0., 0.), identity_rotation());
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0.5, 0.), 1.), key(1), None, false);
+
+    let (move_ball, move_floor) = manager.resolve_penetration(key(1), ComponentKey::world()).unwrap();
+    assert!(close(move_ball, Vector3::new(0., 0.5, 0.)));
+    assert!(close(move_floor, Vector3::new(0., 0., 0.)));
+  }
+
+  #[test]
+  fn separate_colliders_need_no_correction() {
+    let mut manager = CollisionManager::new();
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0., 0.), 1.), key(1), None, false);
+    manager.add_component_collider(sphere_boundary(Point3::new(3., 0., 0.), 1.), key(2), None, false);
+    assert!(manager.resolve_penetration(key(1), key(2)).is_none());
+    assert!(manager.resolve_penetration(key(1), ComponentKey::world()).is_none());
+  }
+}
@@ -0,0 +1,372 @@
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Point3, Vector3};
+
+use crate::graphics::load_mesh_positions;
+
+use super::collider::ColliderBoundary;
+
+const BVH_LEAF_SIZE: usize = 4;
+const RAY_EPSILON: f32 = 1e-6;
+const DEGENERATE_EPSILON: f32 = 1e-12; // squared area (x4) below which a triangle is dropped
+
+#[derive(Clone, Copy, Debug)]
+struct Aabb {
+  min: Point3<f32>,
+  max: Point3<f32>,
+}
+
+impl Aabb {
+  fn empty() -> Aabb {
+    Self {
+      min: Point3::new(f32::MAX, f32::MAX, f32::MAX),
+      max: Point3::new(f32::MIN, f32::MIN, f32::MIN),
+    }
+  }
+
+  fn grow(&mut self, p: Point3<f32>) {
+    self.min = Point3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+    self.max = Point3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+  }
+
+  fn center(&self) -> Point3<f32> {
+    self.min.midpoint(self.max)
+  }
+
+  // distance from a point to the box (0 inside)
+  fn dist(&self, p: Point3<f32>) -> f32 {
+    let dx = (self.min.x - p.x).max(0.).max(p.x - self.max.x);
+    let dy = (self.min.y - p.y).max(0.).max(p.y - self.max.y);
+    let dz = (self.min.z - p.z).max(0.).max(p.z - self.max.z);
+    Vector3::new(dx, dy, dz).magnitude()
+  }
+
+  // slab test, returns the entry distance along the ray
+  fn ray_entry(&self, origin: Point3<f32>, inv_dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+    let mut t_min: f32 = 0.;
+    let mut t_max = max_dist;
+    for axis in 0..3 {
+      let t1 = (self.min[axis] - origin[axis]) * inv_dir[axis];
+      let t2 = (self.max[axis] - origin[axis]) * inv_dir[axis];
+      t_min = t_min.max(t1.min(t2));
+      t_max = t_max.min(t1.max(t2));
+    }
+    if t_min <= t_max {
+      return Some(t_min)
+    }
+    None
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Triangle {
+  a: Point3<f32>,
+  b: Point3<f32>,
+  c: Point3<f32>,
+}
+
+impl Triangle {
+  // zero area triangles have no normal and are dropped at build time
+  fn is_degenerate(&self) -> bool {
+    (self.b - self.a).cross(self.c - self.a).magnitude2() <= DEGENERATE_EPSILON
+  }
+
+  fn centroid(&self) -> Point3<f32> {
+    Point3::from_vec((self.a.to_vec() + self.b.to_vec() + self.c.to_vec()) / 3.)
+  }
+
+  fn normal(&self) -> Vector3<f32> {
+    (self.b - self.a).cross(self.c - self.a).normalize()
+  }
+
+  // moller-trumbore, returns the distance along the ray
+  fn ray_intersect(&self, origin: Point3<f32>, dir: Vector3<f32>) -> Option<f32> {
+    let edge1 = self.b - self.a;
+    let edge2 = self.c - self.a;
+    let h = dir.cross(edge2);
+    let det = edge1.dot(h);
+    if det.abs() < RAY_EPSILON {
+      return None
+    }
+    let inv_det = 1. / det;
+    let s = origin - self.a;
+    let u = inv_det * s.dot(h);
+    if u < 0. || u > 1. {
+      return None
+    }
+    let q = s.cross(edge1);
+    let v = inv_det * dir.dot(q);
+    if v < 0. || u + v > 1. {
+      return None
+    }
+    let t = inv_det * edge2.dot(q);
+    if t > RAY_EPSILON {
+      return Some(t)
+    }
+    None
+  }
+
+  // closest point on the triangle (ericson, real-time collision detection 5.1.5)
+  fn closest_pt(&self, p: Point3<f32>) -> Point3<f32> {
+    let (a, b, c) = (self.a, self.b, self.c);
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0. && d2 <= 0. {
+      return a
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0. && d4 <= d3 {
+      return b
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+      return a + ab * (d1 / (d1 - d3))
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0. && d5 <= d6 {
+      return c
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+      return a + ac * (d2 / (d2 - d6))
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && (d4 - d3) >= 0. && (d5 - d6) >= 0. {
+      return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)))
+    }
+    let denom = 1. / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BvhNode {
+  Leaf {
+    bounds: Aabb,
+    start: usize,
+    count: usize,
+  },
+  Branch {
+    bounds: Aabb,
+    left: usize,
+    right: usize,
+  },
+}
+
+impl BvhNode {
+  fn bounds(&self) -> &Aabb {
+    match self {
+      BvhNode::Leaf { bounds, .. } => bounds,
+      BvhNode::Branch { bounds, .. } => bounds,
+    }
+  }
+}
+
+// Collider boundary over arbitrary triangle geometry (e.g. a loaded obj).
+// Triangles are stored in a bvh so ray and nearest point queries don't
+// have to visit every triangle. Interior tests assume a closed mesh.
+// Collider transforms are rigid, so any scale has to be baked into the
+// vertices with with_scale for distances and normals to come out right
+pub struct MeshBoundary {
+  triangles: Vec<Triangle>,
+  nodes: Vec<BvhNode>,
+}
+
+impl MeshBoundary {
+  pub fn new(positions: &Vec<[f32; 3]>, indices: &Vec<u32>) -> MeshBoundary {
+    Self::with_scale(positions, indices, Vector3::new(1., 1., 1.))
+  }
+
+  // positions are scaled per axis before the bvh is built, so a mesh on a
+  // non uniformly scaled component still gets true distances and normals
+  pub fn with_scale(positions: &Vec<[f32; 3]>, indices: &Vec<u32>, scale: Vector3<f32>) -> MeshBoundary {
+    let vertex = |idx: u32| {
+      let [x, y, z] = positions[idx as usize];
+      Point3::new(x * scale.x, y * scale.y, z * scale.z)
+    };
+    let mut triangles = indices.chunks(3)
+      .filter(|c| c.len() == 3)
+      .map(|c| Triangle {
+        a: vertex(c[0]),
+        b: vertex(c[1]),
+        c: vertex(c[2]),
+      })
+      .filter(|tri| !tri.is_degenerate())
+      .collect::<Vec<Triangle>>();
+    let mut nodes = Vec::new();
+    if triangles.len() > 0 {
+      let len = triangles.len();
+      build_node(&mut triangles, &mut nodes, 0, len);
+    }
+    Self {
+      triangles,
+      nodes
+    }
+  }
+
+  // builds a boundary from the same obj file used to load the model
+  pub async fn from_obj(file_name: &str) -> anyhow::Result<MeshBoundary> {
+    let (positions, indices) = load_mesh_positions(file_name).await?;
+    Ok(Self::new(&positions, &indices))
+  }
+
+  // nearest triangle to a point, along with the closest point on it
+  fn closest_triangle(&self, pt: Point3<f32>) -> Option<(usize, Point3<f32>)> {
+    if self.nodes.len() == 0 {
+      return None
+    }
+    let mut best: Option<(usize, Point3<f32>, f32)> = None;
+    let mut stack = vec![0];
+    while let Some(idx) = stack.pop() {
+      let node = self.nodes[idx];
+      if let Some((_, _, best_dist)) = best {
+        if node.bounds().dist(pt) > best_dist {
+          continue;
+        }
+      }
+      match node {
+        BvhNode::Leaf { start, count, .. } => {
+          for tri_idx in start..start + count {
+            let closest = self.triangles[tri_idx].closest_pt(pt);
+            let dist = closest.distance(pt);
+            if best.map(|b| dist < b.2).unwrap_or(true) {
+              best = Some((tri_idx, closest, dist));
+            }
+          }
+        },
+        BvhNode::Branch { left, right, .. } => {
+          stack.push(left);
+          stack.push(right);
+        }
+      }
+    }
+    best.map(|(idx, closest, _)| (idx, closest))
+  }
+
+  fn ray_hit(&self, origin: Point3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<(usize, f32)> {
+    if self.nodes.len() == 0 {
+      return None
+    }
+    let inv_dir = Vector3::new(1. / dir.x, 1. / dir.y, 1. / dir.z);
+    let mut best: Option<(usize, f32)> = None;
+    let mut stack = vec![0];
+    while let Some(idx) = stack.pop() {
+      let node = self.nodes[idx];
+      let limit = best.map(|b| b.1).unwrap_or(max_dist);
+      if node.bounds().ray_entry(origin, inv_dir, limit).is_none() {
+        continue;
+      }
+      match node {
+        BvhNode::Leaf { start, count, .. } => {
+          for tri_idx in start..start + count {
+            if let Some(t) = self.triangles[tri_idx].ray_intersect(origin, dir) {
+              if t <= max_dist && best.map(|b| t < b.1).unwrap_or(true) {
+                best = Some((tri_idx, t));
+              }
+            }
+          }
+        },
+        BvhNode::Branch { left, right, .. } => {
+          stack.push(left);
+          stack.push(right);
+        }
+      }
+    }
+    best
+  }
+}
+
+impl ColliderBoundary for MeshBoundary {
+  fn closest_boundary_pt(&self, pt: Point3<f32>) -> Point3<f32> {
+    self.closest_triangle(pt).map(|(_, closest)| closest).unwrap_or(pt)
+  }
+
+  fn is_interior_point(&self, pt: Point3<f32>) -> bool {
+    // parity of crossings along an arbitrary (slightly skewed) direction
+    let dir = Vector3::new(1., 0.0013, 0.0007).normalize();
+    let mut crossings = 0;
+    for tri in self.triangles.iter() {
+      if tri.ray_intersect(pt, dir).is_some() {
+        crossings += 1;
+      }
+    }
+    crossings % 2 == 1
+  }
+
+  fn get_boundary_normal(&self, pt: Point3<f32>, tol: f32) -> Option<Vector3<f32>> {
+    if let Some((idx, closest)) = self.closest_triangle(pt) {
+      if closest.distance(pt) <= tol {
+        return Some(self.triangles[idx].normal())
+      }
+    }
+    None
+  }
+
+  fn center(&self) -> Point3<f32> {
+    self.nodes.get(0).map(|n| n.bounds().center()).unwrap_or(Point3::new(0., 0., 0.))
+  }
+
+  fn ray_intersect(&self, origin: Point3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+    self.ray_hit(origin, dir, max_dist).map(|(_, t)| t)
+  }
+
+  fn aabb(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    self.nodes.get(0).map(|n| (n.bounds().min, n.bounds().max))
+  }
+
+  // a closed mesh whose every vertex is a corner of its bounds is the box
+  fn box_bounds(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    let (min, max) = self.aabb()?;
+    let is_corner = |p: &Point3<f32>| {
+      (p.x == min.x || p.x == max.x) && (p.y == min.y || p.y == max.y) && (p.z == min.z || p.z == max.z)
+    };
+    if self.triangles.len() < 12 || !self.triangles.iter().all(|t| is_corner(&t.a) && is_corner(&t.b) && is_corner(&t.c)) {
+      return None
+    }
+    Some((min, max))
+  }
+}
+
+// recursively splits triangles[start..end] at the centroid median of the
+// longest axis. returns the index of the created node
+fn build_node(triangles: &mut Vec<Triangle>, nodes: &mut Vec<BvhNode>, start: usize, end: usize) -> usize {
+  let mut bounds = Aabb::empty();
+  let mut centroid_bounds = Aabb::empty();
+  for tri in triangles[start..end].iter() {
+    bounds.grow(tri.a);
+    bounds.grow(tri.b);
+    bounds.grow(tri.c);
+    centroid_bounds.grow(tri.centroid());
+  }
+
+  let node_idx = nodes.len();
+  if end - start <= BVH_LEAF_SIZE {
+    nodes.push(BvhNode::Leaf { bounds, start, count: end - start });
+    return node_idx
+  }
+
+  let extent = centroid_bounds.max - centroid_bounds.min;
+  let axis = if extent.x >= extent.y && extent.x >= extent.z {
+    0
+  } else if extent.y >= extent.z {
+    1
+  } else {
+    2
+  };
+  triangles[start..end].sort_by(|a, b| {
+    a.centroid()[axis].partial_cmp(&b.centroid()[axis]).unwrap_or(std::cmp::Ordering::Equal)
+  });
+  let mid = start + (end - start) / 2;
+
+  // reserve this node's slot before building the children
+  nodes.push(BvhNode::Leaf { bounds, start, count: 0 });
+  let left = build_node(triangles, nodes, start, mid);
+  let right = build_node(triangles, nodes, mid, end);
+  nodes[node_idx] = BvhNode::Branch { bounds, left, right };
+  node_idx
+}
@@ -1,6 +1,6 @@
 use cgmath::{num_traits::abs, Point3, Vector3};
 
-use crate::{engine::transforms::ComponentTransform, sdf::SdfShape};
+use crate::{engine::{raycasting::Ray, transforms::ComponentTransform}, sdf::SdfShape};
 
 use super::collider::ColliderBoundary;
 
@@ -25,6 +25,11 @@ impl ColliderBoundary for SdfBoundary {
     self.center.clone()
   }
 
+  fn ray_intersect(&self, origin: Point3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+    let res = Ray::new(origin, dir).sphere_trace(&self.sdf, max_dist);
+    res.hit.map(|_| res.distance)
+  }
+
   fn get_boundary_normal(&self, pt: Point3<f32>, tol: f32) -> Option<Vector3<f32>> {
     let dist = self.sdf.dist(pt);
     if abs(dist) <= tol {
@@ -32,13 +37,55 @@ impl ColliderBoundary for SdfBoundary {
     }
     None
   }
+
+  fn aabb(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    self.sdf.bounds()
+  }
+
+  fn surface_samples(&self, resolution: usize) -> Vec<Point3<f32>> {
+    let (min, max) = match self.sdf.bounds() {
+      Some(bounds) => bounds,
+      None => return Vec::new()
+    };
+    let extent = max - min;
+    let spacing = extent.x.max(extent.y).max(extent.z) / resolution.max(1) as f32;
+    if spacing <= 0. {
+      return Vec::new()
+    }
+    // grid points close to the surface, pulled onto it
+    let steps = |len: f32| (len / spacing).ceil() as usize + 1;
+    let mut samples: Vec<Point3<f32>> = Vec::new();
+    for x in 0..steps(extent.x) {
+      for y in 0..steps(extent.y) {
+        for z in 0..steps(extent.z) {
+          let p = min + Vector3::new(x as f32, y as f32, z as f32) * spacing;
+          if self.sdf.dist(p).abs() < spacing / 2. {
+            let mut on_surface = p;
+            self.sdf.gradient_trace(p, &mut on_surface, None, None);
+            samples.push(on_surface);
+          }
+        }
+      }
+    }
+    samples
+  }
 }
 
 impl SdfBoundary {
+  // center is in the shape's local space. prefer from_shape unless the
+  // shape has no natural center (e.g. custom sdfs)
   pub fn new(center: Point3<f32>, sdf: SdfShape) -> SdfBoundary {
     Self {
       center,
       sdf
     }
   }
+
+  // takes the center from the shape itself so the two can't disagree
+  pub fn from_shape(sdf: SdfShape) -> SdfBoundary {
+    Self {
+      center: sdf.center().unwrap_or(Point3::new(0., 0., 0.)),
+      sdf
+    }
+  }
 }
\ No newline at end of file
@@ -23,6 +23,42 @@ pub trait ComponentFunctions: Any + Send + Sync + EventListener + StateListener
     return;
   }
 
+  // fixed_update is called zero or more times per frame at the scene's
+  // fixed timestep (if one is set)
+  fn fixed_update(&mut self, scene: &mut Scene, step: instant::Duration) {
+    return;
+  }
+
+  // receives messages sent with Scene::send_message. messages are
+  // delivered at the start of the next update, before update is called
+  fn receive_message(&mut self, scene: &mut Scene, message: Box<dyn Any + Send>) {
+    return;
+  }
+
+  // called when the component is re-activated with Scene::set_active
+  fn on_enable(&mut self, scene: &mut Scene) {
+    return;
+  }
+
+  // called when the component is deactivated with Scene::set_active.
+  // inactive components are skipped by update, fixed_update and render
+  fn on_disable(&mut self, scene: &mut Scene) {
+    return;
+  }
+
+  // called after Scene::destroy_component, at the end of that frame's
+  // update, right before the component is removed from the scene
+  fn on_destroy(&mut self, scene: &mut Scene) {
+    return;
+  }
+
+  // called when the window size changes. rapid resizes are collapsed so
+  // this runs at most once per frame, at the start of update, with the
+  // latest size
+  fn on_resize(&mut self, scene: &mut Scene, new_size: winit::dpi::PhysicalSize<u32>) {
+    return;
+  }
+
   // get models to be rendered when this component is rendered
   fn render(&self, scene: &mut Scene) -> Result<(), EngineError> {
     Ok(())
@@ -50,19 +86,37 @@ impl Component {
     scene: &mut Scene,
     parent: Option<ComponentKey>
   ) -> Option<Component> {
-    let mut component = Self {
-      key: ComponentKey::zero(),
-      underlying: underlying as Arc<Mutex<dyn ComponentFunctions + 'static>>
-    };
-    let key_res = scene.components.insert(component.clone());
-    if let Ok(key) = key_res {
-      component.key = key;
-      component.clone().init(scene, key.clone(), parent).await;
+    if let Ok(component) = Self::register(underlying, scene, parent) {
+      component.clone().init(scene, component.key, parent).await;
       return Some(component);
     }
     None
   }
 
+  // adds the wrapper to the scene's component store without initializing it
+  pub fn register<T: ComponentFunctions>(
+    underlying: Arc<Mutex<T>>,
+    scene: &mut Scene,
+    parent: Option<ComponentKey>
+  ) -> Result<Component, EngineError> {
+    let mut component = Self::wrap(underlying, ComponentKey::zero());
+    let key = scene.components.insert(component.clone())?;
+    component.key = key;
+    // insert_with_key keeps the stored copy's key in sync with the returned one
+    scene.components.insert_with_key(component.clone(), key);
+    let _ = scene.set_parent(key, parent);
+    Ok(component)
+  }
+
+  // wraps the struct without touching a scene. register is the usual way
+  // in, this is for building a ComponentStore by hand
+  pub fn wrap<T: ComponentFunctions>(underlying: Arc<Mutex<T>>, key: ComponentKey) -> Component {
+    Self {
+      key,
+      underlying: underlying as Arc<Mutex<dyn ComponentFunctions + 'static>>
+    }
+  }
+
   // initialize the underlying component
   pub async fn init(
     &mut self,
@@ -78,10 +132,48 @@ impl Component {
     self.underlying.lock().unwrap().update(scene, dt);
   }
 
-  // render the component
+  // run a fixed timestep update on the underlying component
+  pub fn fixed_update(&self, scene: &mut Scene, step: instant::Duration) {
+    self.underlying.lock().unwrap().fixed_update(scene, step);
+  }
+
+  pub fn receive_message(&self, scene: &mut Scene, message: Box<dyn Any + Send>) {
+    self.underlying.lock().unwrap().receive_message(scene, message);
+  }
+
+  pub fn on_enable(&self, scene: &mut Scene) {
+    self.underlying.lock().unwrap().on_enable(scene);
+  }
+
+  pub fn on_disable(&self, scene: &mut Scene) {
+    self.underlying.lock().unwrap().on_disable(scene);
+  }
+
+  pub fn on_destroy(&self, scene: &mut Scene) {
+    self.underlying.lock().unwrap().on_destroy(scene);
+  }
+
+  pub fn on_resize(&self, scene: &mut Scene, new_size: winit::dpi::PhysicalSize<u32>) {
+    self.underlying.lock().unwrap().on_resize(scene, new_size);
+  }
+
+  // render the component followed by its children. each child is rendered
+  // with its local transform pushed on top of this component's transform
   pub fn render(&self, scene: &mut Scene, transform: Option<ComponentTransform>) -> Result<(), EngineError> {
     scene.model_renderer.start_component_render(transform, self.key);
-    let res = self.underlying.lock().unwrap().render(scene);
+    let mut res = self.underlying.lock().unwrap().render(scene);
+    for child_key in scene.get_children(&self.key) {
+      if !scene.is_active(&child_key) {
+        continue;
+      }
+      if let Some(child) = scene.components.get(&child_key).cloned() {
+        let child_transform = scene.get_local_transform(&child_key);
+        let child_res = child.render(scene, Some(child_transform));
+        if res.is_ok() {
+          res = child_res;
+        }
+      }
+    }
     scene.model_renderer.end_component_render();
     res
   }
@@ -3,15 +3,29 @@ use std::{any, borrow::Borrow, collections::{hash_map::{Iter, IterMut}, HashMap}
 use super::{async_closure::run_component_closure, component::{self, Component}, errors::EngineError};
 
 
+// Indices are reused once a component is removed, so each key also
+// carries the generation of its slot. Keys held past a removal keep the
+// old generation and stop resolving instead of aliasing the new component
 #[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
 pub struct ComponentKey {
   pub index: u32,
+  pub generation: u32,
 }
 
 impl ComponentKey {
   pub fn zero() -> ComponentKey {
     Self {
-      index: 0
+      index: 0,
+      generation: 0
+    }
+  }
+
+  // stands in for the owner of colliders that don't belong to a component
+  // (static world geometry). the store never hands this key out
+  pub fn world() -> ComponentKey {
+    Self {
+      index: u32::MAX,
+      generation: u32::MAX
     }
   }
 }
@@ -19,27 +33,46 @@ impl ComponentKey {
 pub struct ComponentStore {
   next_idx: u32,
   components: HashMap<ComponentKey, Component>,
+  generations: HashMap<u32, u32>, // index -> current generation
+  free_indices: Vec<u32>,
 }
 
 impl ComponentStore {
   pub fn new() -> ComponentStore {
     Self {
       next_idx: 1,
-      components: HashMap::new()
+      components: HashMap::new(),
+      generations: HashMap::new(),
+      free_indices: Vec::new(),
     }
   }
 
   pub fn insert(&mut self, component: Component) -> Result<ComponentKey, EngineError> {
-    if self.next_idx >= u32::MAX {
-      return Err(EngineError::MaxComponentsError { insertion_loc: "ComponentStore::insert".into() })
-    }
-
-    let key = ComponentKey { index: self.next_idx };
-    self.next_idx += 1;
+    let index = match self.free_indices.pop() {
+      Some(index) => index,
+      None => {
+        if self.next_idx >= u32::MAX {
+          return Err(EngineError::MaxComponentsError { insertion_loc: "ComponentStore::insert".into() })
+        }
+        self.next_idx += 1;
+        self.next_idx - 1
+      }
+    };
+
+    let key = ComponentKey { index, generation: self.generation(index) };
     self.components.insert(key.clone(), component);
     Ok(key)
   }
 
+  fn generation(&self, index: u32) -> u32 {
+    self.generations.get(&index).cloned().unwrap_or(0)
+  }
+
+  // true if the key refers to the component currently in its slot
+  pub fn is_current(&self, key: &ComponentKey) -> bool {
+    self.generation(key.index) == key.generation && self.components.contains_key(key)
+  }
+
   pub fn insert_with_key(&mut self, component: Component, key_override: ComponentKey) -> Option<Component> {
     self.components.insert(key_override, component)
   }
@@ -70,15 +103,31 @@ impl ComponentStore {
   }
 
   pub fn get(&self, key: &ComponentKey) -> Option<&Component> {
+    if self.generation(key.index) != key.generation {
+      return None
+    }
     self.components.get(key)
   }
 
   pub fn get_mut(&mut self, key: &ComponentKey) -> Option<&mut Component> {
+    if self.generation(key.index) != key.generation {
+      return None
+    }
     self.components.get_mut(key)
   }
 
+  // frees the slot for reuse and bumps its generation so the removed key
+  // (and any copies of it) go stale
   pub fn remove(&mut self, key: &ComponentKey) -> Option<Component> {
-    self.components.remove(key)
+    if self.generation(key.index) != key.generation {
+      return None
+    }
+    let removed = self.components.remove(key);
+    if removed.is_some() {
+      self.generations.insert(key.index, key.generation.wrapping_add(1));
+      self.free_indices.push(key.index);
+    }
+    removed
   }
 
   pub fn keys(&self) -> Vec<&ComponentKey> {
@@ -92,4 +141,29 @@ impl ComponentStore {
   pub fn iter_mut(&mut self) -> IterMut<ComponentKey, Component> {
     self.components.iter_mut()
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::test_util::recorder_store;
+
+  #[test]
+  fn stale_keys_stop_resolving() {
+    let (mut store, recorders) = recorder_store(1);
+    let stale = recorders[0].0;
+    assert!(store.remove(&stale).is_some());
+    assert!(store.get(&stale).is_none());
+    assert!(!store.is_current(&stale));
+
+    // the slot is reused under a new generation, which the old key can't reach
+    let (_, fresh) = recorder_store(1);
+    let key = store.insert(Component::wrap(fresh[0].1.clone(), ComponentKey::zero())).unwrap();
+    assert_eq!(key.index, stale.index);
+    assert_ne!(key.generation, stale.generation);
+    assert!(store.get(&key).is_some());
+    assert!(store.get_mut(&stale).is_none());
+    assert!(store.remove(&stale).is_none());
+    assert!(store.is_current(&key));
+  }
+}
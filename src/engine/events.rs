@@ -1,6 +1,7 @@
 mod event;
 mod event_manager;
 mod component_event;
+mod scheduled_event;
 
 pub use event::{
   Event,
@@ -9,4 +10,5 @@ pub use event::{
   EventListener
 };
 
-pub use event_manager::EventManager;
\ No newline at end of file
+pub use event_manager::EventManager;
+pub use scheduled_event::ScheduledEventId;
\ No newline at end of file
@@ -1,5 +1,5 @@
 use cgmath::{Point3, Vector3};
-use winit::event::{KeyboardInput, WindowEvent};
+use winit::event::{KeyboardInput, ModifiersState, MouseButton, WindowEvent};
 
 use crate::engine::{collisions::Collision, component_store::ComponentKey, errors::EngineError, Scene};
 
@@ -15,20 +15,48 @@ pub struct Event {
 pub enum EventKey {
   KeyboardEvent,
   MouseRaycastEvent,
+  MouseSelectEvent,
+  MouseDoubleClickEvent,
+  MouseDragStartEvent,
+  MouseDragEndEvent,
   ComponentEvent,
   CollisionOngoingEvent,
   CollisionStartEvent,
   CollisionEndEvent,
+  #[cfg(feature = "gamepad")]
+  GamepadButton,
+  #[cfg(feature = "gamepad")]
+  GamepadAxis,
   CustomEvent,
 }
 
 #[derive(Clone)]
 pub enum EventData {
-  KeyboardEvent (KeyboardInput),
+  KeyboardEvent {
+    input: KeyboardInput,
+    modifiers: ModifiersState // shift/ctrl/alt/logo held when the key changed
+  },
   MouseRaycastEvent {
     origin: Point3<f32>,
     dir: Vector3<f32>
   },
+  MouseSelectEvent {
+    button: MouseButton,
+    pos: (f64, f64)
+  },
+  MouseDoubleClickEvent {
+    button: MouseButton,
+    pos: (f64, f64)
+  },
+  MouseDragStartEvent {
+    button: MouseButton,
+    start: (f64, f64)
+  },
+  MouseDragEndEvent {
+    button: MouseButton,
+    start: (f64, f64),
+    end: (f64, f64)
+  },
   ComponentEvent (ComponentEvent),
   CollisionOngoingEvent {
     c1: ComponentKey,
@@ -43,20 +71,74 @@ pub enum EventData {
   CollisionEndEvent {
     c1: ComponentKey,
     c2: ComponentKey,
-    collider_keys: (u32, u32)
+    collider_keys: (u32, u32),
+    is_trigger: bool
+  },
+  #[cfg(feature = "gamepad")]
+  GamepadButton {
+    gamepad: usize, // gilrs gamepad id
+    button: gilrs::Button,
+    pressed: bool
+  },
+  #[cfg(feature = "gamepad")]
+  GamepadAxis {
+    gamepad: usize,
+    axis: gilrs::Axis,
+    value: f32 // -1 to 1, 0 inside the deadzone
   },
   CustomEvent (String)
 }
 
+impl EventData {
+  // the key events carrying this data are sent under
+  pub fn key(&self) -> EventKey {
+    match self {
+      EventData::KeyboardEvent { .. } => EventKey::KeyboardEvent,
+      EventData::MouseRaycastEvent { .. } => EventKey::MouseRaycastEvent,
+      EventData::MouseSelectEvent { .. } => EventKey::MouseSelectEvent,
+      EventData::MouseDoubleClickEvent { .. } => EventKey::MouseDoubleClickEvent,
+      EventData::MouseDragStartEvent { .. } => EventKey::MouseDragStartEvent,
+      EventData::MouseDragEndEvent { .. } => EventKey::MouseDragEndEvent,
+      EventData::ComponentEvent(_) => EventKey::ComponentEvent,
+      EventData::CollisionOngoingEvent { .. } => EventKey::CollisionOngoingEvent,
+      EventData::CollisionStartEvent { .. } => EventKey::CollisionStartEvent,
+      EventData::CollisionEndEvent { .. } => EventKey::CollisionEndEvent,
+      #[cfg(feature = "gamepad")]
+      EventData::GamepadButton { .. } => EventKey::GamepadButton,
+      #[cfg(feature = "gamepad")]
+      EventData::GamepadAxis { .. } => EventKey::GamepadAxis,
+      EventData::CustomEvent(_) => EventKey::CustomEvent,
+    }
+  }
+}
+
 impl Event {
-  pub fn from(event: &WindowEvent) -> Option<Self> {
+  pub fn new(data: EventData) -> Event {
+    Event {
+      key: data.key(),
+      data
+    }
+  }
+
+  // the key has to match the data, and custom events need a name
+  pub fn is_valid(&self) -> bool {
+    match &self.data {
+      EventData::CustomEvent(name) if name.is_empty() => false,
+      data => data.key() == self.key
+    }
+  }
+
+  pub fn from(event: &WindowEvent, modifiers: ModifiersState) -> Option<Self> {
     match event {
       WindowEvent::KeyboardInput {
         input,
         ..
       } => Some(Event {
         key: EventKey::KeyboardEvent,
-        data: EventData::KeyboardEvent(input.clone())
+        data: EventData::KeyboardEvent {
+          input: input.clone(),
+          modifiers
+        }
       }),
       _ => None
     }
@@ -74,4 +156,12 @@ pub trait EventListener {
     };
     scene.event_manager.add_listener(component_key.clone(), event_key.clone(), listener)
   }
+
+  // like add_event_listener, but runs before listeners with a higher priority
+  fn add_event_listener_with_priority(&mut self, scene: &mut Scene, component_key: &ComponentKey, event_key: &EventKey, priority: i32) -> Result<(), EngineError> {
+    let listener: fn(&mut dyn EventListener, Event) = |component: &mut dyn EventListener, event: Event| {
+      component.handle_event(event);
+    };
+    scene.event_manager.add_listener_with_priority(component_key.clone(), event_key.clone(), listener, priority)
+  }
 }
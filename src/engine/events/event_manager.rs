@@ -1,57 +1,152 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use crate::engine::{component::{self, Component, ComponentFunctions}, component_store::{ComponentKey, ComponentStore}, errors::EngineError, Scene};
 
-use super::event::{Event, EventKey, EventListener};
+use super::{event::{Event, EventKey, EventListener}, scheduled_event::{ScheduledEvent, ScheduledEventId}};
+
+// listeners for the same event run in ascending priority, ties broken by
+// registration order
+#[derive(Clone, Copy)]
+struct Listener {
+  callback: fn(&mut dyn EventListener, Event) -> (),
+  priority: i32,
+  order: u64,
+}
 
 pub struct EventManager {
-  new_events: HashMap<EventKey, Vec<Event>>,
-  event_listeners: HashMap<ComponentKey, HashMap<EventKey, fn(&mut dyn EventListener, Event) -> ()>>,
-  triggered_events: HashMap<ComponentKey, Vec<(EventKey, fn(&mut dyn EventListener, Event) -> ())>>
+  new_events: Vec<Event>, // in the order they were emitted
+  targeted_events: Vec<(ComponentKey, Event)>, // events for a single component
+  event_listeners: HashMap<ComponentKey, HashMap<EventKey, Listener>>,
+  next_order: u64,
+  scheduled_events: HashMap<ScheduledEventId, ScheduledEvent>,
+  next_se_id: u64,
+  time: f32, // seconds since the manager was created
 }
 
 impl EventManager {
   pub fn new() -> EventManager {
     Self {
-      new_events: HashMap::new(),
+      new_events: Vec::new(),
+      targeted_events: Vec::new(),
       event_listeners: HashMap::new(),
-      triggered_events: HashMap::new()
+      next_order: 0,
+      scheduled_events: HashMap::new(),
+      next_se_id: 0,
+      time: 0.,
     }
   }
 
-  pub fn handle_event(&mut self, event: Event) -> bool {
-    for (comp, map) in self.event_listeners.iter() {
-      if map.contains_key(&event.key) {
-        if !self.triggered_events.contains_key(comp) {
-          self.triggered_events.insert(comp.clone(), Vec::new());
+  // advances the clock and fires any scheduled events that are due
+  pub fn update(&mut self, dt: f32) {
+    self.time += dt;
+    let mut fired: Vec<Event> = Vec::new();
+    let mut finished: Vec<ScheduledEventId> = Vec::new();
+    for (id, se) in self.scheduled_events.iter_mut() {
+      se.time_elapsed += dt;
+      if !se.recurrent {
+        if se.ready() {
+          fired.push(se.event.clone());
+          finished.push(*id);
+        }
+        continue;
+      }
+      // a long frame can cover several periods
+      if se.time_to_trigger <= 0. {
+        if se.ready() {
+          fired.push(se.event.clone());
+          se.time_elapsed = 0.;
         }
-        let trigger_vec = self.triggered_events.get_mut(comp).unwrap();
-        trigger_vec.push((event.key.clone(), map.get(&event.key).unwrap().clone()));
+        continue;
+      }
+      while se.ready() {
+        fired.push(se.event.clone());
+        se.reset();
       }
     }
-
-    if !self.new_events.contains_key(&event.key) {
-      self.new_events.insert(event.key.clone(), Vec::new());
+    for id in finished {
+      self.scheduled_events.remove(&id);
     }
-    self.new_events.get_mut(&event.key).unwrap().push(event);
+    for event in fired {
+      self.handle_event(event);
+    }
+  }
+
+  pub fn time(&self) -> f32 {
+    self.time
+  }
+
+  fn add_se(&mut self, event: Event, time_to_trigger: f32, recurrent: bool) -> ScheduledEventId {
+    let id = ScheduledEventId::new(self.next_se_id);
+    self.next_se_id += 1;
+    self.scheduled_events.insert(id, ScheduledEvent::new(id, event, time_to_trigger, recurrent));
+    id
+  }
+
+  // fires the event once the manager's clock reaches time (in seconds)
+  pub fn schedule_at_time(&mut self, event: Event, time: f32) -> ScheduledEventId {
+    let delay = (time - self.time).max(0.);
+    self.add_se(event, delay, false)
+  }
+
+  pub fn trigger_after_delay(&mut self, event: Event, delay: f32) -> ScheduledEventId {
+    self.add_se(event, delay.max(0.), false)
+  }
+
+  // fires the event every period seconds until it is removed
+  pub fn schedule_recurrent(&mut self, event: Event, period: f32) -> ScheduledEventId {
+    self.add_se(event, period, true)
+  }
+
+  // cancels a pending or recurring event. returns false if it already fired
+  pub fn remove_se(&mut self, id: &ScheduledEventId) -> bool {
+    self.scheduled_events.remove(id).is_some()
+  }
+
+  // queues the event until the next trigger_callbacks. who receives it is
+  // decided then, so listeners added or removed in between are respected
+  pub fn handle_event(&mut self, event: Event) -> bool {
+    self.new_events.push(event);
     true
   }
 
+  // queues an event for a single component. it's dropped (returning false)
+  // unless that component listens for the event's key
+  pub fn handle_event_for(&mut self, component: ComponentKey, event: Event) -> bool {
+    let listening = self.event_listeners.get(&component)
+      .map(|map| map.contains_key(&event.key))
+      .unwrap_or(false);
+    if listening {
+      self.targeted_events.push((component, event));
+    }
+    listening
+  }
+
   pub fn add_listener(
     &mut self, 
     component: ComponentKey, 
     event: EventKey,
     function: fn(&mut dyn EventListener, Event) -> ()
   ) -> Result<(), EngineError> {
-    if !self.event_listeners.contains_key(&component) {
-      self.event_listeners.insert(component.clone(), HashMap::new());
-    }
-    if !self.event_listeners.get_mut(&component).unwrap().insert(event, function).is_none() {
-      println!("Event listener successfully added");
-      return Ok(())
-    }
+    self.add_listener_with_priority(component, event, function, 0)
+  }
 
-    Err(EngineError::Custom("Hashmap insertion failure".into()))
+  // lower priorities are called first. re-registering a component for the
+  // same event replaces its listener and moves it to the back of its priority
+  pub fn add_listener_with_priority(
+    &mut self,
+    component: ComponentKey,
+    event: EventKey,
+    function: fn(&mut dyn EventListener, Event) -> (),
+    priority: i32
+  ) -> Result<(), EngineError> {
+    let listener = Listener {
+      callback: function,
+      priority,
+      order: self.next_order
+    };
+    self.next_order += 1;
+    self.event_listeners.entry(component).or_insert(HashMap::new()).insert(event, listener);
+    Ok(())
   }
 
   pub fn remove_listener(
@@ -64,42 +159,194 @@ impl EventManager {
     }
 
     let event_map = self.event_listeners.get_mut(component).unwrap();
-    if !event_map.remove(event).is_none() {
+    if event_map.remove(event).is_none() {
       return Err(EngineError::ArgumentError { index: 2, name: "event".into() });
     }
     Ok(())
   }
 
+  // drops every listener and pending callback registered by a component
+  pub fn remove_component_listeners(&mut self, component: &ComponentKey) {
+    self.event_listeners.remove(component);
+    self.targeted_events.retain(|(key, _)| key != component);
+  }
+
+  // every queued event goes to each listener registered for its key at
+  // this point, in emission order, and is then dropped. events with no
+  // listeners are dropped as well, so nothing carries over to the next call.
+  // targeted events are delivered after the broadcast ones
   pub fn trigger_callbacks(
     &mut self,
     components: &mut ComponentStore,
   ) {
-    let mut callbacks_to_trigger: HashMap<EventKey, Vec<(Component, fn(&mut dyn EventListener, Event) -> ())>> = HashMap::new();
-    for (comp, events) in self.triggered_events.iter() {
-      if let Some(component) = components.get_mut(comp) {
-        let mut triggered_events: HashSet<EventKey> = HashSet::new();
-        for (key, callback) in events {
-          if triggered_events.contains(&key) || !self.new_events.contains_key(&key) {
-            continue;
-          }
-          triggered_events.insert(key.clone());
-          if !callbacks_to_trigger.contains_key(key) {
-            callbacks_to_trigger.insert(key.clone(), Vec::new());
-          }
-          let cloned = component.clone();
-          callbacks_to_trigger.get_mut(key).unwrap().push((cloned, callback.clone()));
-        }
+    for event in std::mem::take(&mut self.new_events) {
+      let mut callbacks = self.event_listeners.iter()
+        .filter_map(|(comp, map)| map.get(&event.key).map(|listener| (comp, *listener)))
+        .filter_map(|(comp, listener)| components.get_mut(comp).map(|component| (component.clone(), listener)))
+        .collect::<Vec<(Component, Listener)>>();
+      callbacks.sort_by_key(|(_, listener)| (listener.priority, listener.order));
+      for (component, listener) in callbacks.iter_mut() {
+        (listener.callback)(component, event.clone());
       }
     }
 
-    for (key, callbacks) in callbacks_to_trigger.iter_mut() {
-      for event in self.new_events.remove(&key).unwrap_or(Vec::new()) {
-        for (component, callback) in callbacks.iter_mut() {
-          (*callback)(component, event.clone());
-        }
+    for (comp, event) in std::mem::take(&mut self.targeted_events) {
+      let listener = self.event_listeners.get(&comp).and_then(|map| map.get(&event.key)).cloned();
+      if let (Some(listener), Some(component)) = (listener, components.get_mut(&comp)) {
+        let mut component = component.clone();
+        (listener.callback)(&mut component, event);
       }
     }
-    
-    self.new_events.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::events::event::EventData;
+  use crate::engine::test_util::{record_event, recorder_store, share_call_log};
+
+  fn custom(name: &str) -> Event {
+    Event::new(EventData::CustomEvent(name.into()))
+  }
+
+  fn names(events: &Vec<Event>) -> Vec<String> {
+    events.iter().map(|event| match &event.data {
+      EventData::CustomEvent(name) => name.clone(),
+      _ => String::new()
+    }).collect()
+  }
+
+  #[test]
+  fn queued_events_are_delivered_exactly_once() {
+    let mut manager = EventManager::new();
+    let (mut components, recorders) = recorder_store(1);
+    let (key, recorder) = &recorders[0];
+    manager.add_listener(*key, EventKey::CustomEvent, record_event).unwrap();
+    manager.handle_event(custom("first"));
+    manager.handle_event(custom("second"));
+    manager.trigger_callbacks(&mut components);
+    manager.trigger_callbacks(&mut components);
+    assert_eq!(names(&recorder.lock().unwrap().events), vec!["first", "second"]);
+  }
+
+  fn noop(_: &mut dyn EventListener, _: Event) {}
+
+  #[test]
+  fn lower_priority_listeners_run_first() {
+    let mut manager = EventManager::new();
+    let (mut components, recorders) = recorder_store(2);
+    let log = share_call_log(&recorders);
+    // registered first, but with the higher priority
+    manager.add_listener_with_priority(recorders[0].0, EventKey::CustomEvent, record_event, 10).unwrap();
+    manager.add_listener_with_priority(recorders[1].0, EventKey::CustomEvent, record_event, 0).unwrap();
+    manager.handle_event(custom("ping"));
+    manager.trigger_callbacks(&mut components);
+    assert_eq!(*log.lock().unwrap(), vec![recorders[1].0, recorders[0].0]);
+  }
+
+  #[test]
+  fn removing_missing_listeners_is_an_error() {
+    let mut manager = EventManager::new();
+    let key = ComponentKey { index: 0, generation: 0 };
+    assert!(manager.remove_listener(&key, &EventKey::CustomEvent).is_err());
+    manager.add_listener(key, EventKey::CustomEvent, noop).unwrap();
+    assert!(manager.remove_listener(&key, &EventKey::KeyboardEvent).is_err());
+    assert!(manager.remove_listener(&key, &EventKey::CustomEvent).is_ok());
+  }
+
+  fn queued(manager: &EventManager) -> usize {
+    manager.new_events.len()
+  }
+
+  #[test]
+  fn delayed_events_fire_once_when_due() {
+    let mut manager = EventManager::new();
+    manager.trigger_after_delay(custom("later"), 1.);
+    manager.update(0.5);
+    assert_eq!(queued(&manager), 0);
+    manager.update(0.6);
+    assert_eq!(queued(&manager), 1);
+    assert!(manager.scheduled_events.is_empty());
+  }
+
+  #[test]
+  fn scheduled_times_are_on_the_manager_clock() {
+    let mut manager = EventManager::new();
+    manager.update(2.);
+    manager.schedule_at_time(custom("at"), 3.);
+    manager.update(0.5);
+    assert_eq!(queued(&manager), 0);
+    manager.update(0.5);
+    assert_eq!(queued(&manager), 1);
+    assert_eq!(manager.time(), 3.);
+  }
+
+  #[test]
+  fn cancelled_recurrent_events_stop_firing() {
+    let mut manager = EventManager::new();
+    let id = manager.schedule_recurrent(custom("spawn"), 1.);
+    manager.update(1.);
+    assert_eq!(queued(&manager), 1);
+    assert!(manager.remove_se(&id));
+    manager.update(5.);
+    assert_eq!(queued(&manager), 1);
+    // already gone
+    assert!(!manager.remove_se(&id));
+  }
+
+  #[test]
+  fn recurrent_events_keep_their_remainder() {
+    let mut manager = EventManager::new();
+    let id = manager.schedule_recurrent(custom("tick"), 0.1);
+    manager.update(0.25);
+    assert_eq!(queued(&manager), 2);
+    let remainder = manager.scheduled_events.get(&id).unwrap().time_elapsed;
+    assert!((remainder - 0.05).abs() < 1e-5);
+  }
+
+  #[test]
+  fn recurrent_events_catch_up_over_long_frames() {
+    let mut manager = EventManager::new();
+    manager.schedule_recurrent(custom("tick"), 1.);
+    manager.update(3.5);
+    assert_eq!(queued(&manager), 3);
+    manager.update(0.5);
+    assert_eq!(queued(&manager), 4);
+  }
+
+  #[test]
+  fn targeted_events_need_a_listener() {
+    let mut manager = EventManager::new();
+    let key = ComponentKey { index: 0, generation: 0 };
+    assert!(!manager.handle_event_for(key, custom("hi")));
+    manager.add_listener(key, EventKey::CustomEvent, noop).unwrap();
+    assert!(manager.handle_event_for(key, custom("hi")));
+    manager.remove_component_listeners(&key);
+    assert!(manager.targeted_events.is_empty());
+  }
+
+  #[test]
+  fn targeted_events_reach_only_their_component() {
+    let mut manager = EventManager::new();
+    let (mut components, recorders) = recorder_store(2);
+    for (key, _) in recorders.iter() {
+      manager.add_listener(*key, EventKey::CustomEvent, record_event).unwrap();
+    }
+    let event = custom("just you");
+    // what Scene::emit_to checks before queueing
+    assert!(event.is_valid());
+    assert!(manager.handle_event_for(recorders[1].0, event));
+    manager.trigger_callbacks(&mut components);
+    assert!(recorders[0].1.lock().unwrap().events.is_empty());
+    assert_eq!(names(&recorders[1].1.lock().unwrap().events), vec!["just you"]);
+  }
+
+  #[test]
+  fn malformed_events_are_invalid() {
+    assert!(!custom("").is_valid());
+    let mut mislabeled = custom("named");
+    mislabeled.key = EventKey::KeyboardEvent;
+    assert!(!mislabeled.is_valid());
   }
 }
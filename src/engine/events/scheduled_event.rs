@@ -0,0 +1,42 @@
+use super::event::Event;
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct ScheduledEventId(u64);
+
+impl ScheduledEventId {
+  pub fn new(id: u64) -> ScheduledEventId {
+    Self(id)
+  }
+}
+
+// an event fired once time_elapsed reaches time_to_trigger (in seconds).
+// recurrent events are reset after firing instead of being removed
+pub struct ScheduledEvent {
+  pub id: ScheduledEventId,
+  pub event: Event,
+  pub time_elapsed: f32,
+  pub time_to_trigger: f32,
+  pub recurrent: bool,
+}
+
+impl ScheduledEvent {
+  pub fn new(id: ScheduledEventId, event: Event, time_to_trigger: f32, recurrent: bool) -> ScheduledEvent {
+    Self {
+      id,
+      event,
+      time_elapsed: 0.,
+      time_to_trigger,
+      recurrent
+    }
+  }
+
+  pub fn ready(&self) -> bool {
+    self.time_elapsed >= self.time_to_trigger
+  }
+
+  // keeps any overshoot past the trigger time so recurrent events don't
+  // drift when frames don't line up with the period
+  pub fn reset(&mut self) {
+    self.time_elapsed -= self.time_to_trigger;
+  }
+}
@@ -0,0 +1,110 @@
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+use crate::graphics::CameraController;
+
+use super::events::{Event, EventData, EventManager};
+
+const DEFAULT_DEADZONE: f32 = 0.15;
+const DEFAULT_LOOK_SPEED: f32 = 400.; // stick deflection to mouse pixels per frame
+
+// Polls connected gamepads through gilrs and forwards their buttons and
+// axes to the event manager as GamepadButton and GamepadAxis events. When
+// drive_camera is set, the left stick moves the camera controller, the
+// right stick turns it and the triggers raise and lower it
+pub struct Gamepads {
+  pub drive_camera: bool,
+  pub deadzone: f32, // axis values closer to 0 than this are reported as 0
+  pub look_speed: f32,
+  gilrs: Option<Gilrs>, // None when no gamepad backend is available
+}
+
+impl Gamepads {
+  pub fn new() -> Gamepads {
+    let gilrs = match Gilrs::new() {
+      Ok(gilrs) => Some(gilrs),
+      Err(e) => {
+        log::warn!("gamepad input unavailable: {}", e);
+        None
+      }
+    };
+    Self {
+      drive_camera: false,
+      deadzone: DEFAULT_DEADZONE,
+      look_speed: DEFAULT_LOOK_SPEED,
+      gilrs,
+    }
+  }
+
+  pub fn is_available(&self) -> bool {
+    self.gilrs.is_some()
+  }
+
+  // drains the events gilrs has queued since the last poll
+  pub fn poll(&mut self, event_manager: &mut EventManager, camera_controller: &mut CameraController) {
+    let Some(gilrs) = self.gilrs.as_mut() else {
+      return;
+    };
+    let mut translated = Vec::new();
+    while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+      if let Some(data) = translate_gamepad_event(usize::from(id), &event, self.deadzone) {
+        translated.push(data);
+      }
+    }
+    for data in translated {
+      if self.drive_camera {
+        self.apply_to_camera(&data, camera_controller);
+      }
+      event_manager.handle_event(Event::new(data));
+    }
+  }
+
+  fn apply_to_camera(&self, data: &EventData, controller: &mut CameraController) {
+    match data {
+      EventData::GamepadAxis { axis, value, .. } => match axis {
+        Axis::LeftStickX => {
+          controller.amount_right = value.max(0.);
+          controller.amount_left = (-value).max(0.);
+        },
+        Axis::LeftStickY => {
+          controller.amount_forward = value.max(0.);
+          controller.amount_backward = (-value).max(0.);
+        },
+        Axis::RightStickX => controller.rotate_horizontal = value * self.look_speed,
+        Axis::RightStickY => controller.rotate_vertical = -value * self.look_speed,
+        _ => ()
+      },
+      EventData::GamepadButton { button, pressed, .. } => {
+        let amount = if *pressed { 1. } else { 0. };
+        match button {
+          Button::RightTrigger2 => controller.amount_up = amount,
+          Button::LeftTrigger2 => controller.amount_down = amount,
+          _ => ()
+        }
+      },
+      _ => ()
+    }
+  }
+}
+
+// the engine event for a gilrs event, if it has one. connection changes
+// and repeats aren't forwarded
+pub fn translate_gamepad_event(gamepad: usize, event: &EventType, deadzone: f32) -> Option<EventData> {
+  match event {
+    EventType::ButtonPressed(button, _) => Some(EventData::GamepadButton {
+      gamepad,
+      button: *button,
+      pressed: true
+    }),
+    EventType::ButtonReleased(button, _) => Some(EventData::GamepadButton {
+      gamepad,
+      button: *button,
+      pressed: false
+    }),
+    EventType::AxisChanged(axis, value, _) => Some(EventData::GamepadAxis {
+      gamepad,
+      axis: *axis,
+      value: if value.abs() < deadzone { 0. } else { *value }
+    }),
+    _ => None
+  }
+}
@@ -1,12 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, num::NonZeroU64, sync::{atomic::{AtomicBool, Ordering}, mpsc::{channel, Receiver, TryRecvError}, Arc, Mutex}};
 
 use anyhow::Error;
-use cgmath::{Matrix4, Point3, Quaternion, Rotation3, Vector3};
-use wgpu::{util::DeviceExt};
+use cgmath::{InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Rotation3, Vector3};
+use tokio::runtime::Runtime;
+use wgpu::{util::{DeviceExt, StagingBelt}};
 
-use crate::graphics::{load_model, Instance, InstanceRaw, Model};
+use crate::graphics::{build_model, load_model, read_model_source, Frustum, Instance, InstanceRaw, Model, ModelSource, Texture};
 
-use super::{component::Component, component_store::ComponentKey, errors::EngineError, transform_queue::TransformQueue, transforms::{ComponentTransform, GlobalTransform, ModelTransform, TransformType}};
+use super::{component::{AsyncCallbackHandler, Component}, component_store::ComponentKey, errors::EngineError, transform_queue::TransformQueue, transforms::{BillboardMode, ComponentTransform, GlobalTransform, ModelTransform, TransformType}};
+
+// size of each staging chunk used to upload instance data
+const INSTANCE_BELT_CHUNK_SIZE: u64 = 1 << 16;
 
 #[derive(Eq, PartialEq, Hash, Clone)]
 pub struct RenderableModel {
@@ -21,7 +25,100 @@ pub struct RenderData {
   global_pos: Vector3<f32>,
   global_rot: Quaternion<f32>,
   instances: Vec<Instance>,
-  instance_buf: wgpu::Buffer
+  instance_buf: wgpu::Buffer,
+  instance_capacity: usize, // number of instances instance_buf can hold
+  opacity: f32,
+  tint: [f32; 4],
+  selected: bool,
+  dirty: bool, // instances changed since the last flush
+  retextured: bool, // materials no longer match the file's, so it can't be batched
+  mesh_filter: Option<Vec<usize>>, // meshes to draw, None draws all
+}
+
+impl RenderData {
+  fn raw_instances(&self) -> Vec<InstanceRaw> {
+    self.instances
+      .iter()
+      .map(|i| i.to_raw_with_state(self.opacity, self.selected, self.tint))
+      .collect::<Vec<InstanceRaw>>()
+  }
+
+  // marks the current instances for upload at the next flush, reallocating
+  // the buffer at the next power of two when they no longer fit
+  fn write_instances(&mut self, device: &wgpu::Device) {
+    let instance_data = self.raw_instances();
+    if instance_data.len() > self.instance_capacity {
+      let capacity = instance_data.len().next_power_of_two();
+      let mut contents = instance_data.clone();
+      contents.resize(capacity, instance_data[0]);
+      self.instance_buf = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+          label: Some("Instance buffer"),
+          contents: bytemuck::cast_slice(&contents),
+          usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
+        }
+      );
+      self.instance_capacity = capacity;
+      self.dirty = false;
+      return;
+    }
+    self.dirty = true;
+  }
+}
+
+// a single draw in the main pass: either one model with its own instance
+// buffer, or every opaque model loaded from the same file sharing a batch buffer
+enum DrawCall {
+  Single(RenderableModel),
+  Batch {
+    model: RenderableModel, // any model in the batch, used for its meshes
+    filename: String,
+    instance_count: u32,
+  }
+}
+
+// Returned by a background model load. Shares the load's progress so
+// callers can poll it (e.g. for a loading bar) while the file is read
+#[derive(Clone)]
+pub struct ModelLoadHandle {
+  filename: String,
+  progress: Arc<Mutex<(u64, u64)>>, // (bytes loaded, total bytes)
+  finished: Arc<AtomicBool>,
+}
+
+impl ModelLoadHandle {
+  pub fn filename(&self) -> &str {
+    &self.filename
+  }
+
+  pub fn progress(&self) -> (u64, u64) {
+    *self.progress.lock().unwrap()
+  }
+
+  // loaded fraction in [0, 1]
+  pub fn fraction(&self) -> f32 {
+    let (loaded, total) = self.progress();
+    if total == 0 {
+      return if self.is_finished() { 1. } else { 0. }
+    }
+    (loaded as f32 / total as f32).min(1.)
+  }
+
+  // true once the model is on the gpu and has been delivered
+  pub fn is_finished(&self) -> bool {
+    self.finished.load(Ordering::Acquire)
+  }
+}
+
+// a model whose files are being read on another thread. gpu resources
+// can only be created once it arrives back on the render thread
+struct PendingLoad {
+  receiver: Receiver<anyhow::Result<ModelSource>>,
+  filename: String,
+  instances: Option<Vec<Instance>>,
+  component_key: ComponentKey,
+  handle: ModelLoadHandle,
+  deliver: Box<dyn FnOnce(Result<RenderableModel, EngineError>)>,
 }
 
 pub struct ModelRenderer {
@@ -30,7 +127,14 @@ pub struct ModelRenderer {
   render_list: Vec<RenderableModel>,
   models: HashMap<RenderableModel, RenderData>,
   transform_queue: TransformQueue,
-  component_transform_cache: HashMap<ComponentKey, Matrix4<f32>>
+  component_transform_cache: HashMap<ComponentKey, Matrix4<f32>>,
+  batch_buffers: HashMap<String, (wgpu::Buffer, usize)>, // filename -> (buffer, instance capacity)
+  draw_list: Vec<DrawCall>,
+  pending_loads: Vec<PendingLoad>,
+  staging_belt: StagingBelt,
+  pending_batch_writes: Vec<(String, Vec<InstanceRaw>)>, // filename -> merged instances
+  last_write_count: usize,
+  lod_groups: HashMap<RenderableModel, Vec<(RenderableModel, f32)>>, // model -> (tier, max distance), nearest first
 }
 
 impl ModelRenderer {
@@ -40,7 +144,14 @@ impl ModelRenderer {
       render_list: Vec::new(),
       models: HashMap::new(),
       transform_queue: TransformQueue::new(),
-      component_transform_cache: HashMap::new()
+      component_transform_cache: HashMap::new(),
+      batch_buffers: HashMap::new(),
+      draw_list: Vec::new(),
+      pending_loads: Vec::new(),
+      staging_belt: StagingBelt::new(INSTANCE_BELT_CHUNK_SIZE),
+      pending_batch_writes: Vec::new(),
+      last_write_count: 0,
+      lod_groups: HashMap::new(),
     }
   }
 
@@ -61,11 +172,37 @@ impl ModelRenderer {
       return Err(EngineError::ModelLoadError { err, filename: filename.into() } );
     }
 
-    let model = model_res.unwrap();
+    Ok(self.add_model(model_res.unwrap(), filename, instances, component_key, device))
+  }
+
+  // registers a model built in code (e.g. Model::ground_grid) rather than
+  // loaded from a file. name stands in for the filename in the key
+  pub fn add_generated_model(
+    &mut self,
+    model: Model,
+    name: &str,
+    instances: Option<Vec<Instance>>,
+    component_key: ComponentKey,
+    device: &wgpu::Device,
+  ) -> RenderableModel {
+    self.add_model(model, name, instances, component_key, device)
+  }
+
+  // indexes an already built model for rendering and returns its key
+  fn add_model(
+    &mut self,
+    model: Model,
+    filename: &str,
+    instances: Option<Vec<Instance>>,
+    component_key: ComponentKey,
+    device: &wgpu::Device,
+  ) -> RenderableModel {
     let instanced = !(instances.is_none());
     let default_inst = Instance {
       position: Vector3 { x: 0., y: 0., z: 0. },
-      rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+      rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0)),
+      scale: Vector3::new(1., 1., 1.),
+      color: [1., 1., 1., 1.]
     };
     let instance_vec: Vec<Instance> = instances.unwrap_or([default_inst.clone()].into());
     let instance_data = instance_vec
@@ -85,24 +222,172 @@ impl ModelRenderer {
       component: component_key,
       filename: filename.into()
     };
+    self.next_idx += 1;
     
     let data: RenderData = RenderData {
       model,
       instanced,
       global_pos: instance_vec.get(0).unwrap_or(&default_inst.clone()).position.clone(),
       global_rot: instance_vec.get(0).unwrap_or(&default_inst.clone()).rotation.clone(),
+      instance_capacity: instance_vec.len(),
       instances: instance_vec,
-      instance_buf
+      instance_buf,
+      opacity: 1.,
+      tint: [1., 1., 1., 1.],
+      selected: false,
+      dirty: false,
+      retextured: false,
+      mesh_filter: None
     };
     self.models.insert(key.clone(), data);
-    Ok(key)
+    key
+  }
+
+  // reads the model's files on a background thread so the caller isn't
+  // blocked. the gpu upload happens in poll_loads, after which the result
+  // is delivered to target through handle_async_res
+  pub fn load_model_async<CType: AsyncCallbackHandler<Result<RenderableModel, EngineError>>>(
+    &mut self,
+    filename: &str,
+    instances: Option<Vec<Instance>>,
+    component_key: ComponentKey,
+    target: Arc<Mutex<Box<CType>>>,
+    progress: Option<Box<dyn Fn(u64, u64) + Send + Sync>>,
+  ) -> ModelLoadHandle {
+    let handle = ModelLoadHandle {
+      filename: filename.into(),
+      progress: Arc::new(Mutex::new((0, 0))),
+      finished: Arc::new(AtomicBool::new(false)),
+    };
+    let (sender, receiver) = channel();
+
+    // in new thread
+    let shared_progress = handle.progress.clone();
+    let thread_filename = filename.to_string();
+    std::thread::spawn(move || {
+      let report = move |loaded: u64, total: u64| {
+        *shared_progress.lock().unwrap() = (loaded, total);
+        if let Some(progress) = &progress {
+          progress(loaded, total);
+        }
+      };
+      let rt = Runtime::new().unwrap();
+      let source = rt.block_on(async {
+        read_model_source(&thread_filename, Some(&report)).await
+      });
+      // the renderer may have been dropped in the meantime
+      let _ = sender.send(source);
+    });
+
+    self.pending_loads.push(PendingLoad {
+      receiver,
+      filename: filename.into(),
+      instances,
+      component_key,
+      handle: handle.clone(),
+      deliver: Box::new(move |res| target.lock().unwrap().handle_async_res(res)),
+    });
+    handle
+  }
+
+  // finishes any background loads whose files have been read
+  pub fn poll_loads(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, tex_layout: &wgpu::BindGroupLayout) {
+    let pending = std::mem::take(&mut self.pending_loads);
+    for load in pending {
+      let source = match load.receiver.try_recv() {
+        Ok(source) => source,
+        Err(TryRecvError::Empty) => {
+          self.pending_loads.push(load);
+          continue;
+        },
+        Err(TryRecvError::Disconnected) => Err(Error::msg("model loading thread exited early")),
+      };
+      let res = source
+        .and_then(|source| build_model(source, device, queue, tex_layout))
+        .map(|model| self.add_model(model, &load.filename, load.instances, load.component_key, device))
+        .map_err(|err| {
+          println!("model load failed!");
+          EngineError::ModelLoadError { err, filename: load.filename.clone() }
+        });
+      load.handle.finished.store(true, Ordering::Release);
+      (load.deliver)(res);
+    }
+  }
+
+  // removes a loaded model, dropping its gpu buffers. the key is invalid afterwards
+  pub fn unload_model(&mut self, model: &RenderableModel) -> Result<(), EngineError> {
+    if self.models.remove(model).is_none() {
+      return Err(EngineError::ArgumentError { index: 1, name: "model".into() })
+    }
+    self.render_list.retain(|rm| rm != model);
+    self.draw_list.retain(|call| match call {
+      DrawCall::Single(rm) => rm != model,
+      DrawCall::Batch { model: rm, .. } => rm != model,
+    });
+    self.lod_groups.remove(model);
+    for tiers in self.lod_groups.values_mut() {
+      tiers.retain(|(tier, _)| tier != model);
+    }
+    self.lod_groups.retain(|_, tiers| tiers.len() > 0);
+    // the batch buffer is shared by every model from the same file
+    if !self.models.keys().any(|rm| rm.filename == model.filename) {
+      self.batch_buffers.remove(&model.filename);
+    }
+    Ok(())
+  }
+
+  // replaces the diffuse texture of one of a loaded model's materials.
+  // tex_layout must be the layout the model was loaded with
+  pub fn set_model_texture(
+    &mut self,
+    model: &RenderableModel,
+    material_index: usize,
+    texture: Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    tex_layout: &wgpu::BindGroupLayout
+  ) -> Result<(), EngineError> {
+    let render_data = self.models.get_mut(model)
+      .ok_or(EngineError::ArgumentError { index: 1, name: "model".into() })?;
+    let material = render_data.model.materials.get_mut(material_index)
+      .ok_or(EngineError::ArgumentError { index: 2, name: "material_index".into() })?;
+    material.set_diffuse_texture(device, queue, texture, tex_layout);
+    render_data.retextured = true;
+    Ok(())
+  }
+
+  // unloads every model owned by a component and drops its unfinished
+  // background loads. returns the number of models unloaded
+  pub fn unload_component_models(&mut self, component_key: ComponentKey) -> usize {
+    let owned = self.models.keys()
+      .filter(|rm| rm.component == component_key)
+      .cloned()
+      .collect::<Vec<RenderableModel>>();
+    for rm in owned.iter() {
+      let _ = self.unload_model(rm);
+    }
+    self.pending_loads.retain(|load| load.component_key != component_key);
+    self.component_transform_cache.remove(&component_key);
+    self.transform_queue.remove(&component_key);
+    owned.len()
+  }
+
+  // highest opacity among the component's models, or None if it has none
+  pub fn component_opacity(&self, key: &ComponentKey) -> Option<f32> {
+    self.models.iter()
+      .filter(|(rm, _)| rm.component == *key)
+      .map(|(_, rd)| rd.opacity)
+      .reduce(f32::max)
+  }
+
+  pub fn loaded_model_count(&self) -> usize {
+    self.models.len()
   }
 
   pub fn position_model(
     &mut self,
     model: &RenderableModel,
     new_pos: Vector3<f32>,
-    queue: &wgpu::Queue,
   ) -> Result<(), EngineError> {
     if !self.models.contains_key(model) {
       return Err(EngineError::ArgumentError { index: 1, name: "model".into() });
@@ -114,15 +399,13 @@ impl ModelRenderer {
     let current_rot = render_data.global_rot.clone();
     render_data.instances[0] = Instance {
       position: new_pos.clone(),
-      rotation: current_rot
+      rotation: current_rot,
+      scale: render_data.instances[0].scale,
+      color: render_data.instances[0].color
     };
     render_data.global_pos = new_pos.clone();
 
-    let instance_data = render_data.instances
-      .iter()
-      .map(Instance::to_raw)
-      .collect::<Vec<InstanceRaw>>();
-    queue.write_buffer(&render_data.instance_buf, 0, bytemuck::cast_slice(&instance_data));
+    render_data.dirty = true;
     self.models.insert(model.clone(), render_data);
     Ok(())
   }
@@ -131,7 +414,6 @@ impl ModelRenderer {
     &mut self,
     model: &RenderableModel,
     new_rot: Quaternion<f32>,
-    queue: &wgpu::Queue,
   ) -> Result<(), EngineError> {
     if !self.models.contains_key(model) {
       return Err(EngineError::ArgumentError { index: 1, name: "model".into() });
@@ -143,15 +425,13 @@ impl ModelRenderer {
     let current_pos = render_data.global_pos.clone();
     render_data.instances[0] = Instance {
       position: current_pos,
-      rotation: new_rot
+      rotation: new_rot,
+      scale: render_data.instances[0].scale,
+      color: render_data.instances[0].color
     };
     render_data.global_rot = new_rot.clone();
 
-    let instance_data = render_data.instances
-      .iter()
-      .map(Instance::to_raw)
-      .collect::<Vec<InstanceRaw>>();
-    queue.write_buffer(&render_data.instance_buf, 0, bytemuck::cast_slice(&instance_data));
+    render_data.dirty = true;
     self.models.insert(model.clone(), render_data);
     Ok(())
   }
@@ -160,7 +440,7 @@ impl ModelRenderer {
     &mut self,
     model: &RenderableModel,
     new_instance_vec: Vec<Instance>,
-    queue: &wgpu::Queue,
+    device: &wgpu::Device,
   ) -> Result<(), EngineError> {
     if !self.models.contains_key(model) {
       return Err(EngineError::ArgumentError { index: 1, name: "model".into() });
@@ -172,11 +452,7 @@ impl ModelRenderer {
       render_data.global_pos = new_instance_vec[0].position.clone();
       render_data.global_rot = new_instance_vec[0].rotation.clone();
     }
-    let instance_data = new_instance_vec
-      .iter()
-      .map(Instance::to_raw)
-      .collect::<Vec<InstanceRaw>>();
-    queue.write_buffer(&render_data.instance_buf, 0, bytemuck::cast_slice(&instance_data));
+    render_data.write_instances(device);
 
     self.models.insert(model.clone(), render_data);
     Ok(())
@@ -184,7 +460,7 @@ impl ModelRenderer {
 
   pub fn start_component_render(&mut self, transform: Option<ComponentTransform>, key: ComponentKey) {
     let transform_unwrapped = transform.unwrap_or(ComponentTransform::default());
-    self.transform_queue.push(transform_unwrapped);
+    self.transform_queue.push(key, transform_unwrapped);
     self.component_transform_cache.insert(key, self.transform_queue.get_transform_matrix());
   }
 
@@ -196,42 +472,52 @@ impl ModelRenderer {
     &mut self, 
     model: &RenderableModel,
     transform: ModelTransform, 
-    queue: &wgpu::Queue,
     device: &wgpu::Device,
+    camera_forward: Vector3<f32>,
   ) -> Result<(), EngineError> {
     if !self.models.contains_key(&model) {
       return Err(EngineError::ArgumentError { index: 1, name: "model".into() })
     }
 
+    // the filter only affects drawing, so it never needs a buffer update
+    self.models.get_mut(&model).unwrap().mesh_filter = transform.mesh_filter.clone();
     let mut instanced = self.models.get(&model).unwrap().instanced;
     let mut global_pos = self.models.get(&model).unwrap().global_pos;
     let mut global_rot = self.models.get(&model).unwrap().global_rot;
     let mut instance_vec = self.models.get(&model).unwrap().instances.clone();
-    let mut needs_buf_update = false;
+    let opacity = self.models.get(&model).unwrap().opacity;
+    let tint = self.models.get(&model).unwrap().tint;
+    let previous_instances = instance_vec.clone();
+    let previous_instanced = instanced;
+    let mut needs_buf_update = opacity != transform.opacity || tint != transform.tint;
     if !transform.instanced {
       let pos = transform.pos;
       let rot = transform.rot;
       if transform.transform_type == TransformType::Global {
-        if global_pos != pos || global_rot != rot {
+        if global_pos != pos || global_rot != rot || instance_vec[0].scale != transform.scale {
           needs_buf_update = true;
           global_pos = pos;
           global_rot = rot;
           instance_vec[0] = Instance {
             position: pos.clone(),
-            rotation: rot.clone()
+            rotation: rot.clone(),
+            scale: transform.scale,
+            color: instance_vec[0].color
           };
         }
       } else {
         let transformed = self.transform_queue.transform_model(&transform);
         let pos_t = transformed.pos;
         let rot_t = transformed.rot;
-        if global_pos != pos_t || global_rot != rot_t {
+        if global_pos != pos_t || global_rot != rot_t || instance_vec[0].scale != transformed.scale {
           needs_buf_update = true;
           global_pos = pos_t;
           global_rot = rot_t;
           instance_vec[0] = Instance {
             position: pos_t.clone(),
-            rotation: rot_t.clone()
+            rotation: rot_t.clone(),
+            scale: transformed.scale,
+            color: instance_vec[0].color
           };
         }
       }
@@ -241,10 +527,13 @@ impl ModelRenderer {
         needs_buf_update = true;
       }
       let instances = transform.clone().instances;
+      if instances.len() != instance_vec.len() {
+        needs_buf_update = true;
+      }
       match transform.transform_type {
         TransformType::Global => {
           for (idx, instance) in instances.iter().enumerate() {
-            if instance_vec[idx] != instance.clone() {
+            if instance_vec.get(idx) != Some(instance) {
               needs_buf_update = true;
               break;
             }
@@ -255,7 +544,7 @@ impl ModelRenderer {
           let transformed = self.transform_queue.transform_model(&transform);
           let instances_t = transformed.instances;
           for (idx, instance) in instances_t.iter().enumerate() {
-            if instance_vec[idx] != instance.clone() {
+            if instance_vec.get(idx) != Some(instance) {
               needs_buf_update = true;
               break;
             }
@@ -264,24 +553,46 @@ impl ModelRenderer {
         }
       }
     }
+    // the facing rotation overrides whatever the transform (and its parents)
+    // produced, so changes are judged on the final instances
+    if let Some(mode) = transform.billboard {
+      let rot = billboard_rotation(mode, camera_forward);
+      for instance in instance_vec.iter_mut() {
+        instance.rotation = rot;
+      }
+      global_rot = rot;
+      needs_buf_update = opacity != transform.opacity
+        || tint != transform.tint
+        || instanced != previous_instanced
+        || instance_vec != previous_instances;
+    }
     if needs_buf_update {
       let mut render_data = self.models.remove(&model).unwrap();
       render_data.instanced = instanced;
       render_data.global_pos = global_pos;
       render_data.global_rot = global_rot;
       render_data.instances = instance_vec;
+      render_data.opacity = transform.opacity;
+      render_data.tint = transform.tint;
       println!("updated render data -> global pos: {:?}, rotation: {:?}, instances: {:?}", render_data.global_pos, render_data.global_rot, render_data.instances);
-      let instance_data = render_data.instances
-        .iter()
-        .map(Instance::to_raw)
-        .collect::<Vec<InstanceRaw>>();
-
-      queue.write_buffer(&render_data.instance_buf, 0, bytemuck::cast_slice(&instance_data));
+      render_data.write_instances(device);
       self.models.insert(model.clone(), render_data);
     }
     Ok(())
   }
 
+  // flags every model owned by the component as selected and clears the
+  // flag everywhere else
+  pub fn set_selected_component(&mut self, selected: Option<ComponentKey>, device: &wgpu::Device) {
+    for (rm, render_data) in self.models.iter_mut() {
+      let is_selected = Some(rm.component) == selected;
+      if render_data.selected != is_selected {
+        render_data.selected = is_selected;
+        render_data.write_instances(device);
+      }
+    }
+  }
+
   pub fn render_from_cache(&mut self, model: &RenderableModel) -> Result<(), EngineError> {
     if !self.models.contains_key(model) {
       return Err(EngineError::ArgumentError { index: 1, name: "model".into() })
@@ -294,31 +605,278 @@ impl ModelRenderer {
     &mut self, 
     model: &RenderableModel, 
     transform: ModelTransform, 
-    queue: &wgpu::Queue,
-    device: &wgpu::Device
+    device: &wgpu::Device,
+    camera_forward: Vector3<f32>,
   ) -> Result<(), EngineError> {
     if !self.models.contains_key(model) {
       return Err(EngineError::ArgumentError { index: 1, name: "model".into() })
     }
-    let res = self.update_render_model(model, transform.clone(), queue, device);
+    let mut res = self.update_render_model(model, transform.clone(), device, camera_forward);
+    // every tier follows the model so whichever is picked is up to date
+    for (tier, _) in self.lod_groups.get(model).cloned().unwrap_or(Vec::new()) {
+      if tier != *model {
+        res = res.and(self.update_render_model(&tier, transform.clone(), device, camera_forward));
+      }
+    }
     self.render_list.push(model.clone());
     res
   }
 
+  // draws one of tiers in place of model depending on the camera's distance
+  // to the model's instances: the first tier whose max distance covers it,
+  // or the last (coarsest) one beyond every threshold. tiers can include
+  // model itself. the shadow pass always uses model
+  pub fn register_lod(&mut self, model: &RenderableModel, tiers: Vec<(RenderableModel, f32)>) -> Result<(), EngineError> {
+    if !self.models.contains_key(model) {
+      return Err(EngineError::ArgumentError { index: 1, name: "model".into() })
+    }
+    if tiers.len() == 0 || tiers.iter().any(|(tier, _)| !self.models.contains_key(tier)) {
+      return Err(EngineError::ArgumentError { index: 2, name: "tiers".into() })
+    }
+    let mut tiers = tiers;
+    tiers.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    self.lod_groups.insert(model.clone(), tiers);
+    Ok(())
+  }
+
+  pub fn unregister_lod(&mut self, model: &RenderableModel) {
+    self.lod_groups.remove(model);
+  }
+
+  // the tier drawn for model from camera_pos (model itself without lods)
+  pub fn select_lod(&self, model: &RenderableModel, camera_pos: Point3<f32>) -> RenderableModel {
+    let (tiers, rd) = match (self.lod_groups.get(model), self.models.get(model)) {
+      (Some(tiers), Some(rd)) => (tiers, rd),
+      _ => return model.clone()
+    };
+    let cam = Vector3::new(camera_pos.x, camera_pos.y, camera_pos.z);
+    let dist = (instance_centroid(&rd.instances) - cam).magnitude();
+    tiers.iter()
+      .find(|(_, max_dist)| dist <= *max_dist)
+      .or(tiers.last())
+      .map(|(tier, _)| tier.clone())
+      .unwrap_or(model.clone())
+  }
+
   pub fn clear(&mut self) {
-    self.render_list.clear()
+    self.render_list.clear();
+    self.draw_list.clear();
+  }
+
+  // models marked for rendering this frame, once each, in load order.
+  // the render list itself follows component traversal, which isn't
+  // guaranteed to be the same every frame
+  fn ordered_render_list(&self) -> Vec<RenderableModel> {
+    let mut ordered = self.render_list.clone();
+    ordered.sort_by_key(|rm| rm.index);
+    ordered.dedup();
+    ordered
+  }
+
+  // every model marked for rendering this frame with its own instance
+  // buffer, instance count and mesh filter, unbatched and unculled (used by
+  // the shadow pass)
+  pub fn get_rendering_models(&self) -> Vec<(&Model, &wgpu::Buffer, u32, Option<&[usize]>)> {
+    self.ordered_render_list().iter()
+      .filter_map(|rm| self.models.get(rm))
+      .map(|rd| (&rd.model, &rd.instance_buf, rd.instances.len() as u32, rd.mesh_filter.as_deref()))
+      .collect::<Vec<(&Model, &wgpu::Buffer, u32, Option<&[usize]>)>>()
+  }
+
+  // builds this frame's draw list. opaque models loaded from the same file
+  // are merged into one instance buffer so they draw with a single call.
+  // transparent models stay separate so they can be sorted back to front,
+  // retextured and mesh filtered models are drawn on their own,
+  // and models entirely outside the frustum are skipped
+  pub fn prepare_draw_list(
+    &mut self,
+    device: &wgpu::Device,
+    camera_pos: Point3<f32>,
+    frustum: Option<&Frustum>,
+  ) {
+    let mut visible: Vec<RenderableModel> = Vec::new();
+    for rm in self.ordered_render_list().iter() {
+      let rm = &self.select_lod(rm, camera_pos);
+      if visible.contains(rm) {
+        continue;
+      }
+      if let Some(rd) = self.models.get(rm) {
+        let in_view = match frustum {
+          Some(f) => rd.instances.iter().any(|i| {
+            // the bounding sphere grows with the instance's largest scale axis
+            let scale = i.scale.x.abs().max(i.scale.y.abs()).max(i.scale.z.abs());
+            f.intersects_sphere(Point3::new(i.position.x, i.position.y, i.position.z), rd.model.bounding_radius * scale)
+          }),
+          None => true
+        };
+        if in_view {
+          visible.push(rm.clone());
+        }
+      }
+    }
+
+    // group opaque models by source file, keeping first-seen order
+    let mut groups: Vec<(String, Vec<RenderableModel>)> = Vec::new();
+    let mut draw_calls: Vec<DrawCall> = Vec::new();
+    let mut sort_keys: Vec<(Vector3<f32>, f32)> = Vec::new();
+    for rm in visible {
+      let rd = self.models.get(&rm).unwrap();
+      if rd.opacity < 1. || rd.retextured || rd.mesh_filter.is_some() {
+        sort_keys.push((instance_centroid(&rd.instances), rd.opacity));
+        draw_calls.push(DrawCall::Single(rm));
+        continue;
+      }
+      match groups.iter_mut().find(|(filename, _)| *filename == rm.filename) {
+        Some((_, group)) => group.push(rm),
+        None => groups.push((rm.filename.clone(), vec![rm]))
+      }
+    }
+
+    for (filename, group) in groups {
+      let instances = group.iter()
+        .flat_map(|rm| self.models.get(rm).unwrap().instances.clone())
+        .collect::<Vec<Instance>>();
+      let instance_data = group.iter()
+        .flat_map(|rm| self.models.get(rm).unwrap().raw_instances())
+        .collect::<Vec<InstanceRaw>>();
+      sort_keys.push((instance_centroid(&instances), 1.));
+      if group.len() == 1 {
+        draw_calls.push(DrawCall::Single(group[0].clone()));
+        continue;
+      }
+
+      let needs_alloc = self.batch_buffers.get(&filename)
+        .map(|(_, capacity)| *capacity < instance_data.len())
+        .unwrap_or(true);
+      if needs_alloc {
+        let capacity = instance_data.len().next_power_of_two();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+          label: Some("Batch instance buffer"),
+          size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+          usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+          mapped_at_creation: false
+        });
+        self.batch_buffers.insert(filename.clone(), (buffer, capacity));
+      }
+      self.pending_batch_writes.push((filename.clone(), instance_data));
+      draw_calls.push(DrawCall::Batch {
+        model: group[0].clone(),
+        filename,
+        instance_count: instances.len() as u32
+      });
+    }
+
+    let order = get_draw_order(&sort_keys, camera_pos);
+    let mut ordered: Vec<Option<DrawCall>> = draw_calls.into_iter().map(Some).collect();
+    self.draw_list = order.iter()
+      .filter_map(|idx| ordered[*idx].take())
+      .collect::<Vec<DrawCall>>();
   }
 
-  pub fn get_rendering_models(&self) -> Vec<(&Model, &wgpu::Buffer)> {
-    self.render_list.iter()
-      .map(|rm| self.models.get(rm))
-      .filter(|rd| !rd.is_none())
-      .map(|rd| (&rd.unwrap().model,&rd.unwrap().instance_buf))
-      .into_iter()
-      .collect::<Vec<(&Model, &wgpu::Buffer)>>()
+  // (model, instance buffer, instance count, transparent, mesh filter) for
+  // each draw call prepared by prepare_draw_list, in draw order
+  pub fn get_draw_list(&self) -> Vec<(&Model, &wgpu::Buffer, u32, bool, Option<&[usize]>)> {
+    self.draw_list.iter()
+      .filter_map(|call| match call {
+        DrawCall::Single(rm) => self.models.get(rm)
+          .map(|rd| (&rd.model, &rd.instance_buf, rd.instances.len() as u32, rd.opacity < 1., rd.mesh_filter.as_deref())),
+        // only opaque, unfiltered models are batched
+        DrawCall::Batch { model, filename, instance_count } => {
+          match (self.models.get(model), self.batch_buffers.get(filename)) {
+            (Some(rd), Some((buffer, _))) => Some((&rd.model, buffer, *instance_count, false, None)),
+            _ => None
+          }
+        }
+      })
+      .collect::<Vec<(&Model, &wgpu::Buffer, u32, bool, Option<&[usize]>)>>()
+  }
+
+  // copies every instance update made since the last flush into its buffer
+  // through the staging belt, so a frame's uploads are recorded in the
+  // encoder rather than issued as separate queue writes. must run before the
+  // passes that read the buffers, and recall_writes after the encoder is
+  // submitted
+  pub fn flush_writes(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+    let mut count = 0;
+    for render_data in self.models.values_mut().filter(|rd| rd.dirty) {
+      render_data.dirty = false;
+      let instance_data = render_data.raw_instances();
+      if let Some(size) = NonZeroU64::new((instance_data.len() * std::mem::size_of::<InstanceRaw>()) as u64) {
+        self.staging_belt.write_buffer(encoder, &render_data.instance_buf, 0, size, device)
+          .copy_from_slice(bytemuck::cast_slice(&instance_data));
+        count += 1;
+      }
+    }
+    for (filename, instance_data) in self.pending_batch_writes.drain(..) {
+      let size = NonZeroU64::new((instance_data.len() * std::mem::size_of::<InstanceRaw>()) as u64);
+      if let (Some((buffer, _)), Some(size)) = (self.batch_buffers.get(&filename), size) {
+        self.staging_belt.write_buffer(encoder, buffer, 0, size, device)
+          .copy_from_slice(bytemuck::cast_slice(&instance_data));
+        count += 1;
+      }
+    }
+    self.staging_belt.finish();
+    self.last_write_count = count;
+  }
+
+  // frees the belt's staging chunks once the flushed encoder has been submitted
+  pub fn recall_writes(&mut self) {
+    self.staging_belt.recall();
+  }
+
+  // number of instance buffers written by the last flush
+  pub fn last_write_count(&self) -> usize {
+    self.last_write_count
+  }
+
+  // how many component world matrices have been rebuilt so far. static
+  // hierarchies stop adding to this after their first frame
+  pub fn transform_recompute_count(&self) -> usize {
+    self.transform_queue.recomputed_count()
   }
 
   pub fn get_position_cache(&self) -> &HashMap<ComponentKey, Matrix4<f32>> {
     &self.component_transform_cache
   }
 }
+
+fn instance_centroid(instances: &Vec<Instance>) -> Vector3<f32> {
+  if instances.len() == 0 {
+    return Vector3::new(0., 0., 0.);
+  }
+  let sum = instances.iter().fold(Vector3::new(0., 0., 0.), |acc, i| acc + i.position);
+  sum / instances.len() as f32
+}
+
+// rotation turning a model's local +z back toward the camera, keeping its
+// +y as close to world up as the mode allows
+pub fn billboard_rotation(mode: BillboardMode, camera_forward: Vector3<f32>) -> Quaternion<f32> {
+  let mut facing = -camera_forward;
+  if mode == BillboardMode::Cylindrical {
+    facing.y = 0.;
+  }
+  if facing.magnitude2() == 0. {
+    return Quaternion::new(1., 0., 0., 0.);
+  }
+  let z = facing.normalize();
+  let mut x = Vector3::unit_y().cross(z);
+  if x.magnitude2() < 1e-8 {
+    // looking straight up or down, any horizontal axis will do
+    x = Vector3::unit_x();
+  }
+  let x = x.normalize();
+  let y = z.cross(x);
+  Quaternion::from(Matrix3::from_cols(x, y, z))
+}
+
+// takes (centroid, opacity) pairs and returns their indices in draw order
+pub fn get_draw_order(models: &Vec<(Vector3<f32>, f32)>, camera_pos: Point3<f32>) -> Vec<usize> {
+  let cam = Vector3::new(camera_pos.x, camera_pos.y, camera_pos.z);
+  let dist = |idx: &usize| (models[*idx].0 - cam).magnitude2();
+  let (mut opaque, mut transparent): (Vec<usize>, Vec<usize>) = (0..models.len())
+    .partition(|idx| models[*idx].1 >= 1.);
+  opaque.sort_by(|a, b| dist(a).partial_cmp(&dist(b)).unwrap_or(std::cmp::Ordering::Equal));
+  transparent.sort_by(|a, b| dist(b).partial_cmp(&dist(a)).unwrap_or(std::cmp::Ordering::Equal));
+  opaque.append(&mut transparent);
+  opaque
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use cgmath::InnerSpace;
+use instant::{Duration, Instant};
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::{ElementState, MouseButton}};
+
+use crate::graphics::{Camera, Projection};
+
+use super::{collisions::CollisionManager, component_store::ComponentKey, events::{Event, EventData, EventKey, EventManager}, raycasting::{Ray, RaycastHit, RaycastManager}};
+
+const DEFAULT_DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+const DEFAULT_DRAG_THRESHOLD: f64 = 5.;
+
+#[derive(Clone, Copy)]
+struct ButtonPress {
+  time: Instant,
+  pos: (f64, f64),
+  dragging: bool,
+}
+
+// Tracks the cursor position and which mouse buttons are currently held.
+// Button presses are forwarded to the event manager as MouseSelectEvents,
+// and press/move/release sequences are turned into double-click and drag
+// gestures
+pub struct Mouse {
+  pub position: PhysicalPosition<f64>,
+  pub double_click_interval: Duration,
+  pub drag_threshold: f64, // in pixels
+  // when set, picking passes through components whose models are all less
+  // opaque than this, selecting whatever is visible behind them
+  pub pass_through_opacity: Option<f32>,
+  pressed_buttons: HashMap<MouseButton, ButtonPress>,
+  last_press: Option<(MouseButton, ButtonPress)>,
+}
+
+impl Mouse {
+  pub fn new() -> Mouse {
+    Self {
+      position: PhysicalPosition::new(0., 0.),
+      double_click_interval: DEFAULT_DOUBLE_CLICK_INTERVAL,
+      drag_threshold: DEFAULT_DRAG_THRESHOLD,
+      pass_through_opacity: None,
+      pressed_buttons: HashMap::new(),
+      last_press: None,
+    }
+  }
+
+  pub fn is_pressed(&self, button: MouseButton) -> bool {
+    self.pressed_buttons.contains_key(&button)
+  }
+
+  pub fn is_dragging(&self, button: MouseButton) -> bool {
+    self.pressed_buttons.get(&button).map(|p| p.dragging).unwrap_or(false)
+  }
+
+  // world space ray from the camera through the cursor
+  pub fn cursor_ray(&self, camera: &Camera, projection: &Projection, size: PhysicalSize<u32>) -> Ray {
+    screen_to_ray((self.position.x, self.position.y), camera, projection, size)
+  }
+
+  // cursor ray clipped to the near and far planes (see screen_to_segment)
+  pub fn cursor_segment(&self, camera: &Camera, projection: &Projection, size: PhysicalSize<u32>) -> (Ray, f32) {
+    screen_to_segment((self.position.x, self.position.y), camera, projection, size)
+  }
+
+  // nearest collider hit along the ray, if any. opacity gives the opacity
+  // of a component's models and is only used for pass_through_opacity
+  pub fn intersect_colliders<F>(&self, ray: &Ray, max_dist: f32, collision_manager: &CollisionManager, opacity: F) -> Option<RaycastHit>
+  where F: Fn(&ComponentKey) -> f32 {
+    match self.pass_through_opacity {
+      Some(threshold) => RaycastManager::cast_first_filtered(ray, max_dist, None, collision_manager, |collider| {
+        opacity(&collider.parent) >= threshold
+      }),
+      None => RaycastManager::cast_first(ray, max_dist, None, collision_manager)
+    }
+  }
+
+  pub fn update_position(&mut self, position: PhysicalPosition<f64>, event_manager: &mut EventManager) {
+    self.position = position;
+    let pos = (position.x, position.y);
+    for (button, press) in self.pressed_buttons.iter_mut() {
+      if press.dragging || distance(press.pos, pos) <= self.drag_threshold {
+        continue;
+      }
+      press.dragging = true;
+      // a press that turned into a drag can't be the first half of a double click
+      if self.last_press.map(|(last_button, _)| last_button == *button).unwrap_or(false) {
+        self.last_press = None;
+      }
+      event_manager.handle_event(Event {
+        key: EventKey::MouseDragStartEvent,
+        data: EventData::MouseDragStartEvent {
+          button: button.clone(),
+          start: press.pos
+        }
+      });
+    }
+  }
+
+  // returns false for buttons the engine doesn't track (Other(u16))
+  pub fn handle_button(&mut self, button: MouseButton, state: ElementState, event_manager: &mut EventManager) -> bool {
+    self.handle_button_at(button, state, Instant::now(), event_manager)
+  }
+
+  pub fn handle_button_at(&mut self, button: MouseButton, state: ElementState, time: Instant, event_manager: &mut EventManager) -> bool {
+    match button {
+      MouseButton::Left | MouseButton::Right | MouseButton::Middle => (),
+      _ => return false
+    }
+
+    let pos = (self.position.x, self.position.y);
+    if state == ElementState::Released {
+      if let Some(press) = self.pressed_buttons.remove(&button) {
+        if press.dragging {
+          event_manager.handle_event(Event {
+            key: EventKey::MouseDragEndEvent,
+            data: EventData::MouseDragEndEvent {
+              button,
+              start: press.pos,
+              end: pos
+            }
+          });
+        }
+      }
+      return true;
+    }
+
+    let press = ButtonPress {
+      time,
+      pos,
+      dragging: false
+    };
+    self.pressed_buttons.insert(button, press);
+    event_manager.handle_event(Event {
+      key: EventKey::MouseSelectEvent,
+      data: EventData::MouseSelectEvent {
+        button,
+        pos
+      }
+    });
+
+    // a second press of the same button within double_click_interval and
+    // drag_threshold of the first is a double click, so quick clicks on two
+    // different spots don't count. the pair is consumed so a third press
+    // starts a new sequence
+    if let Some((last_button, last)) = self.last_press {
+      if last_button == button
+        && time.duration_since(last.time) <= self.double_click_interval
+        && distance(last.pos, pos) <= self.drag_threshold {
+        event_manager.handle_event(Event {
+          key: EventKey::MouseDoubleClickEvent,
+          data: EventData::MouseDoubleClickEvent {
+            button,
+            pos
+          }
+        });
+        self.last_press = None;
+        return true;
+      }
+    }
+    self.last_press = Some((button, press));
+    true
+  }
+}
+
+// world space ray from the camera through a screen position (pixels from the top left)
+pub fn screen_to_ray(screen_pos: (f64, f64), camera: &Camera, projection: &Projection, size: PhysicalSize<u32>) -> Ray {
+  let (near, far) = camera.unproject(screen_to_ndc(screen_pos, size), projection);
+  Ray::new(camera.position, far - near)
+}
+
+// the part of the screen ray between the near and far clip planes, as a
+// ray starting on the near plane and its length to the far plane. picking
+// with this only finds what the camera can actually draw
+pub fn screen_to_segment(screen_pos: (f64, f64), camera: &Camera, projection: &Projection, size: PhysicalSize<u32>) -> (Ray, f32) {
+  let (near, far) = camera.unproject(screen_to_ndc(screen_pos, size), projection);
+  (Ray::new(near, far - near), (far - near).magnitude())
+}
+
+// winit's origin is the top left corner, so y is flipped into ndc
+fn screen_to_ndc(screen_pos: (f64, f64), size: PhysicalSize<u32>) -> (f32, f32) {
+  (
+    2. * screen_pos.0 as f32 / size.width.max(1) as f32 - 1.,
+    1. - 2. * screen_pos.1 as f32 / size.height.max(1) as f32
+  )
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+  ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::{Point3, Vector3};
+  use crate::engine::test_util::{record_event, recorder_store, sphere_boundary};
+
+  #[test]
+  fn picking_selects_the_nearest_collider() {
+    let (_, recorders) = recorder_store(2);
+    let (near, far) = (recorders[0].0, recorders[1].0);
+    let mut collision_manager = CollisionManager::new();
+    // the far one is added first so insertion order can't pick the answer
+    collision_manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -10.), 1.), far, None, false);
+    collision_manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -5.), 1.), near, None, false);
+
+    let ray = Ray::new(Point3::new(0., 0., 0.), -Vector3::unit_z());
+    let hit = Mouse::new().intersect_colliders(&ray, 100., &collision_manager, |_| 1.).unwrap();
+    assert_eq!(hit.component, near);
+    assert!((hit.distance - 4.).abs() < 1e-2);
+  }
+
+  #[test]
+  fn right_press_over_a_collider_selects_with_the_right_button() {
+    let (mut components, recorders) = recorder_store(1);
+    let (key, recorder) = &recorders[0];
+    let mut collision_manager = CollisionManager::new();
+    collision_manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -5.), 1.), *key, None, false);
+    let mut event_manager = EventManager::new();
+    event_manager.add_listener(*key, EventKey::MouseSelectEvent, record_event).unwrap();
+
+    let mut mouse = Mouse::new();
+    let ray = Ray::new(Point3::new(0., 0., 0.), -Vector3::unit_z());
+    let hit = mouse.intersect_colliders(&ray, 100., &collision_manager, |_| 1.);
+    assert_eq!(hit.map(|h| h.component), Some(*key));
+
+    assert!(mouse.handle_button_at(MouseButton::Right, ElementState::Pressed, Instant::now(), &mut event_manager));
+    assert!(mouse.is_pressed(MouseButton::Right));
+    assert!(!mouse.is_pressed(MouseButton::Left));
+    event_manager.trigger_callbacks(&mut components);
+    let events = &recorder.lock().unwrap().events;
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0].data, EventData::MouseSelectEvent { button: MouseButton::Right, .. }));
+  }
+
+  #[test]
+  fn a_click_is_not_a_drag_until_it_moves_past_the_threshold() {
+    let mut event_manager = EventManager::new();
+    let mut mouse = Mouse::new();
+    mouse.handle_button_at(MouseButton::Left, ElementState::Pressed, Instant::now(), &mut event_manager);
+    mouse.update_position(PhysicalPosition::new(2., 2.), &mut event_manager);
+    // still a click, so releasing here selects
+    assert!(mouse.is_pressed(MouseButton::Left) && !mouse.is_dragging(MouseButton::Left));
+
+    mouse.update_position(PhysicalPosition::new(40., 0.), &mut event_manager);
+    // a camera drag, so releasing here keeps the selection
+    assert!(mouse.is_dragging(MouseButton::Left));
+    mouse.handle_button_at(MouseButton::Left, ElementState::Released, Instant::now(), &mut event_manager);
+    assert!(!mouse.is_pressed(MouseButton::Left) && !mouse.is_dragging(MouseButton::Left));
+  }
+
+  // 1600x900 window, camera at the origin looking down -z with a 90 degree
+  // vertical fov, so the top edge is at 45 degrees
+  fn wide_view() -> (Camera, Projection, PhysicalSize<u32>) {
+    let camera = Camera::new((0., 0., 0.), cgmath::Deg(-90.), cgmath::Deg(0.));
+    let projection = Projection::new(1600, 900, cgmath::Deg(90.), 0.1, 100.);
+    (camera, projection, PhysicalSize::new(1600, 900))
+  }
+
+  fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a.normalize() - b.normalize()).magnitude() < 1e-3
+  }
+
+  #[test]
+  fn screen_positions_map_to_ndc_with_y_up() {
+    let size = PhysicalSize::new(1600, 900);
+    assert_eq!(screen_to_ndc((800., 450.), size), (0., 0.));
+    assert_eq!(screen_to_ndc((0., 0.), size), (-1., 1.));
+    assert_eq!(screen_to_ndc((1600., 900.), size), (1., -1.));
+  }
+
+  #[test]
+  fn center_click_goes_through_the_view_center() {
+    let (camera, projection, size) = wide_view();
+    let ray = screen_to_ray((800., 450.), &camera, &projection, size);
+    assert!(close(ray.dir, -Vector3::unit_z()));
+  }
+
+  #[test]
+  fn corner_clicks_point_towards_their_corner() {
+    let (camera, projection, size) = wide_view();
+    let aspect = 1600. / 900.;
+    // top left, then bottom right
+    let ray = screen_to_ray((0., 0.), &camera, &projection, size);
+    assert!(close(ray.dir, Vector3::new(-aspect, 1., -1.)));
+    let ray = screen_to_ray((1600., 900.), &camera, &projection, size);
+    assert!(close(ray.dir, Vector3::new(aspect, -1., -1.)));
+  }
+
+  #[test]
+  fn picking_passes_through_transparent_colliders() {
+    let (_, recorders) = recorder_store(2);
+    let (glass, wall) = (recorders[0].0, recorders[1].0);
+    let mut collision_manager = CollisionManager::new();
+    collision_manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -5.), 1.), glass, None, false);
+    collision_manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -10.), 1.), wall, None, false);
+    let opacity = |key: &ComponentKey| if *key == glass { 0.2 } else { 1. };
+    let ray = Ray::new(Point3::new(0., 0., 0.), -Vector3::unit_z());
+
+    let mut mouse = Mouse::new();
+    assert_eq!(mouse.intersect_colliders(&ray, 100., &collision_manager, opacity).map(|h| h.component), Some(glass));
+    mouse.pass_through_opacity = Some(0.5);
+    let hit = mouse.intersect_colliders(&ray, 100., &collision_manager, opacity).unwrap();
+    assert_eq!(hit.component, wall);
+    assert!((hit.distance - 9.).abs() < 1e-2);
+  }
+}
@@ -0,0 +1,5 @@
+mod ray;
+mod raycast_manager;
+
+pub use ray::{Ray, TraceResult};
+pub use raycast_manager::{Raycast, RaycastHit, RaycastManager};
@@ -0,0 +1,68 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::sdf::SdfShape;
+
+pub const MAX_TRACE_ITERS: usize = 128;
+pub const HIT_TOL: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+  pub origin: Point3<f32>,
+  pub dir: Vector3<f32>, // always normalized
+}
+
+// result of marching a ray. converged is false only when the trace ran
+// out of iterations, so callers can tell a clean miss (ran past max_dist)
+// from a trace that gave up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceResult {
+  pub hit: Option<Point3<f32>>,
+  pub distance: f32,
+  pub iterations: usize,
+  pub converged: bool,
+}
+
+impl Ray {
+  pub fn new(origin: Point3<f32>, dir: Vector3<f32>) -> Ray {
+    Self {
+      origin,
+      dir: dir.normalize()
+    }
+  }
+
+  pub fn at(&self, t: f32) -> Point3<f32> {
+    self.origin + self.dir * t
+  }
+
+  pub fn sphere_trace(&self, sdf: &SdfShape, max_dist: f32) -> TraceResult {
+    self.march(|p| sdf.dist(p), max_dist)
+  }
+
+  // steps along the ray by the distance bound returned from dist_fn until
+  // the bound drops below HIT_TOL or the ray passes max_dist
+  pub fn march<F>(&self, dist_fn: F, max_dist: f32) -> TraceResult
+    where F: Fn(Point3<f32>) -> f32 {
+    let mut t: f32 = 0.;
+    let mut iterations: usize = 0;
+    while iterations < MAX_TRACE_ITERS && t <= max_dist {
+      let p = self.at(t);
+      let dist = dist_fn(p);
+      iterations += 1;
+      if dist.abs() < HIT_TOL {
+        return TraceResult {
+          hit: Some(p),
+          distance: t,
+          iterations,
+          converged: true
+        }
+      }
+      t += dist.abs();
+    }
+    TraceResult {
+      hit: None,
+      distance: t,
+      iterations,
+      converged: t > max_dist
+    }
+  }
+}
@@ -0,0 +1,337 @@
+use std::{collections::HashMap, fmt};
+
+use cgmath::{Matrix4, MetricSpace, Point3, Transform};
+
+use crate::{engine::{collisions::{Collider, CollisionManager}, component_store::ComponentKey, errors::EngineError}, sdf::SdfShape};
+
+use super::ray::{Ray, TraceResult};
+
+#[derive(Clone, Copy, Debug)]
+pub struct RaycastHit {
+  pub component: ComponentKey,
+  pub collider: u32,
+  pub loc: Point3<f32>,
+  pub distance: f32,
+}
+
+impl RaycastHit {
+  // same component and collider, with loc and distance within eps
+  pub fn approx_eq(&self, other: &RaycastHit, eps: f32) -> bool {
+    self.component == other.component
+      && self.collider == other.collider
+      && self.loc.distance(other.loc) <= eps
+      && (self.distance - other.distance).abs() <= eps
+  }
+}
+
+impl fmt::Display for RaycastHit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f, "Hit collider {} of component {}:{} at ({:.3}, {:.3}, {:.3}), distance {:.3}",
+      self.collider, self.component.index, self.component.generation, self.loc.x, self.loc.y, self.loc.z, self.distance
+    )
+  }
+}
+
+pub struct Raycast {
+  pub id: u32,
+  pub ray: Ray,
+  pub owner: ComponentKey,
+  pub max_dist: f32,
+  pub only_first: bool, // only keep the nearest hit
+  local_ray: Option<Ray>, // set when the ray follows its owner's transform
+  hits: Vec<RaycastHit>,
+}
+
+impl Raycast {
+  // hits from the last intersection pass, nearest first
+  pub fn get_hits(&self) -> &Vec<RaycastHit> {
+    &self.hits
+  }
+
+  pub fn sorted_intersections(&self) -> Vec<RaycastHit> {
+    let mut hits = self.hits.clone();
+    hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal));
+    hits
+  }
+
+  pub fn first_hit(&self) -> Option<&RaycastHit> {
+    self.hits.iter()
+      .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal))
+  }
+
+  pub fn is_following(&self) -> bool {
+    self.local_ray.is_some()
+  }
+}
+
+// Stores rays owned by components and intersects them against every
+// registered collider once per frame (after collider positions are
+// updated). Colliders belonging to the ray's owner are ignored
+pub struct RaycastManager {
+  raycasts: HashMap<u32, Raycast>,
+  next_id: u32,
+}
+
+impl RaycastManager {
+  pub fn new() -> RaycastManager {
+    Self {
+      raycasts: HashMap::new(),
+      next_id: 0
+    }
+  }
+
+  pub fn raycast_count(&self) -> usize {
+    self.raycasts.len()
+  }
+
+  pub fn new_raycast(&mut self, ray: Ray, owner: ComponentKey, max_dist: f32) -> u32 {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.raycasts.insert(id, Raycast {
+      id,
+      ray,
+      owner,
+      max_dist,
+      only_first: false,
+      local_ray: None,
+      hits: Vec::new()
+    });
+    id
+  }
+
+  // ray given in the owner's local space. its world space origin and
+  // direction are refreshed from the owner's transform every frame
+  pub fn new_following_raycast(&mut self, local_ray: Ray, owner: ComponentKey, max_dist: f32) -> u32 {
+    let id = self.new_raycast(local_ray, owner, max_dist);
+    self.raycasts.get_mut(&id).unwrap().local_ray = Some(local_ray);
+    id
+  }
+
+  // replaces the ray. for following raycasts the new ray is in local space
+  pub fn update_raycast(&mut self, id: u32, new_ray: Ray) -> Result<(), EngineError> {
+    if let Some(raycast) = self.raycasts.get_mut(&id) {
+      if raycast.local_ray.is_some() {
+        raycast.local_ray = Some(new_ray);
+      } else {
+        raycast.ray = new_ray;
+      }
+      return Ok(())
+    }
+    Err(EngineError::ArgumentError { index: 0, name: "id".into() })
+  }
+
+  // switches a raycast between following its owner and staying fixed.
+  // the current world space ray is kept as the starting point either way
+  pub fn set_follow_component(&mut self, id: u32, follow: bool) -> Result<(), EngineError> {
+    if let Some(raycast) = self.raycasts.get_mut(&id) {
+      raycast.local_ray = if follow { Some(raycast.ray) } else { None };
+      return Ok(())
+    }
+    Err(EngineError::ArgumentError { index: 0, name: "id".into() })
+  }
+
+  pub fn set_only_first(&mut self, id: u32, only_first: bool) -> Result<(), EngineError> {
+    if let Some(raycast) = self.raycasts.get_mut(&id) {
+      raycast.only_first = only_first;
+      return Ok(())
+    }
+    Err(EngineError::ArgumentError { index: 0, name: "id".into() })
+  }
+
+  pub fn update_following_rays(&mut self, position_cache: &HashMap<ComponentKey, Matrix4<f32>>) {
+    for (_, raycast) in self.raycasts.iter_mut() {
+      if let (Some(local), Some(mat)) = (raycast.local_ray, position_cache.get(&raycast.owner)) {
+        raycast.ray = Ray::new(mat.transform_point(local.origin), mat.transform_vector(local.dir));
+      }
+    }
+  }
+
+  pub fn remove_raycast(&mut self, id: u32) -> Option<Raycast> {
+    self.raycasts.remove(&id)
+  }
+
+  pub fn remove_component_raycasts(&mut self, owner: &ComponentKey) {
+    self.raycasts.retain(|_, r| r.owner != *owner);
+  }
+
+  pub fn get_raycast(&self, id: u32) -> Option<&Raycast> {
+    self.raycasts.get(&id)
+  }
+
+  pub fn get_hits(&self, id: u32) -> Option<&Vec<RaycastHit>> {
+    self.raycasts.get(&id).map(|r| r.get_hits())
+  }
+
+  pub fn intersect_colliders(&mut self, collision_manager: &CollisionManager) {
+    for (_, raycast) in self.raycasts.iter_mut() {
+      raycast.hits = if raycast.only_first {
+        Self::cast_first(&raycast.ray, raycast.max_dist, Some(raycast.owner), collision_manager).into_iter().collect()
+      } else {
+        Self::cast(&raycast.ray, raycast.max_dist, Some(raycast.owner), collision_manager)
+      };
+    }
+  }
+
+  // one-off cast against all colliders that doesn't need to be stored
+  pub fn cast(ray: &Ray, max_dist: f32, ignore: Option<ComponentKey>, collision_manager: &CollisionManager) -> Vec<RaycastHit> {
+    let mut hits = Vec::new();
+    for collider in collision_manager.get_colliders() {
+      let collider = collider.read().unwrap();
+      if Some(collider.parent) == ignore || !collider.is_enabled() {
+        continue;
+      }
+      if let Some(distance) = collider.ray_intersect(ray.origin, ray.dir, max_dist) {
+        hits.push(RaycastHit {
+          component: collider.parent,
+          collider: collider.index,
+          loc: ray.at(distance),
+          distance
+        });
+      }
+    }
+    hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal));
+    hits
+  }
+
+  // nearest hit only. each hit shortens the distance later colliders are
+  // tested against, so distant geometry can be rejected early
+  pub fn cast_first(ray: &Ray, max_dist: f32, ignore: Option<ComponentKey>, collision_manager: &CollisionManager) -> Option<RaycastHit> {
+    Self::cast_first_filtered(ray, max_dist, ignore, collision_manager, |_| true)
+  }
+
+  // like cast_first, but the ray passes through colliders the filter rejects
+  pub fn cast_first_filtered<F>(ray: &Ray, max_dist: f32, ignore: Option<ComponentKey>, collision_manager: &CollisionManager, filter: F) -> Option<RaycastHit>
+  where F: Fn(&Collider) -> bool {
+    let mut nearest: Option<RaycastHit> = None;
+    for collider in collision_manager.get_colliders() {
+      let collider = collider.read().unwrap();
+      if Some(collider.parent) == ignore || !collider.is_enabled() || !filter(&collider) {
+        continue;
+      }
+      let limit = nearest.map(|h| h.distance).unwrap_or(max_dist);
+      if let Some(distance) = collider.ray_intersect(ray.origin, ray.dir, limit) {
+        if distance < limit || nearest.is_none() {
+          nearest = Some(RaycastHit {
+            component: collider.parent,
+            collider: collider.index,
+            loc: ray.at(distance),
+            distance
+          });
+        }
+      }
+    }
+    nearest
+  }
+
+  // probe arbitrary sdf geometry that isn't registered as a collider
+  pub fn cast_against_sdf(&self, ray: &Ray, sdf: &SdfShape, max_dist: f32) -> TraceResult {
+    ray.sphere_trace(sdf, max_dist)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::Vector3;
+  use crate::engine::{collisions::SdfBoundary, test_util::sphere_boundary};
+  use crate::graphics::identity_rotation;
+  use crate::sdf::{CubeSdf, Shape};
+
+  fn key(index: u32) -> ComponentKey {
+    ComponentKey { index, generation: 0 }
+  }
+
+  // unit spheres at z = -5, -10 and -15, registered out of order
+  fn spheres_along_z() -> CollisionManager {
+    let mut manager = CollisionManager::new();
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -10.), 1.), key(2), None, false);
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -15.), 1.), key(3), None, false);
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 0., -5.), 1.), key(1), None, false);
+    manager
+  }
+
+  fn down_z() -> Ray {
+    Ray::new(Point3::new(0., 0., 0.), Vector3::new(0., 0., -1.))
+  }
+
+  #[test]
+  fn intersections_are_sorted_near_to_far() {
+    let manager = spheres_along_z();
+    let mut raycasts = RaycastManager::new();
+    let id = raycasts.new_raycast(down_z(), key(0), 100.);
+    raycasts.intersect_colliders(&manager);
+
+    let raycast = raycasts.get_raycast(id).unwrap();
+    let order: Vec<ComponentKey> = raycast.sorted_intersections().iter().map(|h| h.component).collect();
+    assert_eq!(order, vec![key(1), key(2), key(3)]);
+
+    let first = raycast.first_hit().unwrap();
+    assert_eq!(first.component, key(1));
+    assert!((first.distance - 4.).abs() < 1e-2);
+  }
+
+  #[test]
+  fn cast_first_returns_the_nearest_hit() {
+    let manager = spheres_along_z();
+    let hit = RaycastManager::cast_first(&down_z(), 100., None, &manager).unwrap();
+    assert_eq!(hit.component, key(1));
+
+    // the owner's own colliders are skipped
+    let hit = RaycastManager::cast_first(&down_z(), 100., Some(key(1)), &manager).unwrap();
+    assert_eq!(hit.component, key(2));
+  }
+
+  #[test]
+  fn only_first_keeps_a_single_hit() {
+    let manager = spheres_along_z();
+    let mut raycasts = RaycastManager::new();
+    let id = raycasts.new_raycast(down_z(), key(0), 100.);
+    raycasts.set_only_first(id, true).unwrap();
+    raycasts.intersect_colliders(&manager);
+
+    let hits = raycasts.get_hits(id).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].component, key(1));
+  }
+
+  #[test]
+  fn max_dist_cuts_off_far_hits() {
+    let manager = spheres_along_z();
+    let hits = RaycastManager::cast(&down_z(), 12., None, &manager);
+    let order: Vec<ComponentKey> = hits.iter().map(|h| h.component).collect();
+    assert_eq!(order, vec![key(1), key(2)]);
+  }
+
+  #[test]
+  fn falling_components_see_the_static_floor() {
+    let mut manager = CollisionManager::new();
+    // top face at y = 0
+    let floor = SdfShape::new(Shape::Cube { center: Point3::new(0., -1., 0.), half_bounds: Vector3::new(10., 1., 10.) }, CubeSdf).unwrap();
+    manager.add_static_collider(SdfBoundary::from_shape(floor), Vector3::new(0., 0., 0.), identity_rotation());
+    // the falling component's own collider is skipped by its ray
+    manager.add_component_collider(sphere_boundary(Point3::new(0., 5., 0.), 1.), key(1), None, false);
+
+    let mut raycasts = RaycastManager::new();
+    let id = raycasts.new_raycast(Ray::new(Point3::new(0., 5., 0.), Vector3::new(0., -1., 0.)), key(1), 100.);
+    raycasts.intersect_colliders(&manager);
+
+    let hit = raycasts.get_raycast(id).unwrap().first_hit().cloned().unwrap();
+    assert_eq!(hit.component, ComponentKey::world());
+    assert!((hit.distance - 5.).abs() < 1e-2);
+    assert!(hit.loc.y.abs() < 1e-2);
+  }
+
+  #[test]
+  fn hits_compare_within_a_tolerance() {
+    let hit = RaycastHit { component: key(1), collider: 0, loc: Point3::new(0., 0., -4.), distance: 4. };
+    let mut other = hit;
+    other.loc.z += 1e-4;
+    other.distance -= 1e-4;
+    assert!(hit.approx_eq(&other, 1e-3));
+    assert!(!hit.approx_eq(&other, 1e-5));
+    other.collider = 1;
+    assert!(!hit.approx_eq(&other, 1e-3));
+    assert_eq!(format!("{}", hit), "Hit collider 0 of component 1:0 at (0.000, 0.000, -4.000), distance 4.000");
+  }
+}
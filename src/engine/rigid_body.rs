@@ -0,0 +1,90 @@
+use cgmath::Vector3;
+
+use super::transforms::ModelTransform;
+
+pub const DEFAULT_GRAVITY: Vector3<f32> = Vector3::new(0., -9.81, 0.);
+
+// Minimal point-mass motion a component can embed and step from its own
+// update/fixed_update. This isn't a physics engine: there's no rotation and
+// no response to collisions beyond what the component does itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RigidBody {
+  pub velocity: Vector3<f32>,
+  pub acceleration: Vector3<f32>, // applied on top of gravity
+  pub use_gravity: bool,
+}
+
+impl RigidBody {
+  pub fn new() -> RigidBody {
+    Self {
+      velocity: Vector3::new(0., 0., 0.),
+      acceleration: Vector3::new(0., 0., 0.),
+      use_gravity: true,
+    }
+  }
+
+  pub fn apply_impulse(&mut self, delta_v: Vector3<f32>) {
+    self.velocity += delta_v;
+  }
+
+  // advances the transform's position by dt under constant acceleration.
+  // gravity is usually Scene::gravity()
+  pub fn integrate(&mut self, transform: &mut ModelTransform, gravity: Vector3<f32>, dt: instant::Duration) {
+    let dt = dt.as_secs_f32();
+    let mut accel = self.acceleration;
+    if self.use_gravity {
+      accel += gravity;
+    }
+    // exact for constant acceleration, so the step size doesn't change the path
+    let displacement = self.velocity * dt + accel * (0.5 * dt * dt);
+    self.velocity += accel * dt;
+
+    transform.pos += displacement;
+    for instance in transform.instances.iter_mut() {
+      instance.position += displacement;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::InnerSpace;
+  use crate::graphics::identity_rotation;
+
+  #[test]
+  fn falling_body_drops_half_g_t_squared() {
+    let mut body = RigidBody::new();
+    let mut transform = ModelTransform::global(Vector3::new(0., 10., 0.), identity_rotation());
+    body.integrate(&mut transform, DEFAULT_GRAVITY, instant::Duration::from_millis(500));
+
+    // 0.5 * 9.81 * 0.5^2
+    let expected = 10. - 0.5 * 9.81 * 0.25;
+    assert!((transform.pos.y - expected).abs() < 1e-4);
+    assert!((body.velocity.y + 9.81 * 0.5).abs() < 1e-4);
+  }
+
+  #[test]
+  fn step_size_does_not_change_the_path() {
+    let mut whole = RigidBody::new();
+    let mut whole_transform = ModelTransform::global(Vector3::new(0., 0., 0.), identity_rotation());
+    whole.apply_impulse(Vector3::new(1., 5., 0.));
+    let mut stepped = whole;
+    let mut stepped_transform = whole_transform.clone();
+
+    whole.integrate(&mut whole_transform, DEFAULT_GRAVITY, instant::Duration::from_millis(400));
+    for _ in 0..4 {
+      stepped.integrate(&mut stepped_transform, DEFAULT_GRAVITY, instant::Duration::from_millis(100));
+    }
+    assert!((whole_transform.pos - stepped_transform.pos).magnitude() < 1e-4);
+  }
+
+  #[test]
+  fn gravity_can_be_turned_off() {
+    let mut body = RigidBody::new();
+    body.use_gravity = false;
+    let mut transform = ModelTransform::global(Vector3::new(0., 2., 0.), identity_rotation());
+    body.integrate(&mut transform, DEFAULT_GRAVITY, instant::Duration::from_secs(1));
+    assert_eq!(transform.pos, Vector3::new(0., 2., 0.));
+  }
+}
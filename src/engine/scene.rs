@@ -1,46 +1,119 @@
-use std::sync::{Arc, Mutex};
+use std::{any::Any, collections::{HashMap, HashSet}, sync::{Arc, Mutex, RwLock}};
 
-use cgmath::Rotation3;
-use winit::{event::{ElementState, KeyboardInput, MouseButton, WindowEvent}, window::Window};
+use cgmath::{InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix, Vector3};
+use winit::{event::{ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent}, window::Window};
 use wgpu::{util::DeviceExt, BindGroupLayout};
 
-use crate::graphics::{get_light_bind_group_info, get_light_buffer, get_render_pipeline, Camera, CameraController, CameraUniform, DrawModel, Instance, InstanceRaw, LightUniform, Model, Projection, Texture};
+use crate::debug::DebugRenderer;
+use crate::sdf::SdfShape;
+use crate::graphics::{get_light_bind_group_info, get_light_buffer, get_render_pipeline, get_transparent_render_pipeline, Action, Camera, CameraController, CameraPose, CameraUniform, DrawModel, BindGroupLayoutCache, Frustum, Instance, InstanceRaw, LightUniform, Material, Model, Projection, ShadingMode, ShadowMap, Skybox, RectRenderer, TextRenderer, Texture};
 
-use super::{collisions::CollisionManager, component::Component, component_store::{ComponentKey, ComponentStore}, errors::EngineError, events::{Event, EventManager}, model_renderer::{ModelRenderer, RenderableModel}, state::{create_app_state, Store}, test_component::TestComponent, transforms::ModelTransform};
+#[cfg(feature = "gamepad")]
+use super::gamepad::Gamepads;
+use super::{animation::AnimationTrack, collisions::{update_collider_position, Collider, Collision, CollisionManager}, component::{AsyncCallbackHandler, Component, ComponentFunctions}, component_store::{ComponentKey, ComponentStore}, errors::EngineError, events::{Event, EventData, EventManager, ScheduledEventId}, model_renderer::{ModelLoadHandle, ModelRenderer, RenderableModel}, mouse::{screen_to_ray, screen_to_segment, Mouse}, raycasting::{Ray, RaycastHit, RaycastManager, TraceResult}, rigid_body::DEFAULT_GRAVITY, state::{create_app_state, State, Store, LIGHT_ROTATION_AXIS, LIGHT_ROTATION_SPEED}, test_component::TestComponent, transforms::{ComponentTransform, ModelTransform}};
+
+// most fixed steps run in one frame. past this the accumulated time is
+// dropped rather than letting a slow frame schedule ever more steps
+const MAX_FIXED_STEPS_PER_FRAME: u32 = 8;
+
+// what handle_surface_error did about a failed frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceRecovery {
+  Reconfigured, // the surface was configured again, the next frame should succeed
+  SkippedFrame, // transient, nothing to do but try again next frame
+  Fatal, // the event loop should exit
+}
+
+// set_active and destroy_component calls waiting for apply_pending_changes
+enum PendingChange {
+  SetActive(bool),
+  Destroy,
+}
+
+// in-progress lerp_camera_to
+struct CameraTransition {
+  from: CameraPose,
+  to: CameraPose,
+  duration: f32,
+  elapsed: f32,
+}
+
+// snapshot of what the scene is doing, shown by the debug stats overlay
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugStats {
+  pub components: usize,
+  pub colliders: usize,
+  pub colliding_pairs: usize,
+  pub raycasts: usize,
+  pub fps: f32, // smoothed over recent updates
+}
 
 // The Scene struct contains the data needed to render the wgpu scene
 // It manages the camera, lighting and i/o. It also handles the operation
 // of any and all Components within the scene
 pub struct Scene {
-  window: Window,
+  window: Option<Window>, // None for headless scenes
   pub size: winit::dpi::PhysicalSize<u32>,
   device: wgpu::Device,
   queue: wgpu:: Queue,
   config: wgpu::SurfaceConfiguration,
-  surface: wgpu::Surface,
+  present_modes: Vec<wgpu::PresentMode>, // modes the surface supports
+  surface: Option<wgpu::Surface>,
+  offscreen_texture: Option<wgpu::Texture>, // render target used in place of the surface when headless
   pub components: ComponentStore,
   projection: Projection,
   depth_texture: Texture,
-  texture_bind_group_layout: BindGroupLayout,
+  bind_group_layouts: BindGroupLayoutCache,
+  texture_bind_group_layout: Arc<BindGroupLayout>,
   camera: Camera,
   camera_uniform: CameraUniform,
   pub camera_controller: CameraController,
+  camera_transition: Option<CameraTransition>,
   camera_buffer: wgpu::Buffer,
+  camera_bind_group_layout: wgpu::BindGroupLayout,
   camera_bind_group: wgpu::BindGroup,
+  skybox: Option<Skybox>,
+  text_renderer: TextRenderer,
+  rect_renderer: RectRenderer,
+  pub debug_renderer: DebugRenderer,
+  debug_draw_colliders: bool,
+  paused: bool, // freezes simulation but keeps input and rendering
+  animations: HashMap<ComponentKey, AnimationTrack>,
+  debug_stats_enabled: bool,
+  fps: f32,
   light_uniform: LightUniform,
   light_buffer: wgpu::Buffer,
   light_bind_group_layout: wgpu::BindGroupLayout,
   light_bind_group: wgpu::BindGroup,
+  light_auto_rotate: bool,
   light_render_pipeline: wgpu::RenderPipeline,
-  pub mouse_pressed: bool,
+  shadow_map: ShadowMap,
+  pub mouse: Mouse,
+  #[cfg(feature = "gamepad")]
+  pub gamepads: Gamepads,
   clear_color: (f64, f64, f64, f64),
   pub model_renderer: ModelRenderer,
   render_pipeline_layout: wgpu::PipelineLayout,
   render_pipeline: wgpu::RenderPipeline,
+  transparent_render_pipeline: wgpu::RenderPipeline, // no depth writes, for opacity < 1
   pub app: Option<Component>, // top level component
   pub app_state: Store, // state manager
   pub event_manager: EventManager, // event manager
   pub collision_manager: CollisionManager, // collision manager
+  pub raycast_manager: RaycastManager, // raycast manager
+  fixed_timestep: Option<instant::Duration>,
+  fixed_accumulator: instant::Duration,
+  parents: HashMap<ComponentKey, Option<ComponentKey>>, // scene graph: child -> parent
+  local_transforms: HashMap<ComponentKey, ComponentTransform>, // child transforms relative to their parent
+  inactive: HashSet<ComponentKey>, // components skipped by update and render
+  inboxes: HashMap<ComponentKey, Vec<Box<dyn Any + Send>>>, // queued component messages
+  pending_resize: Option<winit::dpi::PhysicalSize<u32>>, // latest size not yet sent to components
+  pending_spawns: Vec<(Component, Option<ComponentKey>)>, // components waiting for init
+  pending_changes: Vec<(ComponentKey, PendingChange)>, // deferred activation changes and destroys
+  modifiers: ModifiersState,
+  held_keys: HashSet<VirtualKeyCode>,
+  selected: Option<ComponentKey>,
+  gravity: Vector3<f32>,
 }
 
 impl Scene {
@@ -95,12 +168,76 @@ impl Scene {
       format: surface_format,
       width: size.width,
       height: size.height,
-      present_mode: surface_caps.present_modes[0],
+      // fifo (vsync) is the one mode every surface supports
+      present_mode: wgpu::PresentMode::Fifo,
       alpha_mode: surface_caps.alpha_modes[0],
       view_formats: vec![]
     };
     surface.configure(&device, &config);
 
+    Self::from_device(Some(window), Some(surface), size, device, queue, config, surface_caps.present_modes).await
+  }
+
+  // creates a scene that renders to an offscreen texture instead of a window
+  // surface. useful for exercising the engine without a display
+  pub async fn new_headless(width: u32, height: u32) -> Scene {
+    let size = winit::dpi::PhysicalSize::new(width.max(1), height.max(1));
+
+    let instance = wgpu::Instance::new(
+      wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+      }
+    );
+
+    let adapter = instance.request_adapter(
+      &wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+      }
+    ).await.unwrap();
+
+    let (device, queue) = adapter.request_device(
+      &wgpu::DeviceDescriptor {
+        features: wgpu::Features::empty(),
+        limits: wgpu::Limits::downlevel_defaults(),
+        label: None
+      },
+      None
+    ).await.unwrap();
+
+    // the surface config is never used to configure a surface here, it just
+    // carries the target size and format around like it does for windowed scenes
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: wgpu::TextureFormat::Rgba8UnormSrgb,
+      width: size.width,
+      height: size.height,
+      present_mode: wgpu::PresentMode::Fifo,
+      alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+      view_formats: vec![]
+    };
+
+    // nothing is presented, so only the default mode is accepted
+    Self::from_device(None, None, size, device, queue, config, vec![wgpu::PresentMode::Fifo]).await
+  }
+
+  // shared setup for windowed and headless scenes once a device exists
+  async fn from_device(
+    window: Option<Window>,
+    surface: Option<wgpu::Surface>,
+    size: winit::dpi::PhysicalSize<u32>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
+  ) -> Scene {
+    let offscreen_texture = match surface {
+      Some(_) => None,
+      None => Some(create_offscreen_texture(&device, &config))
+    };
+
     //camera
     let camera = Camera::new(
       (0.0, 20.0, 50.0),
@@ -156,7 +293,7 @@ impl Scene {
       position: [2.0, 200.0, 2.0],
       _padding: 0,
       color: [1.0, 1.0, 1.0],
-      _padding_2: 0,
+      shading_mode: ShadingMode::Lit as u32,
     };
     let light_buffer = get_light_buffer(&device, &light_uniform);
     let (light_bind_group_layout, light_bind_group) = get_light_bind_group_info(&device, &light_buffer);
@@ -192,47 +329,11 @@ impl Scene {
     };
 
     // texture bind group
-    let texture_bind_group_layout = device.create_bind_group_layout(
-      &wgpu::BindGroupLayoutDescriptor { 
-        label: Some("Texture bind group layout"), 
-        entries: &[
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              multisampled: false,
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            // This should match the filterable field of the
-            // corresponding Texture entry above.
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              multisampled: false,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-              view_dimension: wgpu::TextureViewDimension::D2
-            },
-            count: None
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 3,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None
-          }
-        ] 
-      }
-    );
+    let mut bind_group_layouts = BindGroupLayoutCache::new();
+    let texture_bind_group_layout = bind_group_layouts.get_or_create(&device, "Texture bind group layout", &Material::bind_group_layout_entries());
+
+    // shadow map for the primary light (disabled until set_shadows_enabled)
+    let shadow_map = ShadowMap::new(&device);
 
     // load a depth texture
     let depth_texture = Texture::create_depth_texture(&device, &&config, "depth texture");
@@ -241,9 +342,10 @@ impl Scene {
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Render Pipeline Layout"),
       bind_group_layouts: &[
-        &texture_bind_group_layout,
+        texture_bind_group_layout.as_ref(),
         &camera_bind_group_layout,
         &light_bind_group_layout,
+        &shadow_map.bind_group_layout,
       ],
       push_constant_ranges: &[],
     });
@@ -270,6 +372,22 @@ impl Scene {
         "fs_main"
       )
     };
+    let transparent_render_pipeline = {
+      let shader = wgpu::ShaderModuleDescriptor {
+          label: Some("Normal Shader"),
+          source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+      };
+      get_transparent_render_pipeline(
+        &device,
+        &render_pipeline_layout,
+        config.format,
+        Some(Texture::DEPTH_FORMAT),
+        &[ModelVertex::desc(), InstanceRaw::desc()],
+        shader,
+        "vs_main",
+        "fs_main"
+      )
+    };
 
     // model store, component store, state, events, collisions, initialized here
     let model_renderer = ModelRenderer::new();
@@ -277,6 +395,9 @@ impl Scene {
     let app_state = create_app_state();
     let event_manager = EventManager::new();
     let collision_manager = CollisionManager::new();
+    let text_renderer = TextRenderer::new(&device, &queue, config.format);
+    let rect_renderer = RectRenderer::new(&device, config.format);
+    let debug_renderer = DebugRenderer::new(&device, config.format, &camera_bind_group_layout);
 
     let mut scene = Self {
       window,
@@ -284,30 +405,63 @@ impl Scene {
       device,
       queue,
       config,
+      present_modes,
       surface,
+      offscreen_texture,
       model_renderer,
       components,
       projection,
       depth_texture,
+      bind_group_layouts,
       texture_bind_group_layout,
       camera,
       camera_uniform,
       camera_controller,
+      camera_transition: None,
+      camera_bind_group_layout,
       camera_bind_group,
+      skybox: None,
+      text_renderer,
+      rect_renderer,
+      debug_renderer,
+      debug_draw_colliders: false,
+      paused: false,
+      animations: HashMap::new(),
+      debug_stats_enabled: false,
+      fps: 0.,
       light_uniform,
       light_buffer,
       light_bind_group_layout,
       light_bind_group,
+      light_auto_rotate: true,
       camera_buffer,
       light_render_pipeline,
+      shadow_map,
       render_pipeline,
+      transparent_render_pipeline,
       render_pipeline_layout,
-      mouse_pressed: false,
+      mouse: Mouse::new(),
+      #[cfg(feature = "gamepad")]
+      gamepads: Gamepads::new(),
       clear_color: (0.1, 0.2, 0.3, 1.),
       app: None,
       app_state,
       event_manager,
-      collision_manager
+      collision_manager,
+      raycast_manager: RaycastManager::new(),
+      fixed_timestep: None,
+      fixed_accumulator: instant::Duration::ZERO,
+      parents: HashMap::new(),
+      local_transforms: HashMap::new(),
+      inactive: HashSet::new(),
+      inboxes: HashMap::new(),
+      pending_resize: None,
+      pending_spawns: Vec::new(),
+      pending_changes: Vec::new(),
+      modifiers: ModifiersState::empty(),
+      held_keys: HashSet::new(),
+      selected: None,
+      gravity: DEFAULT_GRAVITY,
     };
 
     println!("Scene initialized");
@@ -324,21 +478,48 @@ impl Scene {
     scene
   }
 
-  pub fn window(&self) -> &Window {
-    &self.window
+  pub fn window(&self) -> Option<&Window> {
+    self.window.as_ref()
   }
 
   pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
     if new_size.width > 0 && new_size.height > 0 {
+      // reconfiguring at the same size isn't worth telling components about
+      if new_size != self.size {
+        self.pending_resize = Some(new_size);
+      }
       self.projection.resize(new_size.width, new_size.height);
       self.size = new_size;
       self.config.width = new_size.width;
       self.config.height = new_size.height;
-      self.surface.configure(&self.device, &self.config);
+      if let Some(surface) = self.surface.as_ref() {
+        surface.configure(&self.device, &self.config);
+      } else {
+        self.offscreen_texture = Some(create_offscreen_texture(&self.device, &self.config));
+      }
       self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
     }
   }
 
+  // recovers from an error returned by render. lost and outdated surfaces
+  // (gpu resets, display changes) are reconfigured at the current size
+  pub fn handle_surface_error(&mut self, err: wgpu::SurfaceError) -> SurfaceRecovery {
+    match err {
+      wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
+        self.resize(self.size);
+        SurfaceRecovery::Reconfigured
+      },
+      wgpu::SurfaceError::Timeout => {
+        log::warn!("surface timed out, skipping frame");
+        SurfaceRecovery::SkippedFrame
+      },
+      wgpu::SurfaceError::OutOfMemory => {
+        log::error!("surface out of memory: {:?}", err);
+        SurfaceRecovery::Fatal
+      }
+    }
+  }
+
   pub fn input (&mut self, event: &WindowEvent) -> bool {
     match event {
       WindowEvent::KeyboardInput {
@@ -350,66 +531,598 @@ impl Scene {
             },
         ..
       } => {
-        self.event_manager.handle_event(Event::from(event).unwrap());
+        match state {
+          ElementState::Pressed => self.held_keys.insert(*key),
+          ElementState::Released => self.held_keys.remove(key),
+        };
+        self.event_manager.handle_event(Event::from(event, self.modifiers).unwrap());
         self.camera_controller.process_keyboard(*key, *state)
       },
+      WindowEvent::ModifiersChanged(modifiers) => {
+        self.modifiers = *modifiers;
+        false
+      },
+      WindowEvent::Focused(false) => {
+        // releases that happen while unfocused never reach us
+        self.held_keys.clear();
+        self.modifiers = ModifiersState::empty();
+        false
+      },
       WindowEvent::MouseWheel { delta, .. } => {
         self.camera_controller.process_scroll(delta);
         true
       }
+      WindowEvent::CursorMoved { position, .. } => {
+        self.mouse.update_position(*position, &mut self.event_manager);
+        false
+      }
       WindowEvent::MouseInput {
-        button: MouseButton::Left,
+        button,
         state,
         ..
       } => {
-        self.mouse_pressed = *state == ElementState::Pressed;
-        true
-      }
+        // left clicks select whatever is under the cursor, or deselect on
+        // empty space. this waits for the release so dragging the camera
+        // around doesn't change the selection
+        if *button == MouseButton::Left && *state == ElementState::Released
+          && self.mouse.is_pressed(MouseButton::Left) && !self.mouse.is_dragging(MouseButton::Left) {
+          let hit = self.pick_under_cursor();
+          self.set_selected(hit.map(|h| h.component));
+        }
+        self.mouse.handle_button(*button, *state, &mut self.event_manager)
+      },
       _ => false,
     }
   }
 
   pub fn update(&mut self, dt: instant::Duration) {
-    // trigger any event callbacks:
+    let dt_secs = dt.as_secs_f32();
+    if dt_secs > 0. {
+      self.fps = if self.fps == 0. { 1. / dt_secs } else { self.fps * 0.9 + 0.1 / dt_secs };
+    }
+
+    // gamepads aren't reported through winit, so they're polled here
+    #[cfg(feature = "gamepad")]
+    self.gamepads.poll(&mut self.event_manager, &mut self.camera_controller);
+
+    // fire scheduled events that are due, then trigger any event callbacks.
+    // the event clock doesn't run while paused, input events still arrive
+    if !self.paused {
+      self.event_manager.update(dt.as_secs_f32());
+    }
     self.event_manager.trigger_callbacks(&mut self.components);
     let _ = self.app_state.trigger_callbacks(&mut self.components);
 
-    let comp_clones: Vec<_> = self.components.iter().map(|(_, comp)| comp.clone()).collect();
-    for comp in comp_clones.iter() {
-      comp.update(self, dt);
+    // upload and deliver any models finished loading in the background
+    self.model_renderer.poll_loads(&self.device, &self.queue, &self.texture_bind_group_layout);
+
+    self.deliver_messages();
+    self.notify_resize();
+
+    if !self.paused {
+      // animations advance before component updates so they see this
+      // frame's transforms
+      for track in self.animations.values_mut() {
+        track.advance(dt.as_secs_f32());
+      }
+
+      // run as many fixed steps as the accumulated real time covers,
+      // carrying the remainder over to the next frame
+      if let Some(step) = self.fixed_timestep {
+        for _ in 0..take_fixed_steps(&mut self.fixed_accumulator, dt, step) {
+          self.fixed_update(step);
+        }
+      }
+
+      self.for_each_component(false, |scene, comp| comp.update(scene, dt));
     }
 
     // should also call component updates
     self.camera_controller.update_camera(&mut self.camera, dt);
+    // transitions override the controller until they finish
+    if let Some(transition) = self.camera_transition.as_mut() {
+      transition.elapsed += dt.as_secs_f32();
+      let t = if transition.duration > 0. { transition.elapsed / transition.duration } else { 1. };
+      self.camera.restore(&transition.from.lerp(&transition.to, t));
+      if t >= 1. {
+        self.camera_transition = None;
+      }
+    }
     self.camera_uniform.update_view_proj(&self.camera, &self.projection);
     self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
 
-    let old_light_position: cgmath::Vector3<_> = self.light_uniform.position.into();
-    self.light_uniform.position = 
-    (cgmath::Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), cgmath::Deg(60.0 * dt.as_secs_f32()))
-        * old_light_position)
-        .into();
-    self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+    if self.light_auto_rotate && !self.paused {
+      let (axis, speed) = self.light_rotation();
+      if speed != 0. {
+        let old_light_position: cgmath::Vector3<_> = self.light_uniform.position.into();
+        self.light_uniform.position = 
+        (cgmath::Quaternion::from_axis_angle(axis, cgmath::Deg(speed * dt.as_secs_f32()))
+            * old_light_position)
+            .into();
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+      }
+    }
+
+    self.apply_pending_changes();
+    self.process_spawns();
+  }
+
+  // starts (or restarts) a keyframe animation for the component. each
+  // update advances it, and the component reads the result with
+  // animated_transform when it renders its model
+  pub fn play_animation(&mut self, component: ComponentKey, track: AnimationTrack) -> Result<(), EngineError> {
+    if self.components.get(&component).is_none() {
+      return Err(EngineError::ArgumentError { index: 1, name: "component".into() });
+    }
+    self.animations.insert(component, track);
+    Ok(())
+  }
+
+  pub fn stop_animation(&mut self, component: &ComponentKey) -> bool {
+    self.animations.remove(component).is_some()
+  }
+
+  // the component's current animated transform. finished tracks that
+  // don't loop keep returning their last keyframe until stopped
+  pub fn animated_transform(&self, component: &ComponentKey) -> Option<ModelTransform> {
+    self.animations.get(component).and_then(|track| track.current())
+  }
+
+  // while paused, update skips component updates, fixed steps, scheduled
+  // events and light rotation. input, the camera and rendering keep going
+  pub fn set_paused(&mut self, paused: bool) {
+    self.paused = paused;
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  // queue a component to be created from inside update (where init can't
+  // be awaited). the key is reserved immediately but the component isn't
+  // initialized, updated or rendered until the end of the current frame
+  pub fn queue_spawn<T: ComponentFunctions>(&mut self, underlying: Arc<Mutex<T>>, parent: Option<ComponentKey>) -> Result<ComponentKey, EngineError> {
+    let component = Component::register(underlying, self, parent)?;
+    let key = component.key;
+    self.inactive.insert(key);
+    self.pending_spawns.push((component, parent));
+    Ok(key)
+  }
+
+  fn process_spawns(&mut self) {
+    // spawns queued by an init run in the next pass of the loop
+    while self.pending_spawns.len() > 0 {
+      let spawns = std::mem::take(&mut self.pending_spawns);
+      for (mut component, parent) in spawns {
+        let key = component.key;
+        // destroyed before it got a chance to initialize
+        if self.components.get(&key).is_none() {
+          continue;
+        }
+        self.inactive.remove(&key);
+        pollster::block_on(component.init(self, key, parent));
+      }
+    }
+  }
+
+  // runs the set_active and destroy_component calls queued since the last
+  // update. the hooks run here, outside every component's own update or
+  // event handler, so a component can disable or destroy itself (or its
+  // parent) without locking itself twice
+  fn apply_pending_changes(&mut self) {
+    // hooks can queue further changes, which are applied in the next pass
+    while self.pending_changes.len() > 0 {
+      for (key, change) in std::mem::take(&mut self.pending_changes) {
+        match change {
+          PendingChange::SetActive(active) => self.apply_set_active(key, active),
+          PendingChange::Destroy => self.apply_destroy(key),
+        }
+      }
+    }
+  }
+
+  // hands each queued message to its target. messages whose target was
+  // destroyed after sending are dropped
+  fn deliver_messages(&mut self) {
+    let inboxes = std::mem::take(&mut self.inboxes);
+    for (key, messages) in inboxes {
+      if let Some(component) = self.components.get(&key).cloned() {
+        for message in messages {
+          component.receive_message(self, message);
+        }
+      }
+    }
+  }
+
+  fn notify_resize(&mut self) {
+    if let Some(new_size) = self.pending_resize.take() {
+      self.for_each_component(true, |scene, comp| comp.on_resize(scene, new_size));
+    }
+  }
+
+  // nearest collider under a screen position (pixels from the top left)
+  pub fn pick(&self, screen_pos: (f64, f64)) -> Option<RaycastHit> {
+    let (ray, length) = screen_to_segment(screen_pos, &self.camera, &self.projection, self.size);
+    self.mouse.intersect_colliders(&ray, length, &self.collision_manager, |key| self.pick_opacity(key))
+  }
+
+  // where the picking ray under a screen position meets a plane, e.g. the
+  // ground for dragging objects around. None if the ray runs parallel to
+  // the plane or the plane is behind the camera
+  pub fn screen_to_plane(&self, screen_pos: (f64, f64), plane_point: Point3<f32>, plane_normal: Vector3<f32>) -> Option<Point3<f32>> {
+    let ray = screen_to_ray(screen_pos, &self.camera, &self.projection, self.size);
+    let denom = ray.dir.dot(plane_normal);
+    if denom.abs() < 1e-6 {
+      return None;
+    }
+    let t = (plane_point - ray.origin).dot(plane_normal) / denom;
+    if t < 0. {
+      return None;
+    }
+    Some(ray.at(t))
+  }
+
+  // components without models (and static colliders) count as opaque
+  fn pick_opacity(&self, key: &ComponentKey) -> f32 {
+    self.model_renderer.component_opacity(key).unwrap_or(1.)
+  }
+
+  pub fn selected_component(&self) -> Option<ComponentKey> {
+    self.selected
+  }
+
+  // highlights the component's models. None clears the selection
+  pub fn set_selected(&mut self, key: Option<ComponentKey>) {
+    if self.selected == key {
+      return;
+    }
+    self.selected = key;
+    self.model_renderer.set_selected_component(key, &self.device);
+  }
+
+  // nearest collider under the current cursor position
+  pub fn pick_under_cursor(&self) -> Option<RaycastHit> {
+    let (ray, length) = self.mouse.cursor_segment(&self.camera, &self.projection, self.size);
+    self.mouse.intersect_colliders(&ray, length, &self.collision_manager, |key| self.pick_opacity(key))
+  }
+
+  // distances from the camera to the near and far clip planes. depth
+  // precision is spread non-linearly between the two, mostly near the
+  // near plane, so pushing near out helps far more than pulling far in
+  // when distant surfaces start z-fighting
+  pub fn set_clip_planes(&mut self, near: f32, far: f32) -> Result<(), EngineError> {
+    if !(near > 0.) {
+      return Err(EngineError::ArgumentError { index: 1, name: "near".into() })
+    }
+    if !(far > near) {
+      return Err(EngineError::ArgumentError { index: 2, name: "far".into() })
+    }
+    self.projection.set_clip_planes(near, far);
+    self.camera_uniform.update_view_proj(&self.camera, &self.projection);
+    self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+    Ok(())
+  }
+
+  pub fn near_plane(&self) -> f32 {
+    self.projection.znear()
+  }
+
+  pub fn far_plane(&self) -> f32 {
+    self.projection.zfar()
+  }
+
+  pub fn camera_position(&self) -> Point3<f32> {
+    self.camera.position
+  }
+
+  pub fn camera_forward(&self) -> Vector3<f32> {
+    self.camera.forward()
+  }
+
+  // moves the camera directly. the controller keeps working from the new
+  // position on the next update
+  pub fn set_camera<Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(&mut self, position: Point3<f32>, yaw: Y, pitch: P) {
+    self.camera.position = position;
+    self.camera.yaw = yaw.into();
+    self.camera.pitch = pitch.into();
+    self.camera_uniform.update_view_proj(&self.camera, &self.projection);
+    self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+  }
+
+  pub fn camera_snapshot(&self) -> CameraPose {
+    self.camera.snapshot()
+  }
+
+  // jumps straight to pose, cancelling any lerp_camera_to in progress
+  pub fn restore_camera(&mut self, pose: &CameraPose) {
+    self.camera_transition = None;
+    self.set_camera(pose.position, pose.yaw, pose.pitch);
+  }
+
+  // moves the camera from where it is now to pose over duration, starting
+  // with the next update. camera controller input is ignored until it arrives
+  pub fn lerp_camera_to(&mut self, pose: CameraPose, duration: instant::Duration) {
+    self.camera_transition = Some(CameraTransition {
+      from: self.camera.snapshot(),
+      to: pose,
+      duration: duration.as_secs_f32(),
+      elapsed: 0.
+    });
+  }
+
+  pub fn is_camera_transitioning(&self) -> bool {
+    self.camera_transition.is_some()
+  }
+
+  pub fn present_mode(&self) -> wgpu::PresentMode {
+    self.config.present_mode
+  }
+
+  pub fn supported_present_modes(&self) -> &Vec<wgpu::PresentMode> {
+    &self.present_modes
+  }
+
+  // Fifo waits for vsync, Mailbox and Immediate don't cap the frame rate.
+  // modes the surface doesn't support are rejected
+  pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> Result<(), EngineError> {
+    if !self.present_modes.contains(&mode) {
+      return Err(EngineError::ArgumentError { index: 1, name: "mode".into() });
+    }
+    self.config.present_mode = mode;
+    if let Some(surface) = self.surface.as_ref() {
+      surface.configure(&self.device, &self.config);
+    }
+    Ok(())
+  }
+
+  pub fn clear_color(&self) -> (f64, f64, f64, f64) {
+    self.clear_color
+  }
+
+  // background color (rgba, 0-1) for anything no model covers
+  pub fn set_clear_color(&mut self, r: f64, g: f64, b: f64, a: f64) {
+    self.clear_color = (r.clamp(0., 1.), g.clamp(0., 1.), b.clamp(0., 1.), a.clamp(0., 1.));
+  }
+
+  // clear_color as the surface expects it. premultiplied surfaces need the
+  // alpha applied to rgb, post multiplied ones take it as is, and opaque
+  // surfaces ignore alpha entirely
+  fn surface_clear_color(&self) -> wgpu::Color {
+    let (r, g, b, a) = self.clear_color;
+    match self.config.alpha_mode {
+      wgpu::CompositeAlphaMode::PreMultiplied => wgpu::Color { r: r * a, g: g * a, b: b * a, a },
+      wgpu::CompositeAlphaMode::Opaque => wgpu::Color { r, g, b, a: 1. },
+      _ => wgpu::Color { r, g, b, a },
+    }
+  }
+
+  // default acceleration for RigidBody::integrate
+  pub fn gravity(&self) -> Vector3<f32> {
+    self.gravity
+  }
+
+  pub fn set_gravity(&mut self, gravity: Vector3<f32>) {
+    self.gravity = gravity;
+  }
+
+  // true between a key's press and release events
+  pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
+    self.held_keys.contains(&key)
+  }
+
+  // true while any key bound to the action is held
+  pub fn is_action_held(&self, action: &Action) -> bool {
+    self.held_keys.iter().any(|key| self.camera_controller.input_map.action(*key) == Some(action))
+  }
+
+  // binds key to action, replacing the key's previous binding. the default
+  // bindings are InputMap::default
+  pub fn bind_key(&mut self, key: VirtualKeyCode, action: Action) -> Option<Action> {
+    let previous = self.camera_controller.input_map.bind(key, action.clone());
+    if let Some(previous) = &previous {
+      self.sync_held_action(previous);
+    }
+    self.sync_held_action(&action);
+    previous
+  }
+
+  pub fn unbind_key(&mut self, key: VirtualKeyCode) -> Option<Action> {
+    let previous = self.camera_controller.input_map.unbind(key);
+    if let Some(previous) = &previous {
+      self.sync_held_action(previous);
+    }
+    previous
+  }
+
+  // makes keys the only bindings for action
+  pub fn rebind_action(&mut self, action: Action, keys: &[VirtualKeyCode]) {
+    let displaced = keys.iter()
+      .filter_map(|key| self.camera_controller.input_map.action(*key).cloned())
+      .collect::<Vec<Action>>();
+    self.camera_controller.input_map.rebind(action.clone(), keys);
+    for previous in displaced.iter() {
+      self.sync_held_action(previous);
+    }
+    self.sync_held_action(&action);
+  }
+
+  // after a binding change the controller's amount for action has to match
+  // the keys now bound to it, otherwise a key held across the change leaves
+  // the camera moving with no release to stop it
+  fn sync_held_action(&mut self, action: &Action) {
+    let state = if self.is_action_held(action) { ElementState::Pressed } else { ElementState::Released };
+    self.camera_controller.process_action(action, state);
+  }
+
+  pub fn modifiers(&self) -> ModifiersState {
+    self.modifiers
+  }
+
+  // broadcasts an event to every component listening for its key.
+  // events whose key doesn't match their data are rejected
+  pub fn emit_event(&mut self, event: Event) -> Result<(), EngineError> {
+    if !event.is_valid() {
+      return Err(EngineError::ArgumentError { index: 1, name: "event".into() });
+    }
+    self.event_manager.handle_event(event);
+    Ok(())
+  }
+
+  pub fn emit(&mut self, data: EventData) -> Result<(), EngineError> {
+    self.emit_event(Event::new(data))
+  }
+
+  // sends an event to one component only. returns false if it doesn't
+  // listen for that kind of event
+  pub fn emit_to(&mut self, component: ComponentKey, data: EventData) -> Result<bool, EngineError> {
+    if self.components.get(&component).is_none() {
+      return Err(EngineError::ArgumentError { index: 1, name: "component".into() });
+    }
+    let event = Event::new(data);
+    if !event.is_valid() {
+      return Err(EngineError::ArgumentError { index: 2, name: "data".into() });
+    }
+    Ok(self.event_manager.handle_event_for(component, event))
+  }
+
+  // schedule an event to fire at a time (in seconds) on the event clock
+  pub fn schedule_event_at(&mut self, event: Event, time: f32) -> ScheduledEventId {
+    self.event_manager.schedule_at_time(event, time)
+  }
+
+  pub fn trigger_event_after(&mut self, event: Event, delay: f32) -> ScheduledEventId {
+    self.event_manager.trigger_after_delay(event, delay)
+  }
+
+  pub fn schedule_recurrent_event(&mut self, event: Event, period: f32) -> ScheduledEventId {
+    self.event_manager.schedule_recurrent(event, period)
+  }
+
+  // stop a pending or recurring scheduled event
+  pub fn cancel_scheduled_event(&mut self, id: &ScheduledEventId) -> bool {
+    self.event_manager.remove_se(id)
+  }
+
+  // queue a message for a single component. it's delivered through
+  // ComponentFunctions::receive_message at the start of the next update
+  pub fn send_message(&mut self, target: ComponentKey, message: Box<dyn Any + Send>) -> Result<(), EngineError> {
+    if self.components.get(&target).is_none() {
+      return Err(EngineError::ArgumentError { index: 1, name: "target".into() });
+    }
+    self.inboxes.entry(target).or_insert(Vec::new()).push(message);
+    Ok(())
+  }
+
+  fn fixed_update(&mut self, step: instant::Duration) {
+    self.for_each_component(false, |scene, comp| comp.fixed_update(scene, step));
+  }
+
+  // calls f for each component with mutable access to the scene, in key
+  // order. only the keys are collected up front: a component destroyed by
+  // an earlier one in the same pass is skipped, and spawns, destroys and
+  // activation changes are deferred to the end of the update. each
+  // component is cloned out of the store because f also borrows the scene
+  // that owns it. the clone only copies the key and an Arc
+  fn for_each_component<F: FnMut(&mut Scene, &Component)>(&mut self, include_inactive: bool, mut f: F) {
+    let mut keys = self.components.keys().into_iter().cloned().collect::<Vec<ComponentKey>>();
+    keys.sort_by_key(|k| (k.index, k.generation));
+    for key in keys {
+      if !include_inactive && self.inactive.contains(&key) {
+        continue;
+      }
+      if let Some(comp) = self.components.get(&key).cloned() {
+        f(self, &comp);
+      }
+    }
+  }
+
+  // None (or a zero step) disables the fixed update loop
+  pub fn set_fixed_timestep(&mut self, step: Option<instant::Duration>) {
+    self.fixed_timestep = step.filter(|s| !s.is_zero());
+    self.fixed_accumulator = instant::Duration::ZERO;
+  }
+
+  pub fn fixed_timestep(&self) -> Option<instant::Duration> {
+    self.fixed_timestep
   }
 
   pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
     // mark models to be rendered
     if let Some(app) = self.app.clone() {
-      if let Err(err) = app.render(self, None) {
-        println!("render failed with err {}", err);
+      if self.is_active(&app.key) {
+        if let Err(err) = app.render(self, None) {
+          println!("render failed with err {}", err);
+        }
       }
     } else {
       println!("No app found");
       return Ok(());
     }
-    self.collision_manager.update_collider_positions(self.model_renderer.get_position_cache());
+    // colliders follow the scene graph rather than the render pass, so
+    // components that weren't rendered this frame still collide
+    let world_matrices = self.component_world_matrices();
+    self.collision_manager.update_collider_positions(&world_matrices);
     self.collision_manager.trigger_collision_events(&mut self.event_manager);
+    self.raycast_manager.update_following_rays(&world_matrices);
+    self.raycast_manager.intersect_colliders(&self.collision_manager);
+    if self.debug_draw_colliders {
+      self.draw_collider_debug();
+    }
+    if self.debug_stats_enabled {
+      self.draw_debug_stats();
+    }
+
+    let output = match self.surface.as_ref() {
+      Some(surface) => Some(surface.get_current_texture()?),
+      None => None
+    };
+    let view = match (output.as_ref(), self.offscreen_texture.as_ref()) {
+      (Some(output), _) => output.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+      (None, Some(texture)) => texture.create_view(&wgpu::TextureViewDescriptor::default()),
+      (None, None) => return Err(wgpu::SurfaceError::Lost)
+    };
+    let frustum = Frustum::from_matrix(self.camera_uniform.view_proj.into());
+    self.model_renderer.prepare_draw_list(&self.device, self.camera.position, Some(&frustum));
+    self.debug_renderer.prepare(&self.device, &self.queue);
 
-    let output = self.surface.get_current_texture()?;
-    let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
     let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
       label: Some("Render encoder")
     });
+    self.model_renderer.flush_writes(&self.device, &mut encoder);
+
+    // the light moves every frame so the light matrix is refreshed before
+    // the shadow pass
+    let shadows_enabled = self.shadow_map.enabled();
+    self.shadow_map.update(&self.queue, self.light_uniform.position, shadows_enabled);
+    if shadows_enabled {
+      let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Shadow pass"),
+        color_attachments: &[],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: &self.shadow_map.texture.view,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: wgpu::StoreOp::Store,
+          }),
+          stencil_ops: None,
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None
+      });
+      shadow_pass.set_pipeline(&self.shadow_map.pipeline);
+      shadow_pass.set_bind_group(0, &self.shadow_map.pass_bind_group, &[]);
+      for model_tuple in self.model_renderer.get_rendering_models() {
+        shadow_pass.set_vertex_buffer(1, model_tuple.1.slice(..));
+        for (idx, mesh) in model_tuple.0.meshes.iter().enumerate() {
+          if model_tuple.3.map(|filter| !filter.contains(&idx)).unwrap_or(false) {
+            continue;
+          }
+          shadow_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+          shadow_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+          shadow_pass.draw_indexed(0..mesh.num_elements, 0, 0..model_tuple.2);
+        }
+      }
+    }
 
     {
       let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
@@ -418,12 +1131,7 @@ impl Scene {
           view: &view,
           resolve_target: None,
           ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color {
-              r: self.clear_color.0,
-              g: self.clear_color.1,
-              b: self.clear_color.2,
-              a: self.clear_color.3,
-            }),
+            load: wgpu::LoadOp::Clear(self.surface_clear_color()),
             store: wgpu::StoreOp::Store,
           },
         })], 
@@ -444,18 +1152,62 @@ impl Scene {
       // render_pass.set_pipeline(&self.light_render_pipeline);
       // render_pass.draw_light_model(&self.obj_model, &self.camera_bind_group, &self.light_bind_group);
 
+      // the skybox goes first so models draw over it
+      if let Some(skybox) = self.skybox.as_ref() {
+        skybox.draw(&mut render_pass, &self.camera_bind_group);
+      }
+
       render_pass.set_pipeline(&self.render_pipeline);
-      for model_tuple in self.model_renderer.get_rendering_models() {
+      render_pass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+      // transparent models come last in the draw list, so the pipeline only
+      // switches once
+      let mut transparent_pass = false;
+      for model_tuple in self.model_renderer.get_draw_list() {
         // println!("Rendering model: {:?}, {:?}", &model_tuple.0, &model_tuple.1);
+        if model_tuple.3 && !transparent_pass {
+          render_pass.set_pipeline(&self.transparent_render_pipeline);
+          transparent_pass = true;
+        }
         render_pass.set_vertex_buffer(1, model_tuple.1.slice(..));
-        render_pass.draw_model_instanced(&model_tuple.0, 0..1, &self.camera_bind_group, &self.light_bind_group);
+        render_pass.draw_model_instanced_filtered(&model_tuple.0, 0..model_tuple.2, model_tuple.4, &self.camera_bind_group, &self.light_bind_group);
       }
+
+      self.debug_renderer.draw(&mut render_pass, &self.camera_bind_group);
+    }
+
+    // 2d rects and then text go over everything else
+    if self.rect_renderer.rects().len() > 0 || self.text_renderer.quads().len() > 0 {
+      let screen_size = (self.config.width, self.config.height);
+      self.rect_renderer.prepare(&self.device, &self.queue, screen_size);
+      self.text_renderer.prepare(&self.device, &self.queue, screen_size);
+      let mut overlay_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Overlay pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: &view,
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: wgpu::StoreOp::Store,
+          },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None
+      });
+      self.rect_renderer.draw(&mut overlay_pass);
+      self.text_renderer.draw(&mut overlay_pass);
     }
 
     self.queue.submit(std::iter::once(encoder.finish()));
-    output.present();
+    self.model_renderer.recall_writes();
+    if let Some(output) = output {
+      output.present();
+    }
     // clear model render list
     self.model_renderer.clear();
+    self.text_renderer.clear();
+    self.rect_renderer.clear();
+    self.debug_renderer.clear();
     Ok(())
   }
 
@@ -469,9 +1221,429 @@ impl Scene {
     }
   }
 
+  // a layout from the scene's cache, shared with anything else asking for
+  // the same entries (model materials use Material::bind_group_layout_entries)
+  pub fn bind_group_layout(&mut self, label: &str, entries: &[wgpu::BindGroupLayoutEntry]) -> Arc<BindGroupLayout> {
+    self.bind_group_layouts.get_or_create(&self.device, label, entries)
+  }
+
+  // adds a Model::ground_grid owned by the component, rendered like any
+  // loaded model
+  pub fn load_ground_grid(&mut self, size: f32, divisions: u32, color: [f32; 3], component_key: ComponentKey) -> Result<RenderableModel, EngineError> {
+    let grid = Model::ground_grid(&self.device, &self.queue, &self.texture_bind_group_layout, size, divisions, color)
+      .map_err(|err| EngineError::ModelLoadError { err, filename: "ground_grid".into() })?;
+    Ok(self.model_renderer.add_generated_model(grid, "ground_grid", None, component_key, &self.device))
+  }
+
+  // loads a model without blocking the caller. the result is passed to
+  // target's handle_async_res on a later update, and progress (if given) is
+  // called with (bytes loaded, total bytes) from the loading thread
+  pub fn load_model_async<CType: AsyncCallbackHandler<Result<RenderableModel, EngineError>>>(
+    &mut self,
+    filename: &str,
+    instances: Option<Vec<Instance>>,
+    component_key: ComponentKey,
+    target: Arc<Mutex<Box<CType>>>,
+    progress: Option<Box<dyn Fn(u64, u64) + Send + Sync>>
+  ) -> ModelLoadHandle {
+    self.model_renderer.load_model_async(filename, instances, component_key, target, progress)
+  }
+
+  // frees a model's gpu memory. models are also unloaded automatically
+  // when the component that loaded them is destroyed
+  pub fn unload_model(&mut self, model: &RenderableModel) -> Result<(), EngineError> {
+    self.model_renderer.unload_model(model)
+  }
+
+  // swaps the diffuse texture of one of the model's materials, e.g. for a
+  // solid color made with Texture::from_raw
+  pub fn set_model_texture(&mut self, model: &RenderableModel, material_index: usize, texture: Texture) -> Result<(), EngineError> {
+    self.model_renderer.set_model_texture(model, material_index, texture, &self.device, &self.queue, &self.texture_bind_group_layout)
+  }
+
+  // level of detail: model is drawn as the first of tiers whose max
+  // distance from the camera covers it, or the last tier when further away
+  pub fn register_lod(&mut self, model: &RenderableModel, tiers: Vec<(RenderableModel, f32)>) -> Result<(), EngineError> {
+    self.model_renderer.register_lod(model, tiers)
+  }
+
   pub fn render_model(&mut self, model: &RenderableModel, transform: ModelTransform) -> Result<(), EngineError> {
     // needs to position/rotate the model appropriately too
-    self.model_renderer.render(model, transform, &self.queue, &self.device)
+    self.model_renderer.render(model, transform, &self.device, self.camera.forward())
     // self.model_renderer.render_from_cache(model)
   }
+
+  pub fn is_active(&self, key: &ComponentKey) -> bool {
+    !self.inactive.contains(key)
+  }
+
+  // takes effect (and calls on_enable/on_disable) at the end of the
+  // current update, once every component has run
+  pub fn set_active(&mut self, key: ComponentKey, active: bool) -> Result<(), EngineError> {
+    if self.components.get(&key).is_none() {
+      return Err(EngineError::ArgumentError { index: 1, name: "key".into() });
+    }
+    self.pending_changes.push((key, PendingChange::SetActive(active)));
+    Ok(())
+  }
+
+  fn apply_set_active(&mut self, key: ComponentKey, active: bool) {
+    let component = match self.components.get(&key) {
+      Some(component) => component.clone(),
+      None => return
+    };
+    if active == self.is_active(&key) {
+      return;
+    }
+    if active {
+      self.inactive.remove(&key);
+      component.on_enable(self);
+    } else {
+      self.inactive.insert(key);
+      component.on_disable(self);
+    }
+  }
+
+  // removes a component and all of its children from the scene, along with
+  // their colliders, raycasts and event/state listeners. the component is
+  // skipped for the rest of the frame and removed at the end of the
+  // current update, when on_destroy is called
+  pub fn destroy_component(&mut self, key: ComponentKey) -> Result<(), EngineError> {
+    if self.components.get(&key).is_none() {
+      return Err(EngineError::ArgumentError { index: 1, name: "key".into() });
+    }
+    self.inactive.insert(key);
+    self.pending_changes.push((key, PendingChange::Destroy));
+    Ok(())
+  }
+
+  fn apply_destroy(&mut self, key: ComponentKey) {
+    let component = match self.components.get(&key) {
+      Some(component) => component.clone(),
+      None => return
+    };
+    for child in self.get_children(&key) {
+      self.apply_destroy(child);
+    }
+    component.on_destroy(self);
+
+    self.components.remove(&key);
+    self.parents.remove(&key);
+    self.local_transforms.remove(&key);
+    self.animations.remove(&key);
+    self.inactive.remove(&key);
+    self.inboxes.remove(&key);
+    self.collision_manager.remove_component_colliders(key);
+    self.raycast_manager.remove_component_raycasts(&key);
+    self.model_renderer.unload_component_models(key);
+    self.event_manager.remove_component_listeners(&key);
+    self.app_state.remove_component_listeners(&key);
+    if self.selected == Some(key) {
+      self.selected = None;
+    }
+    if self.app.as_ref().map(|app| app.key == key).unwrap_or(false) {
+      self.app = None;
+    }
+  }
+
+  // attach a component to a parent (or detach it with None). children are
+  // rendered automatically after their parent with the parent's transform applied
+  pub fn set_parent(&mut self, child: ComponentKey, parent: Option<ComponentKey>) -> Result<(), EngineError> {
+    // walk up from the new parent to make sure we aren't creating a cycle
+    let mut ancestor = parent;
+    while let Some(a) = ancestor {
+      if a == child {
+        return Err(EngineError::ArgumentError { index: 1, name: "parent".into() });
+      }
+      ancestor = self.get_parent(&a);
+    }
+    self.parents.insert(child, parent);
+    Ok(())
+  }
+
+  pub fn get_parent(&self, key: &ComponentKey) -> Option<ComponentKey> {
+    self.parents.get(key).cloned().flatten()
+  }
+
+  // children are sorted by key so the render order is stable between frames
+  pub fn get_children(&self, key: &ComponentKey) -> Vec<ComponentKey> {
+    let mut children = self.parents.iter()
+      .filter(|(_, parent)| **parent == Some(*key))
+      .map(|(child, _)| *child)
+      .collect::<Vec<ComponentKey>>();
+    children.sort_by_key(|k| (k.index, k.generation));
+    children
+  }
+
+  pub fn set_local_transform(&mut self, key: ComponentKey, transform: ComponentTransform) {
+    self.local_transforms.insert(key, transform);
+  }
+
+  pub fn get_local_transform(&self, key: &ComponentKey) -> ComponentTransform {
+    self.local_transforms.get(key).cloned().unwrap_or(ComponentTransform::default())
+  }
+
+  // sets a collider's position and rotation relative to its component and
+  // refreshes its world transform right away
+  pub fn set_collider_transform(&mut self, collider: &Arc<RwLock<Collider>>, pos: Vector3<f32>, rot: Quaternion<f32>) {
+    collider.write().unwrap().update_transform(pos, rot);
+    let parent = collider.read().unwrap().parent;
+    let world = self.world_matrix_into(parent, &mut HashMap::new());
+    update_collider_position(collider, &world);
+  }
+
+  // this frame's collisions involving the component, for components that
+  // would rather poll in update than listen for collision events
+  pub fn collisions_for(&self, component: ComponentKey) -> Vec<Collision> {
+    self.collision_manager.collisions_for(&component)
+  }
+
+  // world position of a component as of the last render pass
+  pub fn world_position(&self, key: &ComponentKey) -> Option<Vector3<f32>> {
+    self.model_renderer.get_position_cache().get(key).map(|mat| mat.w.truncate())
+  }
+
+  // world matrix of every component built from the scene graph (parents
+  // and local transforms), independent of what has been rendered
+  pub fn component_world_matrices(&self) -> HashMap<ComponentKey, Matrix4<f32>> {
+    let mut matrices = HashMap::new();
+    for (key, _) in self.components.iter() {
+      self.world_matrix_into(*key, &mut matrices);
+    }
+    matrices
+  }
+
+  fn world_matrix_into(&self, key: ComponentKey, matrices: &mut HashMap<ComponentKey, Matrix4<f32>>) -> Matrix4<f32> {
+    if let Some(world) = matrices.get(&key) {
+      return *world;
+    }
+    let parent_world = match self.get_parent(&key) {
+      Some(parent) => self.world_matrix_into(parent, matrices),
+      None => Matrix4::identity()
+    };
+    let world = parent_world * self.get_local_transform(&key).to_matrix();
+    matrices.insert(key, world);
+    world
+  }
+
+  // queue text for this frame at (x, y) pixels from the top left.
+  // size is the glyph height in pixels and color is rgba
+  pub fn draw_text(&mut self, text: &str, x: f32, y: f32, size: f32, color: [f32; 4]) {
+    self.text_renderer.queue_text(text, x, y, size, color);
+  }
+
+  // queue a filled rectangle for this frame at (x, y) pixels from the top
+  // left, w by h pixels. color is rgba and drawn under any text
+  pub fn draw_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) {
+    self.rect_renderer.queue_rect(x, y, w, h, color);
+  }
+
+  pub fn debug_stats(&self) -> DebugStats {
+    DebugStats {
+      components: self.components.iter().count(),
+      colliders: self.collision_manager.collider_count(),
+      colliding_pairs: self.collision_manager.colliding_pair_count(),
+      raycasts: self.raycast_manager.raycast_count(),
+      fps: self.fps,
+    }
+  }
+
+  // shows debug_stats in the top left corner every frame
+  pub fn set_debug_stats(&mut self, enabled: bool) {
+    self.debug_stats_enabled = enabled;
+  }
+
+  pub fn debug_stats_enabled(&self) -> bool {
+    self.debug_stats_enabled
+  }
+
+  fn draw_debug_stats(&mut self) {
+    let stats = self.debug_stats();
+    let lines = [
+      format!("fps: {:.0}", stats.fps),
+      format!("components: {}", stats.components),
+      format!("colliders: {}", stats.colliders),
+      format!("colliding pairs: {}", stats.colliding_pairs),
+      format!("raycasts: {}", stats.raycasts),
+    ];
+    for (idx, line) in lines.iter().enumerate() {
+      self.draw_text(line, 8., 8. + idx as f32 * 18., 16., [1., 1., 1., 1.]);
+    }
+  }
+
+  // world space debug line for this frame
+  pub fn draw_debug_line(&mut self, a: Point3<f32>, b: Point3<f32>, color: [f32; 4]) {
+    self.debug_renderer.draw_line(a, b, color);
+  }
+
+  pub fn draw_debug_ray(&mut self, origin: Point3<f32>, dir: Vector3<f32>, length: f32, color: [f32; 4]) {
+    self.debug_renderer.draw_ray(origin, dir, length, color);
+  }
+
+  // outline every collider's bounding box (and sample sdf colliders into a
+  // point cloud) through the debug renderer each frame
+  pub fn set_debug_draw_colliders(&mut self, enabled: bool) {
+    self.debug_draw_colliders = enabled;
+  }
+
+  pub fn debug_draw_colliders(&self) -> bool {
+    self.debug_draw_colliders
+  }
+
+  fn draw_collider_debug(&mut self) {
+    const BOX_COLOR: [f32; 4] = [0.1, 1.0, 0.2, 1.0];
+    const SAMPLE_COLOR: [f32; 4] = [1.0, 0.85, 0.1, 1.0];
+    const SAMPLE_RESOLUTION: usize = 16;
+    for collider in self.collision_manager.get_colliders() {
+      let collider = collider.read().unwrap();
+      if let Some(corners) = collider.world_aabb_corners() {
+        // corners differing in exactly one bit share an edge
+        for i in 0..8 {
+          for bit in [1, 2, 4] {
+            if i & bit == 0 {
+              self.debug_renderer.draw_line(corners[i], corners[i | bit], BOX_COLOR);
+            }
+          }
+        }
+      }
+      let samples = collider.world_surface_samples(SAMPLE_RESOLUTION);
+      if samples.len() == 0 {
+        continue;
+      }
+      // small axis crosses, sized from the spacing of the samples
+      let size = collider.world_aabb_corners()
+        .map(|c| (c[7] - c[0]).magnitude() / SAMPLE_RESOLUTION as f32 * 0.2)
+        .unwrap_or(0.05);
+      for p in samples {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+          self.debug_renderer.draw_line(p - axis * size, p + axis * size, SAMPLE_COLOR);
+        }
+      }
+    }
+  }
+
+  // pass a cubemap texture (see Texture::cubemap_from_faces) to draw it as
+  // the scene background, or None to go back to the clear color
+  pub fn set_skybox(&mut self, cubemap: Option<Texture>) {
+    self.skybox = cubemap.map(|c| Skybox::new(&self.device, self.config.format, &self.camera_bind_group_layout, c));
+  }
+
+  // trace a ray against sdf geometry that isn't a collider (e.g. terrain)
+  pub fn raycast_sdf(&self, ray: Ray, sdf: &SdfShape, max_dist: f32) -> TraceResult {
+    self.raycast_manager.cast_against_sdf(&ray, sdf, max_dist)
+  }
+
+  pub fn light_position(&self) -> Vector3<f32> {
+    self.light_uniform.position.into()
+  }
+
+  pub fn set_light_position(&mut self, position: Vector3<f32>) {
+    self.light_uniform.position = position.into();
+    self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+  }
+
+  pub fn light_color(&self) -> Vector3<f32> {
+    self.light_uniform.color.into()
+  }
+
+  pub fn set_light_color(&mut self, color: Vector3<f32>) {
+    self.light_uniform.color = color.into();
+    self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+  }
+
+  // the light orbits the y axis by default. turn this off to keep a
+  // position set with set_light_position
+  pub fn set_light_auto_rotate(&mut self, auto_rotate: bool) {
+    self.light_auto_rotate = auto_rotate;
+  }
+
+  // (axis, degrees per second) from the app state, falling back to
+  // 60 deg/s around y if the values are missing or the wrong type
+  pub fn light_rotation(&self) -> (Vector3<f32>, f32) {
+    let speed = match self.app_state.get_state(&LIGHT_ROTATION_SPEED.into()) {
+      Some(State::Float(speed)) => *speed,
+      _ => 60.
+    };
+    let axis = match self.app_state.get_state(&LIGHT_ROTATION_AXIS.into()) {
+      Some(State::Vector3(axis)) if axis.magnitude2() > 0. => axis.normalize(),
+      _ => Vector3::unit_y()
+    };
+    (axis, speed)
+  }
+
+  pub fn shadows_enabled(&self) -> bool {
+    self.shadow_map.enabled()
+  }
+
+  pub fn set_shadows_enabled(&mut self, enabled: bool) {
+    self.shadow_map.update(&self.queue, self.light_uniform.position, enabled);
+  }
+
+  pub fn shading_mode(&self) -> ShadingMode {
+    match self.light_uniform.shading_mode {
+      1 => ShadingMode::Position,
+      _ => ShadingMode::Lit
+    }
+  }
+
+  // the shading mode rides along in the light uniform so switching it
+  // only needs a buffer write, not a new bind group
+  pub fn set_shading_mode(&mut self, mode: ShadingMode) {
+    self.light_uniform.shading_mode = mode as u32;
+    self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light_uniform]));
+  }
+}
+
+fn create_offscreen_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::Texture {
+  device.create_texture(&wgpu::TextureDescriptor {
+    label: Some("Offscreen texture"),
+    size: wgpu::Extent3d {
+      width: config.width,
+      height: config.height,
+      depth_or_array_layers: 1,
+    },
+    mip_level_count: 1,
+    sample_count: 1,
+    dimension: wgpu::TextureDimension::D2,
+    format: config.format,
+    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    view_formats: &[],
+  })
+}
+
+// adds dt to the accumulator and returns how many whole steps it covers,
+// leaving the remainder behind. the accumulator is capped at
+// MAX_FIXED_STEPS_PER_FRAME steps
+fn take_fixed_steps(accumulator: &mut instant::Duration, dt: instant::Duration, step: instant::Duration) -> u32 {
+  *accumulator = (*accumulator + dt).min(step * MAX_FIXED_STEPS_PER_FRAME);
+  let steps = (accumulator.as_nanos() / step.as_nanos()) as u32;
+  *accumulator -= step * steps;
+  steps
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use instant::Duration;
+
+  #[test]
+  fn fixed_steps_carry_the_remainder_forward() {
+    let step = Duration::from_millis(16);
+    let mut accumulator = Duration::ZERO;
+    assert_eq!(take_fixed_steps(&mut accumulator, Duration::from_millis(50), step), 3);
+    assert_eq!(accumulator, Duration::from_millis(2));
+    assert_eq!(take_fixed_steps(&mut accumulator, Duration::from_millis(14), step), 1);
+    assert_eq!(accumulator, Duration::ZERO);
+    assert_eq!(take_fixed_steps(&mut accumulator, Duration::from_millis(10), step), 0);
+    assert_eq!(accumulator, Duration::from_millis(10));
+  }
+
+  #[test]
+  fn fixed_steps_are_capped_per_frame() {
+    let step = Duration::from_millis(16);
+    let mut accumulator = Duration::from_millis(5);
+    assert_eq!(take_fixed_steps(&mut accumulator, Duration::from_secs(1), step), MAX_FIXED_STEPS_PER_FRAME);
+    // the backlog is dropped rather than carried into the next frame
+    assert_eq!(accumulator, Duration::ZERO);
+  }
 }
@@ -8,7 +8,8 @@ var<uniform> camera: CameraUniform;
 
 struct Light {
   position: vec3<f32>,
-  color: vec3<f32>
+  color: vec3<f32>,
+  shading_mode: u32,
 }
 @group(2) @binding(0)
 var<uniform> light: Light;
@@ -19,6 +20,7 @@ struct VertexInput {
   @location(2) normal: vec3<f32>,
   @location(3) tangent: vec3<f32>,
   @location(4) bitangent: vec3<f32>,
+  @location(13) color: vec3<f32>,
 }
 
 struct InstanceInput {
@@ -29,6 +31,9 @@ struct InstanceInput {
   @location(9) normal_matrix_0: vec3<f32>,
   @location(10) normal_matrix_1: vec3<f32>,
   @location(11) normal_matrix_2: vec3<f32>,
+  @location(12) opacity: f32,
+  @location(14) selected: u32,
+  @location(15) tint: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -37,6 +42,11 @@ struct VertexOutput {
   @location(1) tangent_position: vec3<f32>,
   @location(2) tangent_light_position: vec3<f32>,
   @location(3) tangent_view_position: vec3<f32>,
+  @location(4) world_position: vec3<f32>,
+  @location(5) opacity: f32,
+  @location(6) vertex_color: vec3<f32>,
+  @location(7) @interpolate(flat) selected: u32,
+  @location(8) tint: vec4<f32>,
 };
 
 @vertex
@@ -72,6 +82,11 @@ fn vs_main(
   out.tangent_position = tangent_matrix * world_position.xyz;
   out.tangent_view_position = tangent_matrix * camera.view_pos.xyz;
   out.tangent_light_position = tangent_matrix * light.position;
+  out.world_position = world_position.xyz;
+  out.opacity = instance.opacity;
+  out.vertex_color = model.color;
+  out.selected = instance.selected;
+  out.tint = instance.tint;
   return out;
 }
 
@@ -84,10 +99,91 @@ var s_diffuse: sampler;
 var t_normal: texture_2d<f32>;
 @group(0) @binding(3)
 var s_normal: sampler;
+@group(0) @binding(4)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(5)
+var s_metallic_roughness: sampler;
+@group(0) @binding(6)
+var t_emissive: texture_2d<f32>;
+@group(0) @binding(7)
+var s_emissive: sampler;
+
+struct Material {
+  metallic: f32,
+  roughness: f32,
+  flags: u32,
+}
+@group(0) @binding(8)
+var<uniform> material: Material;
+
+struct Shadow {
+  light_view_proj: mat4x4<f32>,
+  enabled: u32,
+}
+@group(3) @binding(0)
+var<uniform> shadow: Shadow;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
+// 1.0 when lit, 0.0 when fully occluded from the light
+fn shadow_factor(world_position: vec3<f32>) -> f32 {
+  if (shadow.enabled == 0u) {
+    return 1.0;
+  }
+  let light_space = shadow.light_view_proj * vec4<f32>(world_position, 1.0);
+  let proj = light_space.xyz / light_space.w;
+  let uv = proj.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+  // outside the shadow map counts as lit
+  if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0 || proj.z > 1.0) {
+    return 1.0;
+  }
+  return textureSampleCompareLevel(t_shadow, s_shadow, uv, proj.z);
+}
+
+const PI: f32 = 3.14159265;
+const FLAG_PBR: u32 = 1u;
+const FLAG_METALLIC_ROUGHNESS_TEXTURE: u32 = 2u;
+const FLAG_EMISSIVE_TEXTURE: u32 = 4u;
+
+// cook-torrance specular (ggx distribution, smith geometry, schlick fresnel)
+fn cook_torrance(
+  normal: vec3<f32>,
+  view_dir: vec3<f32>,
+  light_dir: vec3<f32>,
+  half_dir: vec3<f32>,
+  albedo: vec3<f32>,
+  metallic: f32,
+  roughness: f32,
+) -> vec3<f32> {
+  let alpha = max(roughness * roughness, 0.001);
+  let n_dot_h = max(dot(normal, half_dir), 0.0);
+  let n_dot_v = max(dot(normal, view_dir), 0.0001);
+  let n_dot_l = max(dot(normal, light_dir), 0.0);
+  let h_dot_v = max(dot(half_dir, view_dir), 0.0);
+
+  let a2 = alpha * alpha;
+  let d_denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  let distribution = a2 / (PI * d_denom * d_denom);
+
+  let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  let geometry = (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+
+  let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+  let fresnel = f0 + (1.0 - f0) * pow(1.0 - h_dot_v, 5.0);
+
+  return distribution * geometry * fresnel / (4.0 * n_dot_v * max(n_dot_l, 0.0001)) * n_dot_l;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-  var object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+  // position-gradient debug shading
+  if (light.shading_mode == 1u) {
+    return vec4<f32>(fract(in.world_position * 0.05), in.opacity);
+  }
+
+  var object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords) * vec4<f32>(in.vertex_color, 1.0) * in.tint;
   var object_normal = textureSample(t_normal, s_normal, in.tex_coords);
 
   let ambient_strength = 0.1;
@@ -100,15 +196,41 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
   let half_dir = normalize(view_dir + light_dir);
 
   let diffuse_strength = max(dot(tangent_normal, light_dir), 0.0);
-  let diffuse_color = light.color * diffuse_strength;
+  let shadow_amount = shadow_factor(in.world_position);
+  let diffuse_color = light.color * diffuse_strength * shadow_amount;
   // let reflect_dir = reflect(-light_dir, in.world_normal);
 
-  let specular_strength = pow(max(dot(tangent_normal, half_dir), 0.0), 32.0);
-  let specular_color = specular_strength * light.color;
-
-  let result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
-
-  return vec4<f32>(result, object_color.a);
+  var result: vec3<f32>;
+  if ((material.flags & FLAG_PBR) != 0u) {
+    var metallic = material.metallic;
+    var roughness = material.roughness;
+    if ((material.flags & FLAG_METALLIC_ROUGHNESS_TEXTURE) != 0u) {
+      let mr = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+      roughness = roughness * mr.g;
+      metallic = metallic * mr.b;
+    }
+    let albedo = object_color.xyz;
+    let specular = cook_torrance(normalize(tangent_normal), view_dir, light_dir, half_dir, albedo, metallic, roughness);
+    // metals have no diffuse term
+    let diffuse = (1.0 - metallic) * albedo * diffuse_color;
+    result = ambient_color * albedo + diffuse + specular * light.color * shadow_amount;
+  } else {
+    // original blinn-phong
+    let specular_strength = pow(max(dot(tangent_normal, half_dir), 0.0), 32.0);
+    let specular_color = specular_strength * light.color * shadow_amount;
+    result = (ambient_color + diffuse_color + specular_color) * object_color.xyz;
+  }
+  if ((material.flags & FLAG_EMISSIVE_TEXTURE) != 0u) {
+    result = result + textureSample(t_emissive, s_emissive, in.tex_coords).xyz;
+  }
+  // selection highlight: flat tint plus a rim that reads as an outline
+  if (in.selected != 0u) {
+    let highlight = vec3<f32>(1.0, 0.6, 0.1);
+    let rim = pow(1.0 - max(dot(normalize(tangent_normal), view_dir), 0.0), 3.0);
+    result = mix(result, highlight, 0.3) + highlight * rim;
+  }
+
+  return vec4<f32>(result, object_color.a * in.opacity);
 }
 
 @fragment
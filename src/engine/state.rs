@@ -6,5 +6,5 @@ pub use state::{
   State,
   StateListener
 };
-pub use store::Store;
-pub use app_state::create_app_state;
\ No newline at end of file
+pub use store::{Store, SubscriptionId};
+pub use app_state::{create_app_state, LIGHT_ROTATION_AXIS, LIGHT_ROTATION_SPEED};
\ No newline at end of file
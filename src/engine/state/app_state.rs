@@ -1,10 +1,19 @@
+use cgmath::Vector3;
+
 use crate::engine::util::random_quaternion;
 
 use super::{State, Store};
 
+// engine-read state. the scene reads these every frame so components can
+// change them through set_state
+pub const LIGHT_ROTATION_SPEED: &str = "light_rotation_speed"; // Float, degrees per second
+pub const LIGHT_ROTATION_AXIS: &str = "light_rotation_axis"; // Vector3
+
 pub fn create_app_state() -> Store {
   let fields: Vec<(String, State)> = Vec::from([
-    ("parent_rotation".into(), State::Quaternion(random_quaternion()))
+    ("parent_rotation".into(), State::Quaternion(random_quaternion())),
+    (LIGHT_ROTATION_SPEED.into(), State::Float(60.)),
+    (LIGHT_ROTATION_AXIS.into(), State::Vector3(Vector3::unit_y())),
   ]);
   Store::create(fields)
-}
\ No newline at end of file
+}
@@ -1,14 +1,51 @@
-use cgmath::Quaternion;
+use std::collections::HashMap;
+
+use cgmath::{Quaternion, Vector3};
 
 use crate::engine::{component_store::ComponentKey, errors::EngineError, Scene};
 
+use super::store::SubscriptionId;
+
 pub enum State {
   Integer ( i32 ),
   Float ( f32 ),
   Bool ( bool ),
   String ( String ),
   // user can define the rest of the types
-  Quaternion (Quaternion<f32>)
+  Quaternion (Quaternion<f32>),
+  Vector3 (Vector3<f32>),
+  List (Vec<State>),
+  Map (HashMap<String, State>)
+}
+
+impl State {
+  pub fn get_list(&self) -> Option<&Vec<State>> {
+    match self {
+      State::List(list) => Some(list),
+      _ => None
+    }
+  }
+
+  pub fn get_map(&self) -> Option<&HashMap<String, State>> {
+    match self {
+      State::Map(map) => Some(map),
+      _ => None
+    }
+  }
+
+  // lists and maps only match when their contents match element by element
+  // (or key by key), so a list of floats and a list of strings differ
+  pub fn same_type(&self, other: &State) -> bool {
+    match (self, other) {
+      (State::List(a), State::List(b)) => {
+        a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.same_type(y))
+      },
+      (State::Map(a), State::Map(b)) => {
+        a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).map(|w| v.same_type(w)).unwrap_or(false))
+      },
+      _ => std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+  }
 }
 
 pub trait StateListener {
@@ -17,7 +54,7 @@ pub trait StateListener {
     ()
   }
 
-  fn add_state_listener(&mut self, scene: &mut Scene, component_key: &ComponentKey, state_key: String) -> Result<(), EngineError> {
+  fn add_state_listener(&mut self, scene: &mut Scene, component_key: &ComponentKey, state_key: String) -> Result<SubscriptionId, EngineError> {
     let listener: fn(&mut dyn StateListener, key: String, state: &State) = |component: &mut dyn StateListener, key: String, state: &State| {
       component.handle_state_change(key, state);
     };
@@ -4,10 +4,17 @@ use crate::engine::{component::Component, component_store::{ComponentKey, Compon
 
 use super::state::{State, StateListener};
 
+// returned by listen and passed to unsubscribe to stop listening
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct SubscriptionId(u32);
+
 pub struct Store {
   state_map: HashMap<String, State>,
   state_listeners: HashMap<ComponentKey, HashMap<String, fn(&mut dyn StateListener, String, &State) -> ()>>,
   triggered_functions: HashMap<ComponentKey, Vec<(String, fn(&mut dyn StateListener, String, &State) -> ())>>,
+  subscriptions: HashMap<SubscriptionId, (ComponentKey, String)>,
+  next_subscription: u32,
+  computed: HashMap<String, (Vec<String>, fn(&Store) -> State)>, // key -> (dependencies, compute)
 }
 
 impl Store {
@@ -17,6 +24,9 @@ impl Store {
       state_map,
       state_listeners: HashMap::new(),
       triggered_functions: HashMap::new(),
+      subscriptions: HashMap::new(),
+      next_subscription: 0,
+      computed: HashMap::new(),
     }
   }
 
@@ -24,12 +34,48 @@ impl Store {
     self.state_map.insert(key, state)
   }
 
-  pub fn remove_state_key(&mut self, key: &String) -> Option<State> {
-    self.state_map.remove(key)
+  // keys that computed values still read can't be removed. remove the
+  // computed values first
+  pub fn remove_state_key(&mut self, key: &String) -> Result<Option<State>, EngineError> {
+    if let Some((dependent, _)) = self.computed.iter().find(|(_, (deps, _))| deps.contains(key)) {
+      return Err(EngineError::Custom(format!("Computed state {} depends on {}", dependent, key)))
+    }
+    self.computed.remove(key);
+    Ok(self.state_map.remove(key))
+  }
+
+  // adds a value derived from other state keys. it is computed right away
+  // and again whenever one of deps changes, notifying its own listeners
+  // like any other key. computed values can depend on each other, but not
+  // in a cycle
+  pub fn add_computed(&mut self, key: String, deps: Vec<String>, compute: fn(&Store) -> State) -> Result<(), EngineError> {
+    if deps.iter().any(|dep| !self.state_map.contains_key(dep)) {
+      return Err(EngineError::ArgumentError { index: 2, name: "deps".into() })
+    }
+    if deps.iter().any(|dep| self.depends_on(dep, &key)) {
+      return Err(EngineError::Custom(format!("Computed state {} depends on itself", key)))
+    }
+    let value = compute(self);
+    self.state_map.insert(key.clone(), value);
+    self.computed.insert(key.clone(), (deps, compute));
+    self.handle_state_change(key);
+    Ok(())
+  }
+
+  // whether key is state_key or is computed from it, directly or not
+  fn depends_on(&self, key: &String, state_key: &String) -> bool {
+    if key == state_key {
+      return true;
+    }
+    match self.computed.get(key) {
+      Some((deps, _)) => deps.iter().any(|dep| self.depends_on(dep, state_key)),
+      None => false
+    }
   }
 
   pub fn set_state(&mut self, key: String, val: State) -> Result<State, EngineError> {
-    if !self.state_map.contains_key(&key) {
+    // computed values are only written by their compute function
+    if !self.state_map.contains_key(&key) || self.computed.contains_key(&key) {
       return Err(EngineError::ArgumentError { index: 1, name: "key".into() })
     }
     if let Some(inserted) = self.state_map.insert(key.clone(), val) {
@@ -43,7 +89,60 @@ impl Store {
     self.state_map.get(key)
   }
 
-  pub fn listen(&mut self, component_key: ComponentKey, state_key: String, callback: fn(&mut dyn StateListener, String, &State) -> ()) -> Result<(), EngineError> {
+  // the list and map helpers below edit a collection in place and notify
+  // its listeners the same way set_state does
+  pub fn push_to_list(&mut self, key: String, val: State) -> Result<(), EngineError> {
+    match self.writable(&key)? {
+      State::List(list) => list.push(val),
+      _ => return Err(EngineError::ArgumentError { index: 1, name: "key".into() })
+    }
+    self.handle_state_change(key);
+    Ok(())
+  }
+
+  pub fn remove_from_list(&mut self, key: String, index: usize) -> Result<State, EngineError> {
+    let removed = match self.writable(&key)? {
+      State::List(list) if index < list.len() => list.remove(index),
+      State::List(_) => return Err(EngineError::ArgumentError { index: 2, name: "index".into() }),
+      _ => return Err(EngineError::ArgumentError { index: 1, name: "key".into() })
+    };
+    self.handle_state_change(key);
+    Ok(removed)
+  }
+
+  // returns the value previously stored under map_key, if any
+  pub fn insert_into_map(&mut self, key: String, map_key: String, val: State) -> Result<Option<State>, EngineError> {
+    let replaced = match self.writable(&key)? {
+      State::Map(map) => map.insert(map_key, val),
+      _ => return Err(EngineError::ArgumentError { index: 1, name: "key".into() })
+    };
+    self.handle_state_change(key);
+    Ok(replaced)
+  }
+
+  // listeners are only notified if map_key was actually present
+  pub fn remove_from_map(&mut self, key: String, map_key: &String) -> Result<Option<State>, EngineError> {
+    let removed = match self.writable(&key)? {
+      State::Map(map) => map.remove(map_key),
+      _ => return Err(EngineError::ArgumentError { index: 1, name: "key".into() })
+    };
+    if removed.is_some() {
+      self.handle_state_change(key);
+    }
+    Ok(removed)
+  }
+
+  // state a caller is allowed to modify, i.e. present and not computed
+  fn writable(&mut self, key: &String) -> Result<&mut State, EngineError> {
+    if self.computed.contains_key(key) {
+      return Err(EngineError::ArgumentError { index: 1, name: "key".into() })
+    }
+    self.state_map.get_mut(key).ok_or(EngineError::ArgumentError { index: 1, name: "key".into() })
+  }
+
+  // a component has at most one listener per state key, so listening to the
+  // same key again replaces the callback and invalidates the old id
+  pub fn listen(&mut self, component_key: ComponentKey, state_key: String, callback: fn(&mut dyn StateListener, String, &State) -> ()) -> Result<SubscriptionId, EngineError> {
     if !self.state_map.contains_key(&state_key) {
       return Err(EngineError::ArgumentError { index: 2, name: "state_key".into() })
     }
@@ -52,10 +151,43 @@ impl Store {
     }
     let listener_map = self.state_listeners.get_mut(&component_key).unwrap();
     let _ = listener_map.insert(state_key.clone(), callback);
-    return Ok(())
+    self.subscriptions.retain(|_, (comp, key)| *comp != component_key || *key != state_key);
+    let id = SubscriptionId(self.next_subscription);
+    self.next_subscription += 1;
+    self.subscriptions.insert(id, (component_key, state_key));
+    return Ok(id)
+  }
+
+  // removes a single listener, including any callback already queued for
+  // the next trigger_callbacks. returns false if the id is no longer active
+  pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+    let (component_key, state_key) = match self.subscriptions.remove(&id) {
+      Some(sub) => sub,
+      None => return false
+    };
+    if let Some(listener_map) = self.state_listeners.get_mut(&component_key) {
+      listener_map.remove(&state_key);
+      if listener_map.is_empty() {
+        self.state_listeners.remove(&component_key);
+      }
+    }
+    if let Some(pending) = self.triggered_functions.get_mut(&component_key) {
+      pending.retain(|(key, _)| *key != state_key);
+      if pending.is_empty() {
+        self.triggered_functions.remove(&component_key);
+      }
+    }
+    true
   }
 
 
+  // drops every listener and pending callback registered by a component
+  pub fn remove_component_listeners(&mut self, component: &ComponentKey) {
+    self.state_listeners.remove(component);
+    self.triggered_functions.remove(component);
+    self.subscriptions.retain(|_, (comp, _)| comp != component);
+  }
+
   pub fn trigger_callbacks(&mut self, components: &mut ComponentStore) -> Result<(), EngineError> {
     for (key, callback_tuples) in self.triggered_functions.iter() {
       let component: &mut dyn StateListener = components.get_mut(key).unwrap();
@@ -90,5 +222,209 @@ impl Store {
         }
       }
     }
+    self.update_dependents(&state_key);
+  }
+
+  // recomputes every computed value that reads state_key. add_computed
+  // rejects cycles, so this always terminates
+  fn update_dependents(&mut self, state_key: &String) {
+    let dependents = self.computed.iter()
+      .filter(|(_, (deps, _))| deps.contains(state_key))
+      .map(|(key, (_, compute))| (key.clone(), *compute))
+      .collect::<Vec<_>>();
+    for (key, compute) in dependents {
+      let value = compute(self);
+      self.state_map.insert(key.clone(), value);
+      self.handle_state_change(key);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::test_util::{record_state, recorder_store};
+
+  fn noop(_: &mut dyn StateListener, _: String, _: &State) {}
+
+  fn store() -> Store {
+    Store::create(vec![("count".into(), State::Integer(1))])
+  }
+
+  #[test]
+  fn set_state_replaces_known_keys_only() {
+    let mut store = store();
+    assert!(matches!(store.set_state("count".into(), State::Integer(2)), Ok(State::Integer(1))));
+    assert!(matches!(store.get_state(&"count".into()), Some(State::Integer(2))));
+    assert!(store.set_state("missing".into(), State::Integer(2)).is_err());
+  }
+
+  #[test]
+  fn unsubscribed_listeners_stop_firing() {
+    let mut store = store();
+    let (mut components, recorders) = recorder_store(1);
+    let (key, recorder) = &recorders[0];
+    let id = store.listen(*key, "count".into(), record_state).unwrap();
+
+    store.set_state("count".into(), State::Integer(2)).unwrap();
+    store.trigger_callbacks(&mut components).unwrap();
+    assert_eq!(recorder.lock().unwrap().state_changes, vec!["count".to_string()]);
+
+    assert!(store.unsubscribe(id));
+    store.set_state("count".into(), State::Integer(3)).unwrap();
+    store.trigger_callbacks(&mut components).unwrap();
+    assert_eq!(recorder.lock().unwrap().state_changes.len(), 1);
+  }
+
+  #[test]
+  fn unsubscribing_drops_queued_callbacks() {
+    let mut store = store();
+    let key = ComponentKey { index: 0, generation: 0 };
+    assert!(store.listen(key, "missing".into(), noop).is_err());
+    let id = store.listen(key, "count".into(), noop).unwrap();
+    store.set_state("count".into(), State::Integer(2)).unwrap();
+    assert_eq!(store.triggered_functions.get(&key).map(|pending| pending.len()), Some(1));
+    assert!(store.unsubscribe(id));
+    assert!(!store.triggered_functions.contains_key(&key));
+    assert!(!store.unsubscribe(id));
+  }
+
+  #[test]
+  fn listening_again_replaces_the_old_subscription() {
+    let mut store = store();
+    let key = ComponentKey { index: 0, generation: 0 };
+    let first = store.listen(key, "count".into(), noop).unwrap();
+    let second = store.listen(key, "count".into(), noop).unwrap();
+    assert!(!store.unsubscribe(first));
+    assert!(store.unsubscribe(second));
+  }
+
+  fn floats(state: Option<&State>) -> Vec<f32> {
+    state.and_then(|s| s.get_list()).unwrap().iter().map(|v| match v {
+      State::Float(f) => *f,
+      _ => f32::NAN
+    }).collect()
+  }
+
+  #[test]
+  fn float_lists_read_back() {
+    let mut store = Store::create(vec![("samples".into(), State::List(vec![State::Float(0.5), State::Float(1.5)]))]);
+    assert_eq!(floats(store.get_state(&"samples".into())), vec![0.5, 1.5]);
+    assert!(store.get_state(&"samples".into()).unwrap().get_map().is_none());
+
+    store.push_to_list("samples".into(), State::Float(2.5)).unwrap();
+    assert_eq!(floats(store.get_state(&"samples".into())), vec![0.5, 1.5, 2.5]);
+    assert!(matches!(store.remove_from_list("samples".into(), 0), Ok(State::Float(f)) if f == 0.5));
+    assert_eq!(floats(store.get_state(&"samples".into())), vec![1.5, 2.5]);
+    assert!(store.remove_from_list("samples".into(), 5).is_err());
+  }
+
+  #[test]
+  fn collection_edits_notify_listeners() {
+    let mut store = Store::create(vec![
+      ("items".into(), State::List(Vec::new())),
+      ("scores".into(), State::Map(HashMap::new())),
+      ("count".into(), State::Integer(0)),
+    ]);
+    let (mut components, recorders) = recorder_store(1);
+    let (key, recorder) = &recorders[0];
+    store.listen(*key, "items".into(), record_state).unwrap();
+    store.listen(*key, "scores".into(), record_state).unwrap();
+
+    store.push_to_list("items".into(), State::String("sword".into())).unwrap();
+    store.trigger_callbacks(&mut components).unwrap();
+    assert!(matches!(store.insert_into_map("scores".into(), "ana".into(), State::Integer(3)), Ok(None)));
+    store.trigger_callbacks(&mut components).unwrap();
+    assert!(matches!(store.insert_into_map("scores".into(), "ana".into(), State::Integer(4)), Ok(Some(State::Integer(3)))));
+    store.trigger_callbacks(&mut components).unwrap();
+    assert!(matches!(store.remove_from_map("scores".into(), &"ana".into()), Ok(Some(State::Integer(4)))));
+    store.trigger_callbacks(&mut components).unwrap();
+    // nothing to remove, so nothing changed
+    assert!(matches!(store.remove_from_map("scores".into(), &"ana".into()), Ok(None)));
+    store.trigger_callbacks(&mut components).unwrap();
+    assert!(matches!(store.remove_from_list("items".into(), 0), Ok(State::String(_))));
+    store.trigger_callbacks(&mut components).unwrap();
+
+    assert_eq!(recorder.lock().unwrap().state_changes, vec!["items", "scores", "scores", "scores", "items"]);
+    assert!(store.get_state(&"scores".into()).unwrap().get_map().unwrap().is_empty());
+  }
+
+  #[test]
+  fn collection_edits_need_the_right_variant() {
+    let mut store = Store::create(vec![("count".into(), State::Integer(0))]);
+    assert!(store.push_to_list("count".into(), State::Integer(1)).is_err());
+    assert!(store.insert_into_map("count".into(), "a".into(), State::Integer(1)).is_err());
+    assert!(store.push_to_list("missing".into(), State::Integer(1)).is_err());
+  }
+
+  #[test]
+  fn collections_compare_structurally() {
+    let floats = State::List(vec![State::Float(1.), State::Float(2.)]);
+    let more_floats = State::List(vec![State::Float(3.), State::Float(4.)]);
+    let strings = State::List(vec![State::String("a".into()), State::String("b".into())]);
+    assert!(floats.same_type(&more_floats));
+    assert!(!floats.same_type(&strings));
+  }
+
+  fn double(store: &Store) -> State {
+    match store.get_state(&"count".into()) {
+      Some(State::Integer(count)) => State::Integer(count * 2),
+      _ => State::Integer(0)
+    }
+  }
+
+  fn health_fraction(store: &Store) -> State {
+    match (store.get_state(&"health".into()), store.get_state(&"max_health".into())) {
+      (Some(State::Float(health)), Some(State::Float(max))) if *max > 0. => State::Float(health / max),
+      _ => State::Float(0.)
+    }
+  }
+
+  #[test]
+  fn computed_values_update_and_notify_with_their_deps() {
+    let mut store = Store::create(vec![
+      ("health".into(), State::Float(50.)),
+      ("max_health".into(), State::Float(100.)),
+    ]);
+    store.add_computed("health_fraction".into(), vec!["health".into(), "max_health".into()], health_fraction).unwrap();
+    assert!(matches!(store.get_state(&"health_fraction".into()), Some(State::Float(f)) if *f == 0.5));
+
+    let (mut components, recorders) = recorder_store(1);
+    let (key, recorder) = &recorders[0];
+    store.listen(*key, "health_fraction".into(), record_state).unwrap();
+    store.set_state("health".into(), State::Float(25.)).unwrap();
+    assert!(matches!(store.get_state(&"health_fraction".into()), Some(State::Float(f)) if *f == 0.25));
+    store.trigger_callbacks(&mut components).unwrap();
+    assert_eq!(recorder.lock().unwrap().state_changes, vec!["health_fraction".to_string()]);
+  }
+
+  #[test]
+  fn computed_values_follow_their_deps() {
+    let mut store = store();
+    store.add_computed("double".into(), vec!["count".into()], double).unwrap();
+    assert!(matches!(store.get_state(&"double".into()), Some(State::Integer(2))));
+    store.set_state("count".into(), State::Integer(5)).unwrap();
+    assert!(matches!(store.get_state(&"double".into()), Some(State::Integer(10))));
+    // only the compute function writes a computed value
+    assert!(store.set_state("double".into(), State::Integer(0)).is_err());
+  }
+
+  #[test]
+  fn computed_values_reject_missing_deps_and_cycles() {
+    let mut store = store();
+    assert!(store.add_computed("double".into(), vec!["missing".into()], double).is_err());
+    store.add_computed("double".into(), vec!["count".into()], double).unwrap();
+    store.add_computed("quad".into(), vec!["double".into()], double).unwrap();
+    assert!(store.add_computed("double".into(), vec!["quad".into()], double).is_err());
+  }
+
+  #[test]
+  fn keys_with_computed_dependents_cant_be_removed() {
+    let mut store = store();
+    store.add_computed("double".into(), vec!["count".into()], double).unwrap();
+    assert!(store.remove_state_key(&"count".into()).is_err());
+    assert!(store.get_state(&"count".into()).is_some());
+    assert!(matches!(store.remove_state_key(&"double".into()), Ok(Some(State::Integer(2)))));
+    assert!(matches!(store.remove_state_key(&"count".into()), Ok(Some(State::Integer(1)))));
   }
 }
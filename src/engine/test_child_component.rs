@@ -36,9 +36,9 @@ impl ComponentFunctions for TestChildComponent {
       self.model = None;
     }
 
-    let collision_sdf = SdfShape::new(Shape::Cube { center: Point3::new(0., 0., 0.), half_bounds:  Vector3::new(20., 20., 20.)}, CubeSdf);
-    let collision_boundary = SdfBoundary::new(Point3::new(0., 0., 0.), collision_sdf);
-    self.collider = Some(scene.collision_manager.add_component_collider(collision_boundary, key, None));
+    let collision_sdf = SdfShape::new(Shape::Cube { center: Point3::new(0., 0., 0.), half_bounds:  Vector3::new(20., 20., 20.)}, CubeSdf).unwrap();
+    let collision_boundary = SdfBoundary::from_shape(collision_sdf);
+    self.collider = Some(scene.collision_manager.add_component_collider(collision_boundary, key, None, false));
 
     let _ = self.add_event_listener(scene, &key, &EventKey::KeyboardEvent);
   }
@@ -84,11 +84,14 @@ impl TestChildComponent {
 impl EventListener for TestChildComponent {
   fn handle_event(&mut self, event: super::events::Event) {
       match event.data {
-        EventData::KeyboardEvent (KeyboardInput {
-          virtual_keycode: Some(key),
-          state,
+        EventData::KeyboardEvent {
+          input: KeyboardInput {
+            virtual_keycode: Some(key),
+            state,
+            ..
+          },
           ..
-        }) => {
+        } => {
           if state == ElementState::Pressed {
             self.should_set_state = true;
           }
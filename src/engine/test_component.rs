@@ -48,10 +48,13 @@ impl ComponentFunctions for TestComponent {
       Vector3::new(20., 0., 0.), 
       Quaternion::new(5., 0., 0., 0.)
     );
+    if let Some(child) = self.child.as_ref() {
+      scene.set_local_transform(child.key, self.child_pos.clone());
+    }
 
-    let collision_sdf = SdfShape::new(Shape::Cube { center: Point3::new(0., 0., 0.), half_bounds:  Vector3::new(20., 20., 20.)}, CubeSdf);
-    let collision_boundary = SdfBoundary::new(Point3::new(0., 0., 0.), collision_sdf);
-    self.collider = Some(scene.collision_manager.add_component_collider(collision_boundary, key, None));
+    let collision_sdf = SdfShape::new(Shape::Cube { center: Point3::new(0., 0., 0.), half_bounds:  Vector3::new(20., 20., 20.)}, CubeSdf).unwrap();
+    let collision_boundary = SdfBoundary::from_shape(collision_sdf);
+    self.collider = Some(scene.collision_manager.add_component_collider(collision_boundary, key, None, false));
     
     let _ = self.add_event_listener(scene, &key, &EventKey::KeyboardEvent);
     let _ = self.add_event_listener(scene, &key, &EventKey::CollisionStartEvent);
@@ -59,7 +62,13 @@ impl ComponentFunctions for TestComponent {
   }
 
   fn update(&mut self, scene: &mut Scene, dt: instant::Duration) {
-    ()
+    // the child is rendered by the scene graph, so just keep its local transform current
+    if let Some(child) = self.child.as_ref() {
+      scene.set_local_transform(child.key, self.child_pos.clone());
+    }
+    if let (Some(collider), Some(transform)) = (self.collider.clone(), self.model_pos.clone()) {
+      scene.set_collider_transform(&collider, transform.pos, transform.rot);
+    }
   }
 
   fn render(&self, scene: &mut Scene) -> Result<(), EngineError> {
@@ -67,32 +76,25 @@ impl ComponentFunctions for TestComponent {
       // println!("No model to render");
       return Ok(());
     }
-    if let Some(collider) = self.collider.clone() {
-      if let Some(transform) = self.model_pos.clone() {
-        collider.write().unwrap().update_transform(transform.pos, transform.rot)
-      }
-    }
-
     let res: Result<(), EngineError> = scene.render_model(&self.model.as_ref().unwrap(), self.model_pos.clone().unwrap_or(ModelTransform::default()));
-    if let Err(e) = res {
-        return Err(e);
-    }
-    if let Some(child_safe) = self.child.clone() {
-      return child_safe.render(scene, Some(self.child_pos.clone()));
-    }
-    Ok(())
+    res
   }
 }
 
 impl EventListener for TestComponent {
   fn handle_event(&mut self, event: Event) {
     match event.data {
-      EventData::KeyboardEvent(KeyboardInput {
-        virtual_keycode: Some(key),
-        state,
+      EventData::KeyboardEvent {
+        input: KeyboardInput {
+          virtual_keycode: Some(key),
+          state,
+          ..
+        },
         ..
-      }) => {
+      } => {
         if state == ElementState::Pressed {
+          // note: P also toggles the scene's ShadingMode from the event loop,
+          // which makes the child's new position easy to see in Position mode
           // randomize child position in spherical orbit around origin
           let mut radius: f32 = 40.;
           if key == VirtualKeyCode::K {
@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cgmath::Point3;
+
+use crate::sdf::{SdfShape, Shape, SphereSdf};
+
+use super::{collisions::SdfBoundary, component::{Component, ComponentFunctions}, component_store::{ComponentKey, ComponentStore}, events::{Event, EventListener}, state::{State, StateListener}, Scene};
+
+// Component that keeps everything it's handed, for tests that need to see
+// what a listener received
+pub struct Recorder {
+  pub key: ComponentKey,
+  pub events: Vec<Event>,
+  pub state_changes: Vec<String>, // state keys, in callback order
+  // shared between recorders to see the order they were called in
+  pub call_log: Option<Arc<Mutex<Vec<ComponentKey>>>>,
+}
+
+impl Recorder {
+  pub fn new() -> Recorder {
+    Self {
+      key: ComponentKey::zero(),
+      events: Vec::new(),
+      state_changes: Vec::new(),
+      call_log: None,
+    }
+  }
+
+  fn log_call(&self) {
+    if let Some(log) = &self.call_log {
+      log.lock().unwrap().push(self.key);
+    }
+  }
+}
+
+#[async_trait(?Send)]
+impl ComponentFunctions for Recorder {
+  async fn init(&mut self, _scene: &mut Scene, _key: ComponentKey, _parent: Option<ComponentKey>) {}
+}
+
+impl EventListener for Recorder {
+  fn handle_event(&mut self, event: Event) {
+    self.log_call();
+    self.events.push(event);
+  }
+}
+
+impl StateListener for Recorder {
+  fn handle_state_change(&mut self, key: String, _state: &State) {
+    self.log_call();
+    self.state_changes.push(key);
+  }
+}
+
+// the callbacks EventListener and StateListener register, for tests that
+// talk to the managers directly
+pub fn record_event(listener: &mut dyn EventListener, event: Event) {
+  listener.handle_event(event);
+}
+
+pub fn record_state(listener: &mut dyn StateListener, key: String, state: &State) {
+  listener.handle_state_change(key, state);
+}
+
+// a store holding count recorders, along with their keys and handles
+pub fn recorder_store(count: usize) -> (ComponentStore, Vec<(ComponentKey, Arc<Mutex<Recorder>>)>) {
+  let mut store = ComponentStore::new();
+  let mut recorders = Vec::new();
+  for _ in 0..count {
+    let recorder = Arc::new(Mutex::new(Recorder::new()));
+    let key = store.insert(Component::wrap(recorder.clone(), ComponentKey::zero())).unwrap();
+    store.insert_with_key(Component::wrap(recorder.clone(), key), key);
+    recorder.lock().unwrap().key = key;
+    recorders.push((key, recorder));
+  }
+  (store, recorders)
+}
+
+// has every recorder append its key to one shared log when called
+pub fn share_call_log(recorders: &Vec<(ComponentKey, Arc<Mutex<Recorder>>)>) -> Arc<Mutex<Vec<ComponentKey>>> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  for (_, recorder) in recorders.iter() {
+    recorder.lock().unwrap().call_log = Some(log.clone());
+  }
+  log
+}
+
+// sphere collider boundary, centered in its own local space at center
+pub fn sphere_boundary(center: Point3<f32>, rad: f32) -> SdfBoundary {
+  SdfBoundary::from_shape(SdfShape::new(Shape::Sphere { center, rad }, SphereSdf).unwrap())
+}
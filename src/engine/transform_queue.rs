@@ -1,35 +1,86 @@
-use cgmath::{Matrix, Matrix3, Matrix4, Point3, Quaternion, SquareMatrix, Vector3};
+use cgmath::{ElementWise, InnerSpace, Matrix, Matrix3, Matrix4, Point3, Quaternion, SquareMatrix, Vector3};
 
-use super::transforms::{ComponentTransform, ModelTransform, TransformType};
+use std::collections::HashMap;
+
+use super::{component_store::ComponentKey, transforms::{ComponentTransform, ModelTransform, TransformType}};
 
 use crate::graphics::Instance;
 use cgmath::Transform;
 
+// world matrix of a component as of the last time it was pushed, along
+// with what it was computed from
+struct CachedTransform {
+  parent: Option<ComponentKey>,
+  parent_world: Matrix4<f32>,
+  local: ComponentTransform,
+  world: Matrix4<f32>,
+}
+
 pub struct TransformQueue {
-  queue: Vec<ComponentTransform>
+  queue: Vec<ComponentTransform>,
+  keys: Vec<ComponentKey>,
+  accumulated: Vec<Matrix4<f32>>, // world matrix at each depth of the queue
+  cache: HashMap<ComponentKey, CachedTransform>,
+  recomputed: usize, // number of world matrices rebuilt, for profiling
 }
 
 impl TransformQueue {
   pub fn new() -> TransformQueue {
     Self {
-      queue: Vec::new()
+      queue: Vec::new(),
+      keys: Vec::new(),
+      accumulated: Vec::new(),
+      cache: HashMap::new(),
+      recomputed: 0,
     }
   }
 
-  pub fn push(&mut self, transform: ComponentTransform) {
-    // println!("Adding transform");
-    self.queue.push(transform)
+  // the world matrix is only rebuilt when the component's own transform,
+  // its parent, or its parent's world matrix changed since it was last
+  // pushed. comparing against the parent's matrix (rather than whether the
+  // parent changed this frame) keeps children that skip frames correct
+  pub fn push(&mut self, key: ComponentKey, transform: ComponentTransform) {
+    let parent = self.keys.last().cloned();
+    let parent_world = self.accumulated.last().cloned().unwrap_or(Matrix4::identity());
+    let cached = self.cache.get(&key)
+      .filter(|c| c.parent == parent && c.parent_world == parent_world && c.local == transform)
+      .map(|c| c.world);
+    let world = match cached {
+      Some(world) => world,
+      None => {
+        let world = parent_world * transform.to_matrix();
+        self.cache.insert(key, CachedTransform { parent, parent_world, local: transform, world });
+        self.recomputed += 1;
+        world
+      }
+    };
+    self.queue.push(transform);
+    self.keys.push(key);
+    self.accumulated.push(world);
   }
 
   pub fn pop(&mut self) -> Option<ComponentTransform> {
-    // println!("Removing transform");
+    self.keys.pop();
+    self.accumulated.pop();
     self.queue.pop()
   }
 
+  // drops the cached matrix of a component that no longer exists
+  pub fn remove(&mut self, key: &ComponentKey) {
+    self.cache.remove(key);
+  }
+
+  pub fn recomputed_count(&self) -> usize {
+    self.recomputed
+  }
+
   pub fn get_transform_matrix(&self) -> Matrix4<f32> {
-    let mat = self.queue.iter().fold(Matrix4::identity(), |acc, e| acc * e.to_matrix());
-    // println!("transform matrix: {:?}", mat);
-    mat
+    self.accumulated.last().cloned().unwrap_or(Matrix4::identity())
+  }
+
+  // accumulated scale of every transform in the queue
+  pub fn get_scale(&self) -> Vector3<f32> {
+    self.queue.iter().fold(Vector3::new(1., 1., 1.), |acc, e| acc.mul_element_wise(e.scale))
   }
 
   pub fn transform_model(&self, model_transform: &ModelTransform) -> ModelTransform {
@@ -41,13 +92,21 @@ impl TransformQueue {
       if transform_type == TransformType::Global {
         return model_transform.clone();
       }
+      let queue_scale = self.get_scale();
+      // every instance is carried from its own position. the transform's pos
+      // is only the first instance's
       let instances_transformed = instances.iter()
         .map(|i| Instance {
           rotation: apply_quaternion_transform(&self.get_transform_matrix(), i.rotation),
-          position: to_vec(self.get_transform_matrix().transform_point(to_point(pos)))
+          position: to_vec(self.get_transform_matrix().transform_point(to_point(i.position))),
+          scale: queue_scale.mul_element_wise(i.scale),
+          color: i.color
         })
         .collect::<Vec<Instance>>();
-      return ModelTransform::instanced(instances_transformed, transform_type);
+      return ModelTransform::instanced(instances_transformed, transform_type)
+        .opacity(model_transform.opacity)
+        .tint(model_transform.tint)
+        .mesh_filter(model_transform.mesh_filter.clone());
     } else {
       if transform_type == TransformType::Global {
         return model_transform.clone();
@@ -55,18 +114,25 @@ impl TransformQueue {
       let rot_transformed = apply_quaternion_transform(&self.get_transform_matrix(), rot);
       let pos_transformed = to_vec(self.get_transform_matrix().transform_point(to_point(pos)));
       // println!("Queue applied transform to single model. initial pos: {:?}, new pos: {:?}", pos, pos_transformed);
-      return ModelTransform::local(pos_transformed, rot_transformed);
+      let scale_transformed = self.get_scale().mul_element_wise(model_transform.scale);
+      return ModelTransform::local(pos_transformed, rot_transformed)
+        .scale(scale_transformed)
+        .opacity(model_transform.opacity)
+        .tint(model_transform.tint)
+        .mesh_filter(model_transform.mesh_filter.clone());
     }
   }
 }
 
 pub fn apply_quaternion_transform(transform: &Matrix4<f32>, rotation: Quaternion<f32>) -> Quaternion<f32> {
   let rotation_matrix = Matrix3::from(rotation);
-  // Extract the upper-left 3x3 submatrix of the transformation matrix
-  let upper_left = Matrix3::new(
-    transform.x.x, transform.x.y, transform.x.z,
-    transform.y.x, transform.y.y, transform.y.z,
-    transform.z.x, transform.z.y, transform.z.z,
+  // Extract the upper-left 3x3 submatrix of the transformation matrix,
+  // normalizing the columns so any scale doesn't end up in the rotation
+  let unscaled = |v: Vector3<f32>| if v.magnitude2() > 0. { v.normalize() } else { v };
+  let upper_left = Matrix3::from_cols(
+    unscaled(transform.x.truncate()),
+    unscaled(transform.y.truncate()),
+    unscaled(transform.z.truncate()),
   );
 
   // Apply the transformation to the rotation matrix
@@ -82,4 +148,57 @@ pub fn to_point(v: Vector3<f32>) -> Point3<f32> {
 
 pub fn to_vec(v: Point3<f32>) -> Vector3<f32> {
   Vector3::new(v.x, v.y, v.z)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::graphics::identity_rotation;
+
+  fn key(index: u32) -> ComponentKey {
+    ComponentKey { index, generation: 0 }
+  }
+
+  fn at(x: f32) -> ComponentTransform {
+    ComponentTransform::local(Vector3::new(x, 0., 0.), identity_rotation())
+  }
+
+  fn push_pair(queue: &mut TransformQueue, parent_x: f32) -> Matrix4<f32> {
+    queue.push(key(1), at(parent_x));
+    queue.push(key(2), at(1.));
+    let child_world = queue.get_transform_matrix();
+    queue.pop();
+    queue.pop();
+    child_world
+  }
+
+  #[test]
+  fn unchanged_hierarchies_reuse_cached_matrices() {
+    let mut queue = TransformQueue::new();
+    push_pair(&mut queue, 0.);
+    assert_eq!(queue.recomputed_count(), 2);
+    push_pair(&mut queue, 0.);
+    assert_eq!(queue.recomputed_count(), 2);
+
+    // only the parent moved, but the child is rebuilt along with it
+    let child_world = push_pair(&mut queue, 5.);
+    assert_eq!(queue.recomputed_count(), 4);
+    assert_eq!(child_world.transform_point(Point3::new(0., 0., 0.)), Point3::new(6., 0., 0.));
+  }
+
+  #[test]
+  fn children_skipped_while_the_parent_moved_are_not_stale() {
+    let mut queue = TransformQueue::new();
+    push_pair(&mut queue, 0.);
+
+    // the parent moves on a frame its child isn't pushed
+    queue.push(key(1), at(5.));
+    queue.pop();
+    queue.push(key(1), at(5.));
+    let child_world = {
+      queue.push(key(2), at(1.));
+      queue.get_transform_matrix()
+    };
+    assert_eq!(child_world.transform_point(Point3::new(0., 0., 0.)), Point3::new(6., 0., 0.));
+  }
+}
@@ -17,5 +17,5 @@ pub struct GlobalTransform {
 }
 
 pub use component_transform::ComponentTransform;
-pub use model_transform::ModelTransform;
+pub use model_transform::{BillboardMode, ModelTransform};
 pub use collider_transform::ColliderTransform;
\ No newline at end of file
@@ -1,6 +1,6 @@
 use cgmath::{Matrix4, Quaternion, Vector3};
 
-use crate::engine::component_store::ComponentKey;
+use crate::{engine::component_store::ComponentKey, graphics::identity_rotation};
 
 use super::GlobalTransform;
 
@@ -71,7 +71,7 @@ impl ColliderTransform {
     Self {
       parent,
       relative_pos: Vector3::new(0., 0., 0.),
-      relative_rot: Quaternion::new(0., 0., 0., 0.),
+      relative_rot: identity_rotation(),
       cached_global_pos: None,
       cached_global_rot: None
     }
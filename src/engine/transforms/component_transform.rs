@@ -1,4 +1,5 @@
 use cgmath::{Matrix, Matrix4, Quaternion, Vector3};
+use crate::graphics::identity_rotation;
 use super::TransformType;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -6,6 +7,7 @@ pub struct ComponentTransform {
   pub transform_type: TransformType,
   pub pos: Vector3<f32>,
   pub rot: Quaternion<f32>,
+  pub scale: Vector3<f32>,
 }
 
 impl ComponentTransform {
@@ -13,7 +15,8 @@ impl ComponentTransform {
     Self {
       transform_type: TransformType::Local,
       pos,
-      rot
+      rot,
+      scale: Vector3::new(1., 1., 1.)
     }
   }
 
@@ -21,7 +24,8 @@ impl ComponentTransform {
     Self {
       transform_type: TransformType::Global,
       pos,
-      rot
+      rot,
+      scale: Vector3::new(1., 1., 1.)
     }
 }
 
@@ -29,14 +33,22 @@ impl ComponentTransform {
     Self {
       transform_type: TransformType::Local,
       pos: Vector3::new(0., 0., 0.),
-      rot: Quaternion::new(0., 0., 0., 0.)
+      rot: identity_rotation(),
+      scale: Vector3::new(1., 1., 1.)
     }
   }
 
+  // scales everything rendered under this component, children included
+  pub fn scale(mut self, scale: Vector3<f32>) -> ComponentTransform {
+    self.scale = scale;
+    self
+  }
+
   pub fn to_matrix(&self) -> cgmath::Matrix4<f32> {
     let rotation_mat = Matrix4::from(self.rot);
     let translation_mat: Matrix4<f32> = Matrix4::from_translation(self.pos);
-    let combined = translation_mat * rotation_mat;
+    let scale_mat = Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+    let combined = translation_mat * rotation_mat * scale_mat;
     // println!("Rotation matrix: {:?}, Translation: {:?}, Combined: {:?}", rotation_mat, translation_mat, combined);
     combined
   }
@@ -1,14 +1,29 @@
 use cgmath::{Matrix, Matrix4, Quaternion, Vector3};
-use crate::graphics::Instance;
+use crate::graphics::{identity_rotation, Instance};
 use super::TransformType;
 
+// how a billboarded model turns to face the camera. the model's local +z
+// is pointed back at the camera
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BillboardMode {
+  // faces the camera fully, tilting with its pitch
+  Spherical,
+  // only turns about the world y axis, so the model stays upright
+  Cylindrical,
+}
+
 #[derive(Clone, PartialEq)]
 pub struct ModelTransform {
   pub transform_type: TransformType,
   pub pos: Vector3<f32>,
   pub rot: Quaternion<f32>,
   pub instances: Vec<Instance>,
-  pub instanced: bool
+  pub instanced: bool,
+  pub opacity: f32,
+  pub scale: Vector3<f32>,
+  pub billboard: Option<BillboardMode>,
+  pub tint: [f32; 4],
+  pub mesh_filter: Option<Vec<usize>>, // indices into the model's meshes to draw, None draws all
 }
 
 impl ModelTransform {
@@ -19,9 +34,16 @@ impl ModelTransform {
       rot,
       instances: Vec::from([Instance {
         position: pos,
-        rotation: rot
+        rotation: rot,
+        scale: Vector3::new(1., 1., 1.),
+        color: [1., 1., 1., 1.]
       }]),
-      instanced: false
+      instanced: false,
+      opacity: 1.,
+      scale: Vector3::new(1., 1., 1.),
+      billboard: None,
+      tint: [1., 1., 1., 1.],
+      mesh_filter: None
     }
   }
 
@@ -32,25 +54,88 @@ impl ModelTransform {
       rot,
       instances: Vec::from([Instance {
         position: pos,
-        rotation: rot
+        rotation: rot,
+        scale: Vector3::new(1., 1., 1.),
+        color: [1., 1., 1., 1.]
       }]),
-      instanced: false
+      instanced: false,
+      opacity: 1.,
+      scale: Vector3::new(1., 1., 1.),
+      billboard: None,
+      tint: [1., 1., 1., 1.],
+      mesh_filter: None
     }
   }
 
+  // local transform rotated by euler angles in degrees (see Instance::from_euler)
+  pub fn from_euler(pos: Vector3<f32>, pitch: f32, yaw: f32, roll: f32) -> ModelTransform {
+    Self::local(pos, Instance::from_euler(pos, pitch, yaw, roll).rotation)
+  }
+
+  // local transform turning the model's +z towards target (see Instance::looking_at)
+  pub fn looking_at(pos: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) -> ModelTransform {
+    Self::local(pos, Instance::looking_at(pos, target, up).rotation)
+  }
+
+  // each instance keeps its own position, rotation and scale. scale()
+  // leaves them alone
   pub fn instanced(instances: Vec<Instance>, transform_type: TransformType) -> ModelTransform {
-    let default_inst = Instance {
-      position: Vector3::new(0., 0., 0.),
-      rotation: Quaternion::new(0., 0., 0., 0.)
-    };
+    let default_inst = Instance::default();
     let first_instance = instances.get(0).unwrap_or(&default_inst);
     Self {
       transform_type,
       pos: first_instance.position,
       rot: first_instance.rotation,
       instances,
-      instanced: false
+      instanced: true,
+      opacity: 1.,
+      scale: Vector3::new(1., 1., 1.),
+      billboard: None,
+      tint: [1., 1., 1., 1.],
+      mesh_filter: None
+    }
+  }
+
+  // models with opacity < 1 are alpha blended and drawn after opaque models
+  pub fn opacity(mut self, opacity: f32) -> ModelTransform {
+    self.opacity = opacity.clamp(0., 1.);
+    self
+  }
+
+  // scale applied to a single (non instanced) model. instanced models
+  // carry their scale on each Instance
+  pub fn scale(mut self, scale: Vector3<f32>) -> ModelTransform {
+    self.scale = scale;
+    if !self.instanced {
+      for instance in self.instances.iter_mut() {
+        instance.scale = scale;
+      }
     }
+    self
+  }
+
+  // multiplies the color of every instance, e.g. for damage flashes or
+  // team colors. white leaves the model unchanged
+  pub fn tint(mut self, color: [f32; 4]) -> ModelTransform {
+    self.tint = color;
+    self
+  }
+
+  // draws only the meshes at these indices, e.g. to hide part of a model
+  pub fn meshes(mut self, indices: Vec<usize>) -> ModelTransform {
+    self.mesh_filter = Some(indices);
+    self
+  }
+
+  pub fn mesh_filter(mut self, filter: Option<Vec<usize>>) -> ModelTransform {
+    self.mesh_filter = filter;
+    self
+  }
+
+  // replaces the rotation of every instance with one facing the camera
+  pub fn billboard(mut self, mode: BillboardMode) -> ModelTransform {
+    self.billboard = Some(mode);
+    self
   }
 
   pub fn get_pos(&self) -> Vector3<f32> {
@@ -62,18 +147,32 @@ impl ModelTransform {
   }
 
   pub fn default() -> ModelTransform {
-    let instances = Vec::from([
-      Instance {
-        position: Vector3::new(0., 0., 0.),
-        rotation: Quaternion::new(0., 0., 0., 0.)
-      }
-    ]);
+    let instances = Vec::from([Instance::default()]);
     Self {
       transform_type: TransformType::Local,
       pos: Vector3::new(0., 0., 0.),
-      rot: Quaternion::new(0., 0., 0., 0.),
+      rot: identity_rotation(),
       instances,
       instanced: false,
+      opacity: 1.,
+      scale: Vector3::new(1., 1., 1.),
+      billboard: None,
+      tint: [1., 1., 1., 1.],
+      mesh_filter: None,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::{InnerSpace, Rotation};
+
+  #[test]
+  fn default_transform_does_not_collapse_points() {
+    let transform = ModelTransform::default();
+    let rotated = transform.rot.rotate_vector(Vector3::new(1., 2., 3.));
+    assert!((rotated - Vector3::new(1., 2., 3.)).magnitude() < 1e-6);
+    assert_eq!(transform.instances[0].rotation, transform.rot);
+  }
+}
@@ -14,6 +14,12 @@ mod model;
 mod resources;
 mod lighting;
 mod iv_state;
+mod skybox;
+mod frustum;
+mod text;
+mod input_map;
+mod rect;
+mod bind_group_cache;
 
 use state::State;
 pub use model::{
@@ -22,27 +28,37 @@ pub use model::{
   ModelVertex,
   Vertex,
   Model,
+  ground_grid_geometry,
   DrawLight,
   DrawModel,
 };
 pub use instance::{
+  identity_rotation,
   Instance,
   InstanceRaw
 };
 pub use resources::*;
 pub use texture::Texture;
-pub use pipeline::get_render_pipeline;
+pub use pipeline::{get_render_pipeline, get_shadow_pipeline, get_transparent_render_pipeline};
 pub use camera::{
   Camera,
   CameraController,
+  CameraMode,
+  CameraPose,
   Projection,
   CameraUniform
 };
 pub use lighting::*;
+pub use input_map::{Action, InputMap};
+pub use skybox::Skybox;
+pub use frustum::Frustum;
+pub use bind_group_cache::BindGroupLayoutCache;
+pub use text::{GlyphAtlas, GlyphQuad, TextRenderer};
+pub use rect::{rect_vertices, RectRenderer, RectVertex, ScreenRect};
 
 use self::iv_state::IVState;
 use super::playground::pg_state::PgState;
-use super::engine::Scene;
+use super::engine::{Scene, SurfaceRecovery};
 
 pub async fn run() {
   env_logger::init();
@@ -59,13 +75,13 @@ pub async fn run() {
     Event::DeviceEvent {
       event: DeviceEvent::MouseMotion{ delta, },
       .. // We're not using device_id currently
-    } => if scene.mouse_pressed {
+    } => if scene.mouse.is_pressed(MouseButton::Left) {
       scene.camera_controller.process_mouse(delta.0, delta.1)
     },
     Event::WindowEvent {
       ref event,
       window_id,
-    } if window_id == scene.window().id() => if !scene.input(event) {
+    } if window_id == scene.window().unwrap().id() => if !scene.input(event) {
       match event {
         WindowEvent::CloseRequested
         | WindowEvent::KeyboardInput {
@@ -77,6 +93,35 @@ pub async fn run() {
             },
           ..
         } => *control_flow = ControlFlow::Exit,
+        WindowEvent::KeyboardInput {
+          input:
+            KeyboardInput {
+              state: ElementState::Pressed,
+              virtual_keycode: Some(VirtualKeyCode::P),
+              ..
+            },
+          ..
+        } => {
+          // toggle position-based debug shading
+          let mode = match scene.shading_mode() {
+            ShadingMode::Lit => ShadingMode::Position,
+            ShadingMode::Position => ShadingMode::Lit,
+          };
+          scene.set_shading_mode(mode);
+        },
+        WindowEvent::KeyboardInput {
+          input:
+            KeyboardInput {
+              state: ElementState::Pressed,
+              virtual_keycode: Some(VirtualKeyCode::O),
+              ..
+            },
+          ..
+        } => {
+          // toggle shadows from the primary light
+          let enabled = scene.shadows_enabled();
+          scene.set_shadows_enabled(!enabled);
+        },
         WindowEvent::Resized(physical_size) => {
           scene.resize(*physical_size);
         },
@@ -86,20 +131,22 @@ pub async fn run() {
         _ => {}
       }
     },
-    Event::RedrawRequested(window_id) if window_id == scene.window().id() => {
+    Event::RedrawRequested(window_id) if window_id == scene.window().unwrap().id() => {
       let now = instant::Instant::now();
       let dt = now - last_render_time;
       last_render_time = now;
       scene.update(dt);
       match scene.render() {
         Ok(_) => {}
-        Err(wgpu::SurfaceError::Lost) => scene.resize(scene.size),
-        Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-        Err(e) => eprintln!("{:?}", e),
+        Err(err) => {
+          if scene.handle_surface_error(err) == SurfaceRecovery::Fatal {
+            *control_flow = ControlFlow::Exit;
+          }
+        }
       }
     },
     Event::MainEventsCleared => {
-      scene.window().request_redraw();
+      scene.window().unwrap().request_redraw();
     }
     _ => {}
   });
@@ -0,0 +1,39 @@
+use std::{collections::HashMap, sync::Arc};
+
+// Hands out one BindGroupLayout per distinct set of entries, so everything
+// built against the same layout shares the wgpu object (and stays
+// compatible with pipelines made from it). labels aren't part of the key,
+// the first label used for a layout is kept
+pub struct BindGroupLayoutCache {
+  layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>,
+}
+
+impl BindGroupLayoutCache {
+  pub fn new() -> BindGroupLayoutCache {
+    Self {
+      layouts: HashMap::new()
+    }
+  }
+
+  pub fn get_or_create(
+    &mut self,
+    device: &wgpu::Device,
+    label: &str,
+    entries: &[wgpu::BindGroupLayoutEntry]
+  ) -> Arc<wgpu::BindGroupLayout> {
+    self.layouts
+      .entry(entries.to_vec())
+      .or_insert_with(|| Arc::new(device.create_bind_group_layout(
+        &wgpu::BindGroupLayoutDescriptor {
+          label: Some(label),
+          entries
+        }
+      )))
+      .clone()
+  }
+
+  // number of distinct layouts created so far
+  pub fn len(&self) -> usize {
+    self.layouts.len()
+  }
+}
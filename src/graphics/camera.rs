@@ -1,9 +1,14 @@
 use cgmath::{
+  Quaternion,
+  Rotation,
+  Rotation3,
   SquareMatrix,
+  MetricSpace,
   Point3,
   Rad,
   Matrix4,
   Vector3,
+  Vector4,
   InnerSpace,
   perspective,
 };
@@ -12,6 +17,8 @@ use winit::dpi::PhysicalPosition;
 use instant::Duration;
 use std::f32::consts::FRAC_PI_2;
 
+use super::input_map::{Action, InputMap};
+
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -52,19 +59,90 @@ impl Camera {
   }
 
   pub fn calc_matrix(&self) -> Matrix4<f32> {
-    let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
-    let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
-
     Matrix4::look_to_rh(
       self.position,
-      Vector3::new(
-          cos_pitch * cos_yaw,
-          sin_pitch,
-          cos_pitch * sin_yaw
-      ).normalize(),
+      self.forward(),
       Vector3::unit_y(),
     )
   }
+
+  pub fn forward(&self) -> Vector3<f32> {
+    let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
+    let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+    Vector3::new(
+      cos_pitch * cos_yaw,
+      sin_pitch,
+      cos_pitch * sin_yaw
+    ).normalize()
+  }
+
+  // points the camera at target by setting yaw and pitch. pitch is clamped
+  // the same way as the controller so looking straight up/down stays stable
+  pub fn look_at(&mut self, target: Point3<f32>) {
+    let dir = target - self.position;
+    if dir.magnitude2() == 0. {
+      return;
+    }
+    let dir = dir.normalize();
+    self.yaw = Rad(dir.z.atan2(dir.x));
+    self.pitch = Rad(dir.y.asin().clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+  }
+
+  pub fn snapshot(&self) -> CameraPose {
+    CameraPose {
+      position: self.position,
+      yaw: self.yaw,
+      pitch: self.pitch
+    }
+  }
+
+  pub fn restore(&mut self, pose: &CameraPose) {
+    self.position = pose.position;
+    self.yaw = pose.yaw;
+    self.pitch = pose.pitch;
+  }
+
+  // world space points on the near and far planes under an ndc position
+  // (x and y in [-1, 1], y up)
+  pub fn unproject(&self, ndc: (f32, f32), projection: &Projection) -> (Point3<f32>, Point3<f32>) {
+    let inv_view_proj = (projection.calc_matrix() * self.calc_matrix())
+      .invert()
+      .unwrap_or(Matrix4::identity());
+    // wgpu clip space depth runs from 0 at the near plane to 1 at the far plane
+    let unproject = |depth: f32| Point3::from_homogeneous(inv_view_proj * Vector4::new(ndc.0, ndc.1, depth, 1.));
+    (unproject(0.), unproject(1.))
+  }
+}
+
+// a saved camera position and orientation, e.g. for cutscenes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+  pub position: Point3<f32>,
+  pub yaw: Rad<f32>,
+  pub pitch: Rad<f32>,
+}
+
+impl CameraPose {
+  // position is lerped and orientation slerped, so the camera turns the
+  // short way round. t = 1 gives exactly to
+  pub fn lerp(&self, to: &CameraPose, t: f32) -> CameraPose {
+    let t = t.clamp(0., 1.);
+    if t >= 1. {
+      return *to;
+    }
+    let rot = self.orientation().slerp(to.orientation(), t);
+    let dir = rot.rotate_vector(Vector3::unit_x());
+    CameraPose {
+      position: self.position + (to.position - self.position) * t,
+      yaw: Rad(dir.z.atan2(dir.x)),
+      pitch: Rad(dir.y.clamp(-1., 1.).asin())
+    }
+  }
+
+  // rotation taking +x to the camera's forward direction
+  fn orientation(&self) -> Quaternion<f32> {
+    Quaternion::from_angle_y(-self.yaw) * Quaternion::from_angle_z(self.pitch)
+  }
 }
 
 pub struct Projection {
@@ -94,6 +172,27 @@ impl Projection {
     self.aspect = width as f32 / height as f32;
   }
 
+  pub fn aspect(&self) -> f32 {
+    self.aspect
+  }
+
+  pub fn fovy(&self) -> Rad<f32> {
+    self.fovy
+  }
+
+  pub fn znear(&self) -> f32 {
+    self.znear
+  }
+
+  pub fn zfar(&self) -> f32 {
+    self.zfar
+  }
+
+  pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) {
+    self.znear = znear;
+    self.zfar = zfar;
+  }
+
   pub fn calc_matrix(&self) -> Matrix4<f32> {
     OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
   }
@@ -105,7 +204,8 @@ impl Projection {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
   pub view_pos: [f32; 4],
-  pub view_proj: [[f32; 4]; 4]
+  pub view_proj: [[f32; 4]; 4],
+  pub inv_view_proj: [[f32; 4]; 4], // maps clip space back to world space
 }
 
 
@@ -113,16 +213,29 @@ impl CameraUniform {
   pub fn new() -> Self {
     Self {
       view_pos: [0.0; 4],
-      view_proj: cgmath::Matrix4::identity().into()
+      view_proj: cgmath::Matrix4::identity().into(),
+      inv_view_proj: cgmath::Matrix4::identity().into()
     }
   }
 
   pub fn update_view_proj(&mut self, camera: &Camera, projection: &Projection) {
     self.view_pos = camera.position.to_homogeneous().into();
-    self.view_proj = (projection.calc_matrix() * camera.calc_matrix()).into();
+    let view_proj = projection.calc_matrix() * camera.calc_matrix();
+    self.view_proj = view_proj.into();
+    self.inv_view_proj = view_proj.invert().unwrap_or(Matrix4::identity()).into();
   }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+  // wasd + mouse look
+  Fly,
+  // mouse drag rotates around focus and scroll changes the orbit radius
+  Orbit {
+    focus: Point3<f32>
+  },
+}
+
 pub struct CameraController {
   pub amount_left: f32,
   pub amount_right: f32,
@@ -135,6 +248,11 @@ pub struct CameraController {
   pub scroll: f32,
   pub speed: f32,
   pub sensitivity: f32,
+  pub input_map: InputMap, // which keys move the camera
+  mode: CameraMode,
+  velocity: Vector3<f32>,
+  acceleration: f32, // units/s^2, 0 moves at constant speed
+  decay: f32, // fraction of velocity lost per second once keys are released
 }
 
 impl CameraController {
@@ -151,38 +269,54 @@ impl CameraController {
       scroll: 0.0,
       speed,
       sensitivity,
+      input_map: InputMap::default(),
+      mode: CameraMode::Fly,
+      velocity: Vector3::new(0., 0., 0.),
+      acceleration: 0.,
+      decay: 0.,
     }
   }
 
+  // ramp up to speed at accel and slow down by decay when input stops.
+  // an accel of 0 keeps the instant start/stop movement
+  pub fn set_damping(&mut self, accel: f32, decay: f32) {
+    self.acceleration = accel.max(0.);
+    self.decay = decay.max(0.);
+    self.velocity = Vector3::new(0., 0., 0.);
+  }
+
+  pub fn velocity(&self) -> Vector3<f32> {
+    self.velocity
+  }
+
+  pub fn mode(&self) -> CameraMode {
+    self.mode
+  }
+
+  pub fn set_mode(&mut self, mode: CameraMode) {
+    self.mode = mode;
+  }
+
   pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool{
+    match self.input_map.action(key).cloned() {
+      Some(action) => self.process_action(&action, state),
+      None => false
+    }
+  }
+
+  // returns false for actions the controller doesn't handle
+  pub fn process_action(&mut self, action: &Action, state: ElementState) -> bool {
     let amount = if state == ElementState::Pressed { 1.0 } else { 0.0 };
-    match key {
-      VirtualKeyCode::W | VirtualKeyCode::Up => {
-        self.amount_forward = amount;
-        true
-      }
-      VirtualKeyCode::S | VirtualKeyCode::Down => {
-        self.amount_backward = amount;
-        true
-      }
-      VirtualKeyCode::A | VirtualKeyCode::Left => {
-        self.amount_left = amount;
-        true
-      }
-      VirtualKeyCode::D | VirtualKeyCode::Right => {
-        self.amount_right = amount;
-        true
-      }
-      VirtualKeyCode::Space => {
-        self.amount_up = amount;
-        true
-      }
-      VirtualKeyCode::LShift => {
-        self.amount_down = amount;
-        true
-      }
-      _ => false,
+    match action {
+      Action::MoveForward => self.amount_forward = amount,
+      Action::MoveBackward => self.amount_backward = amount,
+      Action::MoveLeft => self.amount_left = amount,
+      Action::MoveRight => self.amount_right = amount,
+      Action::MoveUp => self.amount_up = amount,
+      Action::MoveDown => self.amount_down = amount,
+      Action::Custom(_) => return false,
     }
+    true
   }
 
   pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
@@ -202,14 +336,35 @@ impl CameraController {
   }
 
   pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+    if let CameraMode::Orbit { focus } = self.mode {
+      self.update_orbit(camera, focus, dt);
+      return;
+    }
     let dt = dt.as_secs_f32();
 
     // Move forward/backward and left/right
     let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
     let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
     let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-    camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-    camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+    let target_velocity = (forward * (self.amount_forward - self.amount_backward)
+      + right * (self.amount_right - self.amount_left)
+      + Vector3::unit_y() * (self.amount_up - self.amount_down)) * self.speed;
+    if self.acceleration > 0. {
+      if target_velocity.magnitude2() > 0. {
+        let diff = target_velocity - self.velocity;
+        let step = self.acceleration * dt;
+        if diff.magnitude() <= step {
+          self.velocity = target_velocity;
+        } else {
+          self.velocity += diff.normalize() * step;
+        }
+      } else {
+        self.velocity *= (1. - self.decay * dt).max(0.);
+      }
+    } else {
+      self.velocity = target_velocity;
+    }
+    camera.position += self.velocity * dt;
 
     // Move in/out (aka. "zoom")
     // Note: this isn't an actual zoom. The camera's position
@@ -220,10 +375,6 @@ impl CameraController {
     camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
     self.scroll = 0.0;
 
-    // Move up/down. Since we don't use roll, we can just
-    // modify the y coordinate directly.
-    camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
-
     // Rotate
     camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
     camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
@@ -241,4 +392,100 @@ impl CameraController {
       camera.pitch = Rad(SAFE_FRAC_PI_2);
     }
   }
-}
\ No newline at end of file
+
+  fn update_orbit(&mut self, camera: &mut Camera, focus: Point3<f32>, dt: Duration) {
+    let dt = dt.as_secs_f32();
+    let radius = (camera.position.distance(focus) - self.scroll * self.speed * self.sensitivity * dt).max(0.1);
+    self.scroll = 0.0;
+
+    // dragging right swings the camera around to the right of the focus
+    camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
+    camera.pitch += Rad(self.rotate_vertical) * self.sensitivity * dt;
+    self.rotate_horizontal = 0.0;
+    self.rotate_vertical = 0.0;
+    if camera.pitch < -Rad(SAFE_FRAC_PI_2) {
+      camera.pitch = -Rad(SAFE_FRAC_PI_2);
+    } else if camera.pitch > Rad(SAFE_FRAC_PI_2) {
+      camera.pitch = Rad(SAFE_FRAC_PI_2);
+    }
+
+    camera.position = focus - camera.forward() * radius;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn moved_by(controller: &mut CameraController, key: VirtualKeyCode) -> f32 {
+    let mut camera = Camera::new((0., 0., 0.), cgmath::Deg(-90.), cgmath::Deg(0.));
+    controller.process_keyboard(key, ElementState::Pressed);
+    controller.update_camera(&mut camera, Duration::from_millis(100));
+    controller.process_keyboard(key, ElementState::Released);
+    camera.position.distance(Point3::new(0., 0., 0.))
+  }
+
+  #[test]
+  fn rebound_forward_ignores_w() {
+    let mut controller = CameraController::new(4., 0.4);
+    assert!(moved_by(&mut controller, VirtualKeyCode::W) > 0.);
+
+    controller.input_map.rebind(Action::MoveForward, &[VirtualKeyCode::Up]);
+    assert!(!controller.process_keyboard(VirtualKeyCode::W, ElementState::Pressed));
+    assert_eq!(moved_by(&mut controller, VirtualKeyCode::W), 0.);
+    assert!(moved_by(&mut controller, VirtualKeyCode::Up) > 0.);
+  }
+
+  fn pose(x: f32, yaw: f32, pitch: f32) -> CameraPose {
+    CameraPose {
+      position: Point3::new(x, 0., 0.),
+      yaw: Rad(yaw),
+      pitch: Rad(pitch),
+    }
+  }
+
+  fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+  }
+
+  #[test]
+  fn restore_returns_to_the_snapshot() {
+    let mut camera = Camera::new((1., 2., 3.), Rad(0.3), Rad(-0.2));
+    let saved = camera.snapshot();
+    camera.position = Point3::new(10., 0., -4.);
+    camera.look_at(Point3::new(0., 0., 0.));
+    assert_ne!(camera.snapshot(), saved);
+
+    camera.restore(&saved);
+    assert_eq!(camera.position, Point3::new(1., 2., 3.));
+    assert_eq!(camera.yaw, Rad(0.3));
+    assert_eq!(camera.pitch, Rad(-0.2));
+  }
+
+  #[test]
+  fn lerp_hits_both_ends() {
+    let (from, to) = (pose(0., 0.2, 0.1), pose(4., 1.2, -0.3));
+    let start = from.lerp(&to, 0.);
+    assert!(start.position.distance(from.position) < 1e-4);
+    assert!(close(start.yaw.0, 0.2) && close(start.pitch.0, 0.1));
+    assert_eq!(from.lerp(&to, 1.), to);
+    // t is clamped
+    assert_eq!(from.lerp(&to, 2.), to);
+  }
+
+  #[test]
+  fn lerp_moves_and_turns_halfway() {
+    let half = pose(0., 0., 0.).lerp(&pose(4., std::f32::consts::FRAC_PI_2, 0.), 0.5);
+    assert!(close(half.position.x, 2.));
+    assert!(close(half.yaw.0, std::f32::consts::FRAC_PI_4));
+    assert!(close(half.pitch.0, 0.));
+  }
+
+  #[test]
+  fn lerp_turns_the_short_way_round() {
+    let (from, to) = (pose(0., 3., 0.), pose(0., -3., 0.));
+    let half = from.lerp(&to, 0.5);
+    // halfway between them through pi, not through 0
+    assert!(half.yaw.0.cos() < -0.9);
+  }
+}
@@ -0,0 +1,47 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+// The six clipping planes of a view-projection matrix. Each plane is stored
+// as (normal, distance) with the normal pointing into the frustum, so a
+// point is inside a plane when dot(normal, p) + distance >= 0
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+  planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+  // extracts the planes from the rows of the matrix (gribb/hartmann).
+  // wgpu clip space has z in [0, w], so the near plane is just the z row
+  pub fn from_matrix(view_proj: Matrix4<f32>) -> Frustum {
+    let row = |i: usize| view_proj.row(i);
+    let planes = [
+      row(3) + row(0), // left
+      row(3) - row(0), // right
+      row(3) + row(1), // bottom
+      row(3) - row(1), // top
+      row(2),          // near
+      row(3) - row(2), // far
+    ].map(normalize_plane);
+    Self {
+      planes
+    }
+  }
+
+  pub fn contains_point(&self, point: Point3<f32>) -> bool {
+    self.intersects_sphere(point, 0.)
+  }
+
+  // true if any part of the sphere is inside the frustum
+  pub fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+    self.planes.iter().all(|plane| {
+      plane.truncate().dot(Vector3::new(center.x, center.y, center.z)) + plane.w >= -radius
+    })
+  }
+}
+
+fn normalize_plane(plane: Vector4<f32>) -> Vector4<f32> {
+  let len = plane.truncate().magnitude();
+  if len == 0. {
+    return plane;
+  }
+  plane / len
+}
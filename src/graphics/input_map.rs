@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use winit::event::VirtualKeyCode;
+
+// something a key can be bound to. the move actions drive the camera
+// controller, Custom ones are left for components to check
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+  MoveForward,
+  MoveBackward,
+  MoveLeft,
+  MoveRight,
+  MoveUp,
+  MoveDown,
+  Custom(String),
+}
+
+// Maps keys to actions. Each key triggers at most one action, but an
+// action can have any number of keys
+#[derive(Clone, Debug)]
+pub struct InputMap {
+  bindings: HashMap<VirtualKeyCode, Action>,
+}
+
+impl InputMap {
+  pub fn new() -> InputMap {
+    Self {
+      bindings: HashMap::new()
+    }
+  }
+
+  // wasd/arrows to move, space and left shift for up and down
+  pub fn default() -> InputMap {
+    let mut map = Self::new();
+    map.rebind(Action::MoveForward, &[VirtualKeyCode::W, VirtualKeyCode::Up]);
+    map.rebind(Action::MoveBackward, &[VirtualKeyCode::S, VirtualKeyCode::Down]);
+    map.rebind(Action::MoveLeft, &[VirtualKeyCode::A, VirtualKeyCode::Left]);
+    map.rebind(Action::MoveRight, &[VirtualKeyCode::D, VirtualKeyCode::Right]);
+    map.rebind(Action::MoveUp, &[VirtualKeyCode::Space]);
+    map.rebind(Action::MoveDown, &[VirtualKeyCode::LShift]);
+    map
+  }
+
+  // binds key to action, replacing whatever the key was bound to. returns
+  // the previous action
+  pub fn bind(&mut self, key: VirtualKeyCode, action: Action) -> Option<Action> {
+    self.bindings.insert(key, action)
+  }
+
+  pub fn unbind(&mut self, key: VirtualKeyCode) -> Option<Action> {
+    self.bindings.remove(&key)
+  }
+
+  // makes keys the only bindings for action
+  pub fn rebind(&mut self, action: Action, keys: &[VirtualKeyCode]) {
+    self.bindings.retain(|_, bound| *bound != action);
+    for key in keys {
+      self.bindings.insert(*key, action.clone());
+    }
+  }
+
+  pub fn action(&self, key: VirtualKeyCode) -> Option<&Action> {
+    self.bindings.get(&key)
+  }
+
+  pub fn keys_for(&self, action: &Action) -> Vec<VirtualKeyCode> {
+    self.bindings.iter()
+      .filter(|(_, bound)| *bound == action)
+      .map(|(key, _)| *key)
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sorted(mut keys: Vec<VirtualKeyCode>) -> Vec<VirtualKeyCode> {
+    keys.sort_by_key(|key| *key as u32);
+    keys
+  }
+
+  #[test]
+  fn default_binds_wasd_and_arrows() {
+    let map = InputMap::default();
+    assert_eq!(map.action(VirtualKeyCode::W), Some(&Action::MoveForward));
+    assert_eq!(map.action(VirtualKeyCode::Up), Some(&Action::MoveForward));
+    assert_eq!(map.action(VirtualKeyCode::LShift), Some(&Action::MoveDown));
+    assert_eq!(map.action(VirtualKeyCode::Q), None);
+  }
+
+  #[test]
+  fn bind_replaces_the_keys_previous_action() {
+    let mut map = InputMap::default();
+    let jump = Action::Custom("jump".into());
+    assert_eq!(map.bind(VirtualKeyCode::Space, jump.clone()), Some(Action::MoveUp));
+    assert_eq!(map.action(VirtualKeyCode::Space), Some(&jump));
+    assert!(map.keys_for(&Action::MoveUp).is_empty());
+    assert_eq!(map.unbind(VirtualKeyCode::Space), Some(jump));
+    assert_eq!(map.unbind(VirtualKeyCode::Space), None);
+  }
+
+  #[test]
+  fn rebind_drops_the_actions_old_keys() {
+    let mut map = InputMap::default();
+    map.rebind(Action::MoveForward, &[VirtualKeyCode::I, VirtualKeyCode::K]);
+    assert_eq!(map.action(VirtualKeyCode::W), None);
+    assert_eq!(sorted(map.keys_for(&Action::MoveForward)), sorted(vec![VirtualKeyCode::I, VirtualKeyCode::K]));
+  }
+}
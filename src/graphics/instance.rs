@@ -1,27 +1,113 @@
 use std::mem;
+use cgmath::{InnerSpace, SquareMatrix};
 use super::model::Vertex;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Instance {
   pub position: cgmath::Vector3<f32>,
   pub rotation: cgmath::Quaternion<f32>,
+  pub scale: cgmath::Vector3<f32>,
+  // multiplies the diffuse color. white leaves the model unchanged
+  pub color: [f32; 4],
 }
 
 impl Instance {
+  // unrotated, unscaled and untinted at the origin
+  pub fn default() -> Instance {
+    Self {
+      position: cgmath::Vector3::new(0., 0., 0.),
+      rotation: identity_rotation(),
+      scale: cgmath::Vector3::new(1., 1., 1.),
+      color: [1., 1., 1., 1.]
+    }
+  }
+
+  // angles are in degrees about the x (pitch), y (yaw) and z (roll) axes
+  pub fn from_euler(position: cgmath::Vector3<f32>, pitch: f32, yaw: f32, roll: f32) -> Instance {
+    Self {
+      position,
+      rotation: cgmath::Quaternion::from(cgmath::Euler::new(cgmath::Deg(pitch), cgmath::Deg(yaw), cgmath::Deg(roll))),
+      ..Self::default()
+    }
+  }
+
+  // turns the model's local +z towards target, keeping its +y as close to
+  // up as possible. a target at position leaves it unrotated
+  pub fn looking_at(position: cgmath::Vector3<f32>, target: cgmath::Vector3<f32>, up: cgmath::Vector3<f32>) -> Instance {
+    Self {
+      position,
+      rotation: look_rotation(target - position, up),
+      ..Self::default()
+    }
+  }
+
   pub fn to_raw(&self) -> InstanceRaw {
+    self.to_raw_with_opacity(1.)
+  }
+
+  pub fn to_raw_with_opacity(&self, opacity: f32) -> InstanceRaw {
+    self.to_raw_with_state(opacity, false, [1., 1., 1., 1.])
+  }
+
+  // selected instances are highlighted in the fragment shader. tint is
+  // multiplied into the instance's own color
+  pub fn to_raw_with_state(&self, opacity: f32, selected: bool, tint: [f32; 4]) -> InstanceRaw {
+    let scale = cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+    // normals use the inverse transpose, which for R * S is R * S^-1
+    let inv_scale = cgmath::Matrix3::from_diagonal(cgmath::Vector3::new(
+      safe_recip(self.scale.x),
+      safe_recip(self.scale.y),
+      safe_recip(self.scale.z),
+    ));
     InstanceRaw {
-      model: (cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation)).into(),
-      normal: cgmath::Matrix3::from(self.rotation).into()
+      model: (cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation) * scale).into(),
+      normal: (cgmath::Matrix3::from(self.rotation) * inv_scale).into(),
+      opacity,
+      selected: selected as u32,
+      color: [
+        self.color[0] * tint[0],
+        self.color[1] * tint[1],
+        self.color[2] * tint[2],
+        self.color[3] * tint[3],
+      ]
     }
   }
 }
 
+// the no-op rotation. Quaternion::new(0., 0., 0., 0.) is the zero
+// quaternion, which can't be composed, normalized or interpolated
+pub fn identity_rotation() -> cgmath::Quaternion<f32> {
+  cgmath::Quaternion::new(1., 0., 0., 0.)
+}
+
+fn look_rotation(dir: cgmath::Vector3<f32>, up: cgmath::Vector3<f32>) -> cgmath::Quaternion<f32> {
+  if dir.magnitude2() == 0. {
+    return identity_rotation();
+  }
+  let z = dir.normalize();
+  let mut x = up.cross(z);
+  if x.magnitude2() < 1e-8 {
+    // dir is parallel to up, any perpendicular axis will do
+    x = if z.x.abs() < 0.9 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() }.cross(z);
+  }
+  let x = x.normalize();
+  let y = z.cross(x);
+  cgmath::Quaternion::from(cgmath::Matrix3::from_cols(x, y, z))
+}
+
+fn safe_recip(v: f32) -> f32 {
+  if v == 0. { 0. } else { 1. / v }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 #[allow(dead_code)]
 pub struct InstanceRaw {
   model: [[f32; 4]; 4],
-  normal: [[f32; 3]; 3]
+  normal: [[f32; 3]; 3],
+  opacity: f32,
+  selected: u32,
+  color: [f32; 4]
 }
 
 impl Vertex for InstanceRaw {
@@ -72,7 +158,73 @@ impl Vertex for InstanceRaw {
           shader_location: 11,
           format: wgpu::VertexFormat::Float32x3,
         },
+        wgpu::VertexAttribute {
+          offset: mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+          shader_location: 12,
+          format: wgpu::VertexFormat::Float32,
+        },
+        wgpu::VertexAttribute {
+          offset: mem::size_of::<[f32; 26]>() as wgpu::BufferAddress,
+          shader_location: 14,
+          format: wgpu::VertexFormat::Uint32,
+        },
+        wgpu::VertexAttribute {
+          offset: mem::size_of::<[f32; 27]>() as wgpu::BufferAddress,
+          shader_location: 15,
+          format: wgpu::VertexFormat::Float32x4,
+        },
       ],
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::{Rotation, Vector3};
+
+  fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a - b).magnitude() < 1e-5
+  }
+
+  #[test]
+  fn from_euler_zero_is_the_default_rotation() {
+    let instance = Instance::from_euler(Vector3::new(1., 2., 3.), 0., 0., 0.);
+    assert_eq!(instance.position, Vector3::new(1., 2., 3.));
+    assert_eq!(instance.scale, Instance::default().scale);
+    assert_eq!(instance.color, Instance::default().color);
+    assert!(close(instance.rotation.rotate_vector(Vector3::unit_x()), Vector3::unit_x()));
+  }
+
+  #[test]
+  fn from_euler_takes_degrees_about_each_axis() {
+    let origin = Vector3::new(0., 0., 0.);
+    let yawed = Instance::from_euler(origin, 0., 90., 0.);
+    assert!(close(yawed.rotation.rotate_vector(Vector3::unit_x()), -Vector3::unit_z()));
+    let pitched = Instance::from_euler(origin, 90., 0., 0.);
+    assert!(close(pitched.rotation.rotate_vector(Vector3::unit_y()), Vector3::unit_z()));
+    let rolled = Instance::from_euler(origin, 0., 0., 90.);
+    assert!(close(rolled.rotation.rotate_vector(Vector3::unit_x()), Vector3::unit_y()));
+  }
+
+  #[test]
+  fn from_euler_zero_is_the_identity_quaternion() {
+    let rotation = Instance::from_euler(Vector3::new(0., 0., 0.), 0., 0., 0.).rotation;
+    let identity = identity_rotation();
+    assert!((rotation.s - identity.s).abs() < 1e-6 && close(rotation.v, identity.v));
+  }
+
+  #[test]
+  fn default_instances_keep_their_geometry() {
+    let model = cgmath::Matrix4::from(Instance::default().to_raw().model);
+    let p = model * cgmath::Vector4::new(1., 2., 3., 1.);
+    assert!(close(p.truncate(), Vector3::new(1., 2., 3.)));
+  }
+
+  #[test]
+  fn looking_at_turns_z_towards_the_target() {
+    let instance = Instance::looking_at(Vector3::new(0., 0., 0.), Vector3::new(5., 0., 0.), Vector3::unit_y());
+    assert!(close(instance.rotation.rotate_vector(Vector3::unit_z()), Vector3::unit_x()));
+    assert!(close(instance.rotation.rotate_vector(Vector3::unit_y()), Vector3::unit_y()));
+  }
+}
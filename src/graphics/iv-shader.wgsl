@@ -19,6 +19,7 @@ struct VertexInput {
   @location(2) normal: vec3<f32>,
   @location(3) tangent: vec3<f32>,
   @location(4) bitangent: vec3<f32>,
+  @location(13) color: vec3<f32>,
 }
 
 struct VertexOutput {
@@ -26,6 +27,7 @@ struct VertexOutput {
   @location(0) tex_coords: vec2<f32>,
   @location(1) world_normal: vec3<f32>,
   @location(2) world_position: vec3<f32>,
+  @location(3) color: vec3<f32>,
 };
 
 @vertex
@@ -35,6 +37,7 @@ fn vs_main(
   var out: VertexOutput;
   out.tex_coords = model.tex_coords;
   out.world_normal = model.normal;
+  out.color = model.color;
   var world_position: vec4<f32> = vec4<f32>(model.position, 1.0);
   out.world_position = world_position.xyz;
   out.clip_position = camera.view_proj * world_position;
@@ -53,7 +56,7 @@ var s_normal: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-  let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+  let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords) * vec4<f32>(in.color, 1.0);
   
   // We don't need (or want) much ambient light, so 0.1 is fine
   let ambient_strength = 0.1;
@@ -6,7 +6,7 @@ use crate::debug::{
   self, DebugCubeNet, DrawDebugNet
 };
 
-use crate::{graphics::{model::{self, Vertex}, pipeline::get_render_pipeline, Texture}, sdf::{DrawIVModel, InferredVertexModel, SdfBounds, SdfShape, Shape}, util::Point};
+use crate::{graphics::{model::{self, Vertex}, pipeline::get_render_pipeline, BindGroupLayoutCache, Texture}, sdf::{DrawIVModel, InferredVertexModel, MeshingOptions, DEFAULT_REFINE_ITERS, DEFAULT_REFINE_TOL, SdfBounds, SdfShape, Shape}, util::Point};
 
 use super::{camera::{Camera, CameraController, CameraUniform, Projection}, lighting};
 
@@ -25,6 +25,7 @@ pub struct IVState {
   pub camera_bind_group: wgpu::BindGroup,
   pub camera_buffer: wgpu::Buffer,
   pub depth_texture: Texture,
+  pub bind_group_layouts: BindGroupLayoutCache,
   pub iv_model: InferredVertexModel,
   pub debug_net: DebugCubeNet,
   pub debug_render_pipeline: wgpu::RenderPipeline,
@@ -150,7 +151,7 @@ impl IVState {
       position: [2.0, 10.0, 2.0],
       _padding: 0,
       color: [1.0, 1.0, 1.0],
-      _padding_2: 0,
+      shading_mode: lighting::ShadingMode::Lit as u32,
     };
     let light_buffer = lighting::get_light_buffer(&device, &light_uniform);
     let (light_bind_group_layout, light_bind_group) = lighting::get_light_bind_group_info(&device, &light_buffer);
@@ -199,7 +200,7 @@ impl IVState {
           _ => 0.0
         }
       }
-    );
+    ).unwrap();
 
     let bounds = SdfBounds {
       xmin: -2.1 + center.x,
@@ -210,7 +211,20 @@ impl IVState {
       zmax: 2.1 + center.z
     };
 
-    let iv_model = InferredVertexModel::new(&device, &queue, sdf, bounds, 0.025, &[200, 100, 0, 255]);
+    let mut bind_group_layouts = BindGroupLayoutCache::new();
+    let iv_model = InferredVertexModel::new(
+      &device,
+      &queue,
+      sdf,
+      bounds,
+      0.025,
+      DEFAULT_REFINE_ITERS,
+      DEFAULT_REFINE_TOL,
+      MeshingOptions::default(),
+      &[200, 100, 0, 255],
+      None,
+      &mut bind_group_layouts
+    );
 
     // draw debug cubes
     let debug_net = DebugCubeNet::new(&device, &config, iv_model.triangle_coords.clone(), 0.015);
@@ -227,7 +241,7 @@ impl IVState {
       &wgpu::PipelineLayoutDescriptor {
         label: Some("Render pipeline layout"),
         bind_group_layouts: &[
-          &iv_model.diffuse_bind_group_layout,
+          iv_model.diffuse_bind_group_layout.as_ref(),
           &camera_bind_group_layout,
           &light_bind_group_layout,
         ],
@@ -295,6 +309,7 @@ impl IVState {
       camera_bind_group,
       camera_buffer,
       depth_texture,
+      bind_group_layouts,
       iv_model,
       debug_net,
       debug_render_pipeline,
@@ -1,12 +1,25 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
 use wgpu::util::DeviceExt;
 
+use super::camera::OPENGL_TO_WGPU_MATRIX;
+use super::instance::InstanceRaw;
+use super::model::{ModelVertex, Vertex};
+use super::pipeline::get_shadow_pipeline;
+use super::texture::Texture;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct LightUniform {
   pub position: [f32; 3],
   pub _padding: u32, // uniforms have 4-float (16-byte) spacing
   pub color: [f32; 3],
-  pub _padding_2: u32
+  pub shading_mode: u32 // occupies the padding slot after color, see ShadingMode
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadingMode {
+  Lit = 0,
+  Position = 1, // debug coloring by world position
 }
 
 pub fn get_light_buffer(device: &wgpu::Device, uniform: &LightUniform) -> wgpu::Buffer {
@@ -53,3 +66,173 @@ pub fn get_light_bind_group_info(device: &wgpu::Device, buffer: &wgpu::Buffer) -
 
   (layout, bind_group)
 }
+
+const SHADOW_MAP_SIZE: u32 = 2048;
+const SHADOW_EXTENT: f32 = 100.; // half width of the area covered by the shadow map
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+  pub light_view_proj: [[f32; 4]; 4],
+  pub enabled: u32,
+  pub _padding: [u32; 3],
+}
+
+// orthographic projection from the light position toward the origin
+pub fn get_light_view_proj(position: [f32; 3]) -> Matrix4<f32> {
+  let eye = Point3::new(position[0], position[1], position[2]);
+  let dir = (Point3::new(0., 0., 0.) - eye).normalize();
+  // look_at breaks down when the view direction is parallel to up
+  let up = if dir.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+  let view = Matrix4::look_at_rh(eye, Point3::new(0., 0., 0.), up);
+  let dist = (eye - Point3::new(0., 0., 0.)).magnitude();
+  let proj = cgmath::ortho(-SHADOW_EXTENT, SHADOW_EXTENT, -SHADOW_EXTENT, SHADOW_EXTENT, 1., dist + SHADOW_EXTENT * 2.);
+  OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+// Everything needed for the primary light's shadow: the depth texture,
+// the light view-projection uniform, and the depth-only pipeline used to
+// render into it. bind_group is read by the main shader at group 3,
+// pass_bind_group is used while rendering the shadow map itself
+pub struct ShadowMap {
+  pub texture: Texture,
+  pub uniform: ShadowUniform,
+  pub buffer: wgpu::Buffer,
+  pub bind_group_layout: wgpu::BindGroupLayout,
+  pub bind_group: wgpu::BindGroup,
+  pub pass_bind_group: wgpu::BindGroup,
+  pub pipeline: wgpu::RenderPipeline,
+}
+
+impl ShadowMap {
+  pub fn new(device: &wgpu::Device) -> ShadowMap {
+    let texture = Texture::create_shadow_map(device, SHADOW_MAP_SIZE, "Shadow map");
+    let uniform = ShadowUniform {
+      light_view_proj: Matrix4::identity().into(),
+      enabled: 0,
+      _padding: [0; 3],
+    };
+    let buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Shadow uniform buffer"),
+        contents: bytemuck::cast_slice(&[uniform]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+      }
+    );
+
+    let uniform_entry = wgpu::BindGroupLayoutEntry {
+      binding: 0,
+      visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None
+      },
+      count: None
+    };
+    let bind_group_layout = device.create_bind_group_layout(
+      &wgpu::BindGroupLayoutDescriptor {
+        label: Some("Shadow bind group layout"),
+        entries: &[
+          uniform_entry,
+          wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+              multisampled: false,
+              view_dimension: wgpu::TextureViewDimension::D2,
+              sample_type: wgpu::TextureSampleType::Depth,
+            },
+            count: None
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+            count: None
+          },
+        ]
+      }
+    );
+    let bind_group = device.create_bind_group(
+      &wgpu::BindGroupDescriptor {
+        label: Some("Shadow bind group"),
+        layout: &bind_group_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding()
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&texture.view)
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(&texture.sampler)
+          },
+        ]
+      }
+    );
+
+    // the shadow pass can't bind the map it's rendering into, so it gets a
+    // bind group with just the uniform
+    let pass_bind_group_layout = device.create_bind_group_layout(
+      &wgpu::BindGroupLayoutDescriptor {
+        label: Some("Shadow pass bind group layout"),
+        entries: &[uniform_entry]
+      }
+    );
+    let pass_bind_group = device.create_bind_group(
+      &wgpu::BindGroupDescriptor {
+        label: Some("Shadow pass bind group"),
+        layout: &pass_bind_group_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding()
+          }
+        ]
+      }
+    );
+
+    let pipeline_layout = device.create_pipeline_layout(
+      &wgpu::PipelineLayoutDescriptor {
+        label: Some("Shadow pipeline layout"),
+        bind_group_layouts: &[&pass_bind_group_layout],
+        push_constant_ranges: &[],
+      }
+    );
+    let pipeline = get_shadow_pipeline(
+      device,
+      &pipeline_layout,
+      &[ModelVertex::desc(), InstanceRaw::desc()],
+      wgpu::ShaderModuleDescriptor {
+        label: Some("Shadow shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+      },
+      "vs_main"
+    );
+
+    Self {
+      texture,
+      uniform,
+      buffer,
+      bind_group_layout,
+      bind_group,
+      pass_bind_group,
+      pipeline,
+    }
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.uniform.enabled != 0
+  }
+
+  // recompute the light matrix and upload the uniform
+  pub fn update(&mut self, queue: &wgpu::Queue, light_position: [f32; 3], enabled: bool) {
+    self.uniform.light_view_proj = get_light_view_proj(light_position).into();
+    self.uniform.enabled = enabled as u32;
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+  }
+}
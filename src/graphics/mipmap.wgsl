@@ -0,0 +1,26 @@
+// Fullscreen blit used to downsample one mip level into the next
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  // a single triangle covering the whole target
+  let x = f32((vertex_index << 1u) & 2u);
+  let y = f32(vertex_index & 2u);
+  var out: VertexOutput;
+  out.position = vec4<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0, 1.0);
+  out.tex_coords = vec2<f32>(x, y);
+  return out;
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  return textureSample(t_source, s_source, in.tex_coords);
+}
@@ -1,24 +1,162 @@
 use std::mem;
 use std::ops::Range;
+use wgpu::util::DeviceExt;
+
 use super::texture::Texture;
 
+// flags stored in MaterialUniform::flags
+pub const MATERIAL_FLAG_PBR: u32 = 1;
+pub const MATERIAL_FLAG_METALLIC_ROUGHNESS_TEXTURE: u32 = 1 << 1;
+pub const MATERIAL_FLAG_EMISSIVE_TEXTURE: u32 = 1 << 2;
+
+// per-material shading factors, uploaded alongside the material textures
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+  pub metallic: f32,
+  pub roughness: f32,
+  pub flags: u32,
+  _padding: u32,
+}
+
 #[derive(Debug)]
 pub struct Material {
   pub name: String,
   pub diffuse_texture: Texture,
   pub normal_texture: Texture,
+  pub metallic: f32,
+  pub roughness: f32,
+  pub metallic_roughness_texture: Option<Texture>,
+  pub emissive_texture: Option<Texture>,
+  pub uniform_buffer: wgpu::Buffer,
   pub bind_group: wgpu::BindGroup,
 }
 
 impl Material {
+  // plain blinn-phong material (the original shading model)
   pub fn new(
     device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    name: &str,
+    diffuse_texture: Texture,
+    normal_texture: Texture,
+    layout: &wgpu::BindGroupLayout
+  ) -> Self {
+    Self::create(device, queue, name, diffuse_texture, normal_texture, 0., 0.5, None, None, 0, layout)
+  }
+
+  // metallic-roughness material shaded with cook-torrance. the metallic
+  // and roughness factors are multiplied with the b and g channels of the
+  // metallic-roughness texture when one is given
+  pub fn new_pbr(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    name: &str,
+    diffuse_texture: Texture,
+    normal_texture: Texture,
+    metallic: f32,
+    roughness: f32,
+    metallic_roughness_texture: Option<Texture>,
+    emissive_texture: Option<Texture>,
+    layout: &wgpu::BindGroupLayout
+  ) -> Self {
+    Self::create(device, queue, name, diffuse_texture, normal_texture, metallic, roughness, metallic_roughness_texture, emissive_texture, MATERIAL_FLAG_PBR, layout)
+  }
+
+  fn create(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
     name: &str,
     diffuse_texture: Texture,
     normal_texture: Texture,
+    metallic: f32,
+    roughness: f32,
+    metallic_roughness_texture: Option<Texture>,
+    emissive_texture: Option<Texture>,
+    flags: u32,
     layout: &wgpu::BindGroupLayout
   ) -> Self {
-    let bind_group = device.create_bind_group(
+    let mut flags = flags;
+    if metallic_roughness_texture.is_some() {
+      flags |= MATERIAL_FLAG_METALLIC_ROUGHNESS_TEXTURE;
+    }
+    if emissive_texture.is_some() {
+      flags |= MATERIAL_FLAG_EMISSIVE_TEXTURE;
+    }
+    let uniform = MaterialUniform {
+      metallic,
+      roughness,
+      flags,
+      _padding: 0,
+    };
+    let uniform_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} material uniform", name)),
+        contents: bytemuck::cast_slice(&[uniform]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      }
+    );
+
+    let bind_group = Self::create_bind_group(
+      device,
+      queue,
+      name,
+      &diffuse_texture,
+      &normal_texture,
+      metallic_roughness_texture.as_ref(),
+      emissive_texture.as_ref(),
+      &uniform_buffer,
+      layout
+    );
+
+    Self {
+      name: String::from(name),
+      diffuse_texture,
+      normal_texture,
+      metallic,
+      roughness,
+      metallic_roughness_texture,
+      emissive_texture,
+      uniform_buffer,
+      bind_group
+    }
+  }
+
+  // swaps the diffuse texture, rebuilding the bind group around it. layout
+  // has to be the one the material was created with
+  pub fn set_diffuse_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: Texture, layout: &wgpu::BindGroupLayout) {
+    self.bind_group = Self::create_bind_group(
+      device,
+      queue,
+      &self.name,
+      &texture,
+      &self.normal_texture,
+      self.metallic_roughness_texture.as_ref(),
+      self.emissive_texture.as_ref(),
+      &self.uniform_buffer,
+      layout
+    );
+    self.diffuse_texture = texture;
+  }
+
+  fn create_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    name: &str,
+    diffuse_texture: &Texture,
+    normal_texture: &Texture,
+    metallic_roughness_texture: Option<&Texture>,
+    emissive_texture: Option<&Texture>,
+    uniform_buffer: &wgpu::Buffer,
+    layout: &wgpu::BindGroupLayout
+  ) -> wgpu::BindGroup {
+    // missing optional textures are bound to 1x1 placeholders, the flags
+    // tell the shader to ignore them
+    let placeholder = Texture::from_raw(device, queue, vec![255, 255, 255, 255], (1, 1), "placeholder").unwrap();
+    let metallic_roughness = metallic_roughness_texture.unwrap_or(&placeholder);
+    let emissive = emissive_texture.unwrap_or(&placeholder);
+
+    device.create_bind_group(
       &wgpu::BindGroupDescriptor {
         layout,
         label: Some(name),
@@ -40,16 +178,99 @@ impl Material {
             binding: 3,
             resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
           },
+          wgpu::BindGroupEntry {
+            binding: 4,
+            resource: wgpu::BindingResource::TextureView(&metallic_roughness.view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 5,
+            resource: wgpu::BindingResource::Sampler(&metallic_roughness.sampler),
+          },
+          wgpu::BindGroupEntry {
+            binding: 6,
+            resource: wgpu::BindingResource::TextureView(&emissive.view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 7,
+            resource: wgpu::BindingResource::Sampler(&emissive.sampler),
+          },
+          wgpu::BindGroupEntry {
+            binding: 8,
+            resource: uniform_buffer.as_entire_binding(),
+          },
         ]
       }
-    );
+    )
+  }
 
-    Self {
-      name: String::from(name),
-      diffuse_texture,
-      normal_texture,
-      bind_group
+  // layout shared by every material bind group
+  pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(
+      &wgpu::BindGroupLayoutDescriptor {
+        label: Some("Texture bind group layout"),
+        entries: &Self::bind_group_layout_entries()
+      }
+    )
+  }
+
+  // diffuse, normal, metallic-roughness and emissive texture/sampler pairs
+  // followed by the MaterialUniform
+  pub fn bind_group_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+    let texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        multisampled: false,
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    };
+    let sampler_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+      count: None,
+    };
+    vec![
+      texture_entry(0),
+      sampler_entry(1),
+      texture_entry(2),
+      sampler_entry(3),
+      texture_entry(4),
+      sampler_entry(5),
+      texture_entry(6),
+      sampler_entry(7),
+      wgpu::BindGroupLayoutEntry {
+        binding: 8,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      },
+    ]
+  }
+
+  pub fn set_pbr_factors(&mut self, queue: &wgpu::Queue, metallic: f32, roughness: f32) {
+    self.metallic = metallic;
+    self.roughness = roughness;
+    let mut flags = MATERIAL_FLAG_PBR;
+    if self.metallic_roughness_texture.is_some() {
+      flags |= MATERIAL_FLAG_METALLIC_ROUGHNESS_TEXTURE;
     }
+    if self.emissive_texture.is_some() {
+      flags |= MATERIAL_FLAG_EMISSIVE_TEXTURE;
+    }
+    let uniform = MaterialUniform {
+      metallic,
+      roughness,
+      flags,
+      _padding: 0,
+    };
+    queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
   }
 }
 
@@ -61,12 +282,157 @@ pub struct Mesh {
   pub index_buffer: wgpu::Buffer,
   pub num_elements: u32,
   pub material: usize,
+  pub index_format: wgpu::IndexFormat,
+  // cpu side copy of the buffers' contents, kept for export
+  pub vertices: Vec<ModelVertex>,
+  pub indices: Vec<u32>,
+}
+
+impl Mesh {
+  // wavefront obj text for the mesh. positions, texture coords and normals
+  // share each vertex's index, and the v flip done on load is undone so the
+  // file loads back the same
+  pub fn to_obj(&self) -> String {
+    use std::fmt::Write;
+    let mut out = String::new();
+    let _ = writeln!(out, "o {}", self.name);
+    for v in self.vertices.iter() {
+      let _ = writeln!(out, "v {} {} {}", v.position[0], v.position[1], v.position[2]);
+    }
+    for v in self.vertices.iter() {
+      let _ = writeln!(out, "vt {} {}", v.tex_coords[0], 1.0 - v.tex_coords[1]);
+    }
+    for v in self.vertices.iter() {
+      let _ = writeln!(out, "vn {} {} {}", v.normal[0], v.normal[1], v.normal[2]);
+    }
+    // obj indices start at 1
+    for face in self.indices.chunks_exact(3) {
+      let _ = writeln!(out, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", face[0] + 1, face[1] + 1, face[2] + 1);
+    }
+    out
+  }
+
+  // writes to_obj to a file. native only, wasm has no filesystem to write to
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn export_obj<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<()> {
+    std::fs::write(path, self.to_obj())?;
+    Ok(())
+  }
+
+  // u16 indices whenever every vertex fits in one, which halves the buffer
+  pub fn index_format_for(vertex_count: usize) -> wgpu::IndexFormat {
+    if vertex_count < u16::MAX as usize + 1 {
+      wgpu::IndexFormat::Uint16
+    } else {
+      wgpu::IndexFormat::Uint32
+    }
+  }
+
+  // index buffer in the narrowest format that can address vertex_count vertices
+  pub fn create_index_buffer(device: &wgpu::Device, label: &str, indices: &[u32], vertex_count: usize) -> (wgpu::Buffer, wgpu::IndexFormat) {
+    let format = Self::index_format_for(vertex_count);
+    let buffer = match format {
+      wgpu::IndexFormat::Uint16 => {
+        let narrow = indices.iter().map(|i| *i as u16).collect::<Vec<u16>>();
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+          label: Some(label),
+          contents: bytemuck::cast_slice(&narrow),
+          usage: wgpu::BufferUsages::INDEX,
+        })
+      },
+      wgpu::IndexFormat::Uint32 => device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(indices),
+        usage: wgpu::BufferUsages::INDEX,
+      })
+    };
+    (buffer, format)
+  }
 }
 
 #[derive(Debug)]
 pub struct Model {
   pub meshes: Vec<Mesh>,
   pub materials: Vec<Material>,
+  pub bounding_radius: f32, // bounding sphere radius around the model origin
+}
+
+impl Model {
+  // reference grid on the xz plane, centered on the origin and size units
+  // across, built without an obj file. lines are drawn as thin quads so the
+  // grid goes through the regular model pipeline and is lit like any model
+  pub fn ground_grid(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    size: f32,
+    divisions: u32,
+    color: [f32; 3],
+  ) -> anyhow::Result<Model> {
+    let (vertices, indices) = ground_grid_geometry(size, divisions, color);
+    let vertex_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Ground grid Vertex Buffer"),
+        contents: bytemuck::cast_slice(&vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+      }
+    );
+    let (index_buffer, index_format) = Mesh::create_index_buffer(device, "Ground grid Index Buffer", &indices, vertices.len());
+
+    // the line color comes from the vertices, so the textures are a plain
+    // white diffuse and a flat normal
+    let pixel = |rgba: [u8; 4]| image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(rgba)));
+    let diffuse_texture = Texture::from_image(device, queue, &pixel([255, 255, 255, 255]), Some("Ground grid diffuse"), false)?;
+    let normal_texture = Texture::from_image(device, queue, &pixel([128, 128, 255, 255]), Some("Ground grid normal"), true)?;
+    let material = Material::new(device, queue, "ground_grid", diffuse_texture, normal_texture, layout);
+
+    Ok(Model {
+      meshes: vec![Mesh {
+        name: "ground_grid".into(),
+        vertex_buffer,
+        index_buffer,
+        num_elements: indices.len() as u32,
+        material: 0,
+        index_format,
+        vertices,
+        indices
+      }],
+      materials: vec![material],
+      bounding_radius: size * std::f32::consts::FRAC_1_SQRT_2,
+    })
+  }
+}
+
+// divisions + 1 lines along each axis, each a quad of 4 vertices and 6
+// indices facing +y. lines are 2% of a cell wide
+pub fn ground_grid_geometry(size: f32, divisions: u32, color: [f32; 3]) -> (Vec<ModelVertex>, Vec<u32>) {
+  let divisions = divisions.max(1);
+  let half_size = size * 0.5;
+  let step = size / divisions as f32;
+  let half_width = step * 0.01;
+  let vertex = |x: f32, z: f32| ModelVertex {
+    position: [x, 0., z],
+    tex_coords: [0., 0.],
+    normal: [0., 1., 0.],
+    tangent: [1., 0., 0.],
+    bitangent: [0., 0., -1.],
+    color,
+  };
+
+  let mut vertices = Vec::new();
+  let mut indices = Vec::new();
+  let mut push_quad = |x0: f32, z0: f32, x1: f32, z1: f32| {
+    let base = vertices.len() as u32;
+    vertices.extend_from_slice(&[vertex(x0, z0), vertex(x0, z1), vertex(x1, z1), vertex(x1, z0)]);
+    indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+  };
+  for i in 0..=divisions {
+    let offset = -half_size + i as f32 * step;
+    // parallel to z, then parallel to x
+    push_quad(offset - half_width, -half_size, offset + half_width, half_size);
+    push_quad(-half_size, offset - half_width, half_size, offset + half_width);
+  }
+  (vertices, indices)
 }
 
 pub trait Vertex {
@@ -81,6 +447,7 @@ pub struct ModelVertex {
   pub normal: [f32; 3],
   pub tangent: [f32; 3],
   pub bitangent: [f32; 3],
+  pub color: [f32; 3], // white unless the obj has per vertex colors
 }
 
 impl Vertex for ModelVertex {
@@ -114,6 +481,12 @@ impl Vertex for ModelVertex {
           shader_location: 4,
           format: wgpu::VertexFormat::Float32x3
         },
+        // 5-12 are taken by InstanceRaw
+        wgpu::VertexAttribute {
+          offset: mem::size_of::<[f32; 14]>() as wgpu::BufferAddress,
+          shader_location: 13,
+          format: wgpu::VertexFormat::Float32x3
+        },
       ]
     }
   }
@@ -152,6 +525,17 @@ pub trait DrawModel<'a> {
     light_bind_group: &'a wgpu::BindGroup
   );
 
+  // only draws the meshes whose indices are in mesh_filter, or every mesh
+  // when it's None
+  fn draw_model_instanced_filtered(
+    &mut self,
+    model: &'a Model,
+    instances: Range<u32>,
+    mesh_filter: Option<&[usize]>,
+    camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup
+  );
+
   fn draw_model_instanced_with_material(
     &mut self,
     model: &'a Model,
@@ -182,7 +566,7 @@ impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b: 'a {
     light_bind_group: &'a wgpu::BindGroup,
   ) {
       self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-      self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+      self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
       self.set_bind_group(0, &material.bind_group, &[]);
       self.set_bind_group(1, camera_bind_group, &[]);
       self.set_bind_group(2, light_bind_group, &[]);
@@ -211,6 +595,23 @@ impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b: 'a {
     }
   }
 
+  fn draw_model_instanced_filtered(
+    &mut self,
+    model: &'b Model,
+    instances: std::ops::Range<u32>,
+    mesh_filter: Option<&[usize]>,
+    camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup
+  ) {
+    for (idx, mesh) in model.meshes.iter().enumerate() {
+      if mesh_filter.map(|filter| !filter.contains(&idx)).unwrap_or(false) {
+        continue;
+      }
+      let material = &model.materials[mesh.material];
+      self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group, light_bind_group);
+    }
+  }
+
   fn draw_model_instanced_with_material(
     &mut self,
     model: &'b Model,
@@ -275,7 +676,7 @@ impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a> where 'b: 'a {
     light_bind_group: &'a wgpu::BindGroup
   ) {
     self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-    self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
     self.set_bind_group(0, camera_bind_group, &[]);
     self.set_bind_group(1, light_bind_group, &[]);
     self.draw_indexed(0..mesh.num_elements, 0, instances);
@@ -302,3 +703,44 @@ impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a> where 'b: 'a {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::Vector3;
+
+  #[test]
+  fn index_format_fits_the_vertex_count() {
+    assert_eq!(Mesh::index_format_for(0), wgpu::IndexFormat::Uint16);
+    assert_eq!(Mesh::index_format_for(65535), wgpu::IndexFormat::Uint16);
+    assert_eq!(Mesh::index_format_for(65536), wgpu::IndexFormat::Uint32);
+    assert_eq!(Mesh::index_format_for(100_000), wgpu::IndexFormat::Uint32);
+  }
+
+  #[test]
+  fn ten_division_grid_has_a_quad_per_line() {
+    let (vertices, indices) = ground_grid_geometry(10., 10, [1., 1., 1.]);
+    // 11 lines along each axis, one quad of 4 vertices per line
+    assert_eq!(vertices.len(), 22 * 4);
+    assert_eq!(indices.len(), 22 * 6);
+    assert!(indices.iter().all(|idx| (*idx as usize) < vertices.len()));
+    // at least one division, so a grid is never empty
+    let (vertices, _) = ground_grid_geometry(2., 0, [1., 1., 1.]);
+    assert_eq!(vertices.len(), 4 * 4);
+  }
+
+  #[test]
+  fn ground_grid_lies_flat_and_faces_up() {
+    let (vertices, indices) = ground_grid_geometry(4., 4, [1., 1., 1.]);
+    let half_width = 0.01;
+    for vertex in vertices.iter() {
+      assert_eq!(vertex.position[1], 0.);
+      assert!(vertex.position[0].abs() <= 2. + half_width && vertex.position[2].abs() <= 2. + half_width);
+    }
+    let pos = |idx: u32| Vector3::from(vertices[idx as usize].position);
+    for tri in indices.chunks(3) {
+      let normal = (pos(tri[1]) - pos(tri[0])).cross(pos(tri[2]) - pos(tri[0]));
+      assert!(normal.y > 0.);
+    }
+  }
+}
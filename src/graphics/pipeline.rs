@@ -15,11 +15,68 @@ pub fn get_render_pipeline(
   shader: wgpu::ShaderModuleDescriptor,
   vert_entry: &str,
   frag_entry: &str,
+) -> wgpu::RenderPipeline {
+  build_render_pipeline(
+    device,
+    render_pipeline_layout,
+    color_format,
+    depth_format,
+    vertex_layouts,
+    shader,
+    vert_entry,
+    frag_entry,
+    "Render Pipeline",
+    true,
+    wgpu::BlendState::REPLACE
+  )
+}
+
+// same as get_render_pipeline but without depth writes, for alpha blended
+// models. they still test against the opaque depth so they're hidden behind
+// solid geometry, but don't hide each other (or themselves) when drawn back
+// to front
+pub fn get_transparent_render_pipeline(
+  device: &wgpu::Device,
+  render_pipeline_layout: &wgpu::PipelineLayout,
+  color_format: wgpu::TextureFormat,
+  depth_format: Option<wgpu::TextureFormat>,
+  vertex_layouts: &[wgpu::VertexBufferLayout],
+  shader: wgpu::ShaderModuleDescriptor,
+  vert_entry: &str,
+  frag_entry: &str,
+) -> wgpu::RenderPipeline {
+  build_render_pipeline(
+    device,
+    render_pipeline_layout,
+    color_format,
+    depth_format,
+    vertex_layouts,
+    shader,
+    vert_entry,
+    frag_entry,
+    "Transparent Render Pipeline",
+    false,
+    wgpu::BlendState::ALPHA_BLENDING
+  )
+}
+
+fn build_render_pipeline(
+  device: &wgpu::Device,
+  render_pipeline_layout: &wgpu::PipelineLayout,
+  color_format: wgpu::TextureFormat,
+  depth_format: Option<wgpu::TextureFormat>,
+  vertex_layouts: &[wgpu::VertexBufferLayout],
+  shader: wgpu::ShaderModuleDescriptor,
+  vert_entry: &str,
+  frag_entry: &str,
+  label: &str,
+  depth_write_enabled: bool,
+  blend: wgpu::BlendState, // only the transparent pipeline blends
 ) -> wgpu::RenderPipeline {
   let shader = device.create_shader_module(shader);
 
   device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-    label: Some("Render Pipeline"),
+    label: Some(label),
     layout: Some(&render_pipeline_layout),
     vertex: wgpu::VertexState {
       module: &shader,
@@ -31,7 +88,7 @@ pub fn get_render_pipeline(
       entry_point: frag_entry,
       targets: &[Some(wgpu::ColorTargetState { // 4.
         format: color_format,
-        blend: Some(wgpu::BlendState::REPLACE),
+        blend: Some(blend),
         write_mask: wgpu::ColorWrites::ALL,
       })],
     }),
@@ -46,7 +103,7 @@ pub fn get_render_pipeline(
     },
     depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
       format,
-      depth_write_enabled: true,
+      depth_write_enabled,
       depth_compare: wgpu::CompareFunction::Less,
       stencil: wgpu::StencilState::default(),
       bias: wgpu::DepthBiasState::default(),
@@ -59,3 +116,49 @@ pub fn get_render_pipeline(
     multiview: None,
   })
 }
+
+// depth-only pipeline used for the shadow pass. there's no fragment stage,
+// only the depth written from the light's point of view matters
+pub fn get_shadow_pipeline(
+  device: &wgpu::Device,
+  layout: &wgpu::PipelineLayout,
+  vertex_layouts: &[wgpu::VertexBufferLayout],
+  shader: wgpu::ShaderModuleDescriptor,
+  vert_entry: &str,
+) -> wgpu::RenderPipeline {
+  let shader = device.create_shader_module(shader);
+
+  device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Shadow Pipeline"),
+    layout: Some(layout),
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: vert_entry,
+      buffers: vertex_layouts,
+    },
+    fragment: None,
+    primitive: wgpu::PrimitiveState {
+      topology: wgpu::PrimitiveTopology::TriangleList,
+      strip_index_format: None,
+      front_face: wgpu::FrontFace::Ccw,
+      cull_mode: Some(wgpu::Face::Back),
+      unclipped_depth: false,
+      polygon_mode: wgpu::PolygonMode::Fill,
+      conservative: false,
+    },
+    depth_stencil: Some(wgpu::DepthStencilState {
+      format: Texture::DEPTH_FORMAT,
+      depth_write_enabled: true,
+      depth_compare: wgpu::CompareFunction::Less,
+      stencil: wgpu::StencilState::default(),
+      // bias against shadow acne
+      bias: wgpu::DepthBiasState {
+        constant: 2,
+        slope_scale: 2.0,
+        clamp: 0.0,
+      },
+    }),
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  })
+}
@@ -0,0 +1,179 @@
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RectVertex {
+  pub position: [f32; 2], // clip space
+  pub color: [f32; 4],
+}
+
+// a filled rectangle in screen pixels (origin top left)
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenRect {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+  pub color: [f32; 4],
+}
+
+// Immediate mode 2d rectangles for things like health bars and crosshairs.
+// Rects are queued during the frame, drawn over the 3d scene (under any
+// text) with no depth test and cleared once the frame is presented
+pub struct RectRenderer {
+  rects: Vec<ScreenRect>,
+  pipeline: wgpu::RenderPipeline,
+  vertex_buffer: wgpu::Buffer,
+  vertex_capacity: usize,
+  vertex_count: u32,
+}
+
+impl RectRenderer {
+  pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> RectRenderer {
+    let layout = device.create_pipeline_layout(
+      &wgpu::PipelineLayoutDescriptor {
+        label: Some("Rect pipeline layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+      }
+    );
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Rect shader"),
+      source: wgpu::ShaderSource::Wgsl(include_str!("rect.wgsl").into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Rect pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[wgpu::VertexBufferLayout {
+          array_stride: std::mem::size_of::<RectVertex>() as wgpu::BufferAddress,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+        }],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format: color_format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        cull_mode: None,
+        ..Default::default()
+      },
+      // drawn in the overlay pass over the finished frame
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    let vertex_capacity = 6 * 64;
+    let vertex_buffer = create_vertex_buffer(device, vertex_capacity);
+
+    Self {
+      rects: Vec::new(),
+      pipeline,
+      vertex_buffer,
+      vertex_capacity,
+      vertex_count: 0,
+    }
+  }
+
+  pub fn queue_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+    self.rects.push(ScreenRect { x, y, width, height, color });
+  }
+
+  pub fn rects(&self) -> &Vec<ScreenRect> {
+    &self.rects
+  }
+
+  // converts the queued rects to clip space and uploads them
+  pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, screen_size: (u32, u32)) {
+    let vertices = self.rects.iter()
+      .flat_map(|rect| rect_vertices(rect, screen_size))
+      .collect::<Vec<RectVertex>>();
+    if vertices.len() > self.vertex_capacity {
+      self.vertex_capacity = vertices.len().next_power_of_two();
+      self.vertex_buffer = create_vertex_buffer(device, self.vertex_capacity);
+    }
+    if vertices.len() > 0 {
+      queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+    self.vertex_count = vertices.len() as u32;
+  }
+
+  pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+    if self.vertex_count == 0 {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.draw(0..self.vertex_count, 0..1);
+  }
+
+  pub fn clear(&mut self) {
+    self.rects.clear();
+    self.vertex_count = 0;
+  }
+}
+
+// the two triangles covering rect, in clip space for a screen of the given
+// size in pixels
+pub fn rect_vertices(rect: &ScreenRect, screen_size: (u32, u32)) -> [RectVertex; 6] {
+  let (w, h) = (screen_size.0.max(1) as f32, screen_size.1.max(1) as f32);
+  let to_clip = |px: f32, py: f32| [px / w * 2. - 1., 1. - py / h * 2.];
+  let corners = [
+    to_clip(rect.x, rect.y),
+    to_clip(rect.x, rect.y + rect.height),
+    to_clip(rect.x + rect.width, rect.y + rect.height),
+    to_clip(rect.x + rect.width, rect.y),
+  ];
+  [0, 1, 2, 0, 2, 3].map(|idx| RectVertex { position: corners[idx], color: rect.color })
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+  device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Rect vertex buffer"),
+    size: (capacity * std::mem::size_of::<RectVertex>()) as wgpu::BufferAddress,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rect(x: f32, y: f32, width: f32, height: f32) -> ScreenRect {
+    ScreenRect { x, y, width, height, color: [1., 0., 0., 1.] }
+  }
+
+  #[test]
+  fn rect_vertices_map_pixels_to_clip_space() {
+    let vertices = rect_vertices(&rect(0., 0., 100., 50.), (200, 100));
+    let positions = vertices.map(|v| v.position);
+    assert_eq!(positions, [
+      [-1., 1.], [-1., 0.], [0., 0.],
+      [-1., 1.], [0., 0.], [0., 1.],
+    ]);
+    assert!(vertices.iter().all(|v| v.color == [1., 0., 0., 1.]));
+  }
+
+  #[test]
+  fn full_screen_rect_covers_clip_space() {
+    let vertices = rect_vertices(&rect(0., 0., 640., 480.), (640, 480));
+    for v in vertices.iter() {
+      assert!(v.position[0].abs() == 1. && v.position[1].abs() == 1.);
+    }
+  }
+
+  #[test]
+  fn zero_sized_screens_dont_divide_by_zero() {
+    let vertices = rect_vertices(&rect(0., 0., 1., 1.), (0, 0));
+    assert!(vertices.iter().all(|v| v.position.iter().all(|c| c.is_finite())));
+  }
+}
@@ -1,6 +1,7 @@
 use std::io::{BufReader, Cursor};
 
 use cfg_if::cfg_if;
+use cgmath::{InnerSpace, Vector2, Vector3};
 use wgpu::util::{
   DeviceExt
 };
@@ -20,8 +21,7 @@ pub async fn load_texture(
   device: &wgpu::Device,
   queue: &wgpu::Queue
 ) -> anyhow::Result<Texture> {
-  let data = load_binary(file_name).await?;
-  Texture::from_bytes(device, queue, &data, file_name, is_normal_map)
+  Texture::from_path(device, queue, file_name, is_normal_map).await
 }
 
 pub async fn load_model(
@@ -30,7 +30,38 @@ pub async fn load_model(
   queue: &wgpu::Queue,
   layout: &wgpu::BindGroupLayout
 ) -> anyhow::Result<Model> {
+  let source = read_model_source(file_name, None).await?;
+  build_model(source, device, queue, layout)
+}
+
+// texture file name and its encoded bytes
+type TextureSource = (String, Vec<u8>);
+
+struct MaterialSource {
+  name: String,
+  diffuse: TextureSource,
+  normal: TextureSource,
+  emissive: Option<TextureSource>,
+  metallic: Option<f32>,
+  roughness: Option<f32>,
+}
+
+// Everything an obj file needs read from disk (geometry, materials and
+// encoded textures) but no gpu resources, so it can be produced off the
+// render thread and finished later with build_model
+pub struct ModelSource {
+  file_name: String,
+  models: Vec<tobj::Model>,
+  materials: Vec<MaterialSource>,
+}
+
+// progress is called with (bytes loaded, total bytes) after each file is read
+pub async fn read_model_source(
+  file_name: &str,
+  progress: Option<&(dyn Fn(u64, u64) + Send + Sync)>
+) -> anyhow::Result<ModelSource> {
   let obj_text = load_string(file_name).await?;
+  let mut loaded = obj_text.len() as u64;
   let obj_cursor = Cursor::new(obj_text);
   let mut obj_reader = BufReader::new(obj_cursor);
 
@@ -46,21 +77,110 @@ pub async fn load_model(
       tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
     }
   ).await?;
+  let obj_materials = obj_materials?;
+
+  let material_files = obj_materials.iter()
+    .map(material_texture_files)
+    .collect::<Vec<Vec<String>>>();
+  let total = loaded + material_files.iter()
+    .flatten()
+    .map(|f| resource_size(f).unwrap_or(0))
+    .sum::<u64>();
+  if let Some(progress) = progress {
+    progress(loaded, total);
+  }
+
+  let mut materials: Vec<MaterialSource> = Vec::new();
+  for (m, files) in obj_materials.into_iter().zip(material_files) {
+    let mut textures: Vec<TextureSource> = Vec::new();
+    for file in files {
+      let bytes = load_binary(&file).await?;
+      loaded += bytes.len() as u64;
+      if let Some(progress) = progress {
+        progress(loaded, total.max(loaded));
+      }
+      textures.push((file, bytes));
+    }
+    let mut textures = textures.into_iter();
+    materials.push(MaterialSource {
+      name: m.name.clone(),
+      diffuse: textures.next().unwrap(),
+      normal: textures.next().unwrap(),
+      emissive: textures.next(),
+      metallic: m.unknown_param.get("Pm").and_then(|v| v.parse::<f32>().ok()),
+      roughness: m.unknown_param.get("Pr").and_then(|v| v.parse::<f32>().ok()),
+    });
+  }
+
+  Ok(ModelSource {
+    file_name: file_name.into(),
+    models,
+    materials
+  })
+}
+
+// diffuse, normal and (for pbr materials) emissive texture files.
+// pbr extension params (Pm/Pr/map_Ke) end up in unknown_param
+fn material_texture_files(m: &tobj::Material) -> Vec<String> {
+  let mut files = vec![m.diffuse_texture.clone(), m.normal_texture.clone()];
+  let is_pbr = m.unknown_param.contains_key("Pm") || m.unknown_param.contains_key("Pr");
+  if is_pbr {
+    files.extend(m.unknown_param.get("map_Ke").cloned());
+  }
+  files
+}
+
+// creates the gpu side of a model read by read_model_source
+pub fn build_model(
+  source: ModelSource,
+  device: &wgpu::Device,
+  queue: &wgpu::Queue,
+  layout: &wgpu::BindGroupLayout
+) -> anyhow::Result<Model> {
+  let ModelSource { file_name, models, materials: material_sources } = source;
+  let file_name = file_name.as_str();
 
   let mut materials: Vec<Material> = Vec::new();
-  for m in obj_materials? {
-    let diffuse_texture = load_texture(&m.diffuse_texture, false, device, queue).await?;
-    let normal_texture = load_texture(&m.normal_texture, true, device, queue).await?;
+  for m in material_sources {
+    let diffuse_texture = Texture::from_bytes(device, queue, &m.diffuse.1, &m.diffuse.0, false)?;
+    let normal_texture = Texture::from_bytes(device, queue, &m.normal.1, &m.normal.0, true)?;
 
-    materials.push(Material::new(
+    if m.metallic.is_none() && m.roughness.is_none() {
+      materials.push(Material::new(
+        &device,
+        &queue,
+        &m.name,
+        diffuse_texture,
+        normal_texture,
+        layout
+      ));
+      continue;
+    }
+    let emissive_texture = match m.emissive {
+      Some((path, bytes)) => Some(Texture::from_bytes(device, queue, &bytes, &path, false)?),
+      None => None
+    };
+    materials.push(Material::new_pbr(
       &device,
+      &queue,
       &m.name,
       diffuse_texture,
       normal_texture,
+      m.metallic.unwrap_or(0.),
+      m.roughness.unwrap_or(0.5),
+      None,
+      emissive_texture,
       layout
     ));
   }
 
+  // radius of a sphere around the model origin containing every vertex,
+  // used for frustum culling
+  let bounding_radius = models.iter()
+    .flat_map(|m| m.mesh.positions.chunks(3))
+    .map(|p| (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt())
+    .fold(0., f32::max);
+
   let meshes = models
     .into_iter()
     .map(|m| {
@@ -71,76 +191,40 @@ pub async fn load_model(
               m.mesh.positions[i * 3 + 1],
               m.mesh.positions[i * 3 + 2],
           ],
-          tex_coords: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
-          normal: [
+          tex_coords: if m.mesh.texcoords.len() >= (i + 1) * 2 {
+            [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]]
+          } else {
+            [0.0; 2]
+          },
+          // filled in below when the obj has no vn lines
+          normal: if m.mesh.normals.len() >= (i + 1) * 3 {
+            [
               m.mesh.normals[i * 3],
               m.mesh.normals[i * 3 + 1],
               m.mesh.normals[i * 3 + 2],
-          ],
+            ]
+          } else {
+            [0.0; 3]
+          },
           tangent: [0.0; 3],
           bitangent: [0.0; 3],
+          // filled from `v x y z r g b` lines when present
+          color: if m.mesh.vertex_color.len() >= (i + 1) * 3 {
+            [
+              m.mesh.vertex_color[i * 3],
+              m.mesh.vertex_color[i * 3 + 1],
+              m.mesh.vertex_color[i * 3 + 2],
+            ]
+          } else {
+            [1.0; 3]
+          },
         })
         .collect::<Vec<_>>();
 
-      let indices = &m.mesh.indices;
-      let mut triangles_included = vec![0; vertices.len()];
-
-      for c in indices.chunks(3) {
-        let v0 = vertices[c[0] as usize];
-        let v1 = vertices[c[1] as usize];
-        let v2 = vertices[c[2] as usize];
-
-        let pos0: cgmath::Vector3<_> = v0.position.into();
-        let pos1: cgmath::Vector3<_> = v1.position.into();
-        let pos2: cgmath::Vector3<_> = v2.position.into();
-
-        let uv0: cgmath::Vector2<_> = v0.tex_coords.into();
-        let uv1: cgmath::Vector2<_> = v1.tex_coords.into();
-        let uv2: cgmath::Vector2<_> = v2.tex_coords.into();
-
-        let delta_pos1 = pos1 - pos0;
-        let delta_pos2 = pos2 - pos0;
-
-        let delta_uv1 = uv1 - uv0;
-        let delta_uv2 = uv2 - uv0;
-
-        // Solving the following system of equations will
-        // give us the tangent and bitangent.
-        //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
-        //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
-        // Luckily, the place I found this equation provided
-        // the solution!
-        let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-        // We flip the bitangent to enable right-handed normal
-        // maps with wgpu texture coordinate system
-        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
-
-        vertices[c[0] as usize].tangent = 
-          (tangent + cgmath::Vector3::from(vertices[c[0] as usize].tangent)).into();
-        vertices[c[1] as usize].tangent =
-          (tangent + cgmath::Vector3::from(vertices[c[1] as usize].tangent)).into();
-        vertices[c[2] as usize].tangent =
-          (tangent + cgmath::Vector3::from(vertices[c[2] as usize].tangent)).into();
-        vertices[c[0] as usize].bitangent =
-          (bitangent + cgmath::Vector3::from(vertices[c[0] as usize].bitangent)).into();
-        vertices[c[1] as usize].bitangent =
-          (bitangent + cgmath::Vector3::from(vertices[c[1] as usize].bitangent)).into();
-        vertices[c[2] as usize].bitangent =
-          (bitangent + cgmath::Vector3::from(vertices[c[2] as usize].bitangent)).into();
-
-        // Used to average the tangents/bitangents
-        triangles_included[c[0] as usize] += 1;
-        triangles_included[c[1] as usize] += 1;
-        triangles_included[c[2] as usize] += 1;
-      }
-
-      for (i, n) in triangles_included.into_iter().enumerate() {
-        let denom = 1.0 / n as f32;
-        let mut v = &mut vertices[i];
-        v.tangent = (cgmath::Vector3::from(v.tangent) * denom).into();
-        v.bitangent = (cgmath::Vector3::from(v.bitangent) * denom).into();
+      if m.mesh.normals.len() < vertices.len() * 3 {
+        compute_normals(&mut vertices, &m.mesh.indices);
       }
+      compute_tangents(&mut vertices, &m.mesh.indices);
 
       let vertex_buffer = device.create_buffer_init(
         &wgpu::util::BufferInitDescriptor {
@@ -149,12 +233,11 @@ pub async fn load_model(
           usage: wgpu::BufferUsages::VERTEX,
         }
       );
-      let index_buffer = device.create_buffer_init(
-        &  wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Index Buffer", file_name)),
-        contents: bytemuck::cast_slice(&m.mesh.indices),
-        usage: wgpu::BufferUsages::INDEX,
-        }
+      let (index_buffer, index_format) = Mesh::create_index_buffer(
+        device,
+        &format!("{:?} Index Buffer", file_name),
+        &m.mesh.indices,
+        vertices.len()
       );
 
       Mesh {
@@ -162,17 +245,137 @@ pub async fn load_model(
         vertex_buffer,
         index_buffer,
         num_elements: m.mesh.indices.len() as u32,
-        material: m.mesh.material_id.unwrap_or(0)
+        material: m.mesh.material_id.unwrap_or(0),
+        index_format,
+        vertices,
+        indices: m.mesh.indices
       }
     })
     .collect::<Vec<_>>();
   
   Ok(Model {
     meshes,
-    materials
+    materials,
+    bounding_radius
   })
 }
 
+// area weighted vertex normals, for meshes without vn lines
+fn compute_normals(vertices: &mut Vec<ModelVertex>, indices: &[u32]) {
+  let mut normals = vec![Vector3::new(0., 0., 0.); vertices.len()];
+  for c in indices.chunks(3).filter(|c| c.len() == 3) {
+    let p0: Vector3<f32> = vertices[c[0] as usize].position.into();
+    let p1: Vector3<f32> = vertices[c[1] as usize].position.into();
+    let p2: Vector3<f32> = vertices[c[2] as usize].position.into();
+    // unnormalized, so larger faces count for more
+    let face_normal = (p1 - p0).cross(p2 - p0);
+    for i in c {
+      normals[*i as usize] += face_normal;
+    }
+  }
+  for (v, n) in vertices.iter_mut().zip(normals) {
+    if n.magnitude2() > 0. {
+      v.normal = n.normalize().into();
+    }
+  }
+}
+
+// per vertex tangent space for normal mapping. tangents are accumulated
+// from each triangle's uv derivatives, then orthonormalized against the
+// vertex normal (gram-schmidt). the bitangent is rebuilt from the normal
+// and tangent, keeping the handedness of the accumulated one
+fn compute_tangents(vertices: &mut Vec<ModelVertex>, indices: &[u32]) {
+  let mut tangents = vec![Vector3::new(0., 0., 0.); vertices.len()];
+  let mut bitangents = vec![Vector3::new(0., 0., 0.); vertices.len()];
+
+  for c in indices.chunks(3).filter(|c| c.len() == 3) {
+    let v0 = vertices[c[0] as usize];
+    let v1 = vertices[c[1] as usize];
+    let v2 = vertices[c[2] as usize];
+
+    let pos0: Vector3<f32> = v0.position.into();
+    let pos1: Vector3<f32> = v1.position.into();
+    let pos2: Vector3<f32> = v2.position.into();
+
+    let uv0: Vector2<f32> = v0.tex_coords.into();
+    let uv1: Vector2<f32> = v1.tex_coords.into();
+    let uv2: Vector2<f32> = v2.tex_coords.into();
+
+    let delta_pos1 = pos1 - pos0;
+    let delta_pos2 = pos2 - pos0;
+
+    let delta_uv1 = uv1 - uv0;
+    let delta_uv2 = uv2 - uv0;
+
+    // Solving the following system of equations will
+    // give us the tangent and bitangent.
+    //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
+    //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+    let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+    // degenerate (or missing) uvs give no usable direction
+    if det.abs() < 1e-8 {
+      continue;
+    }
+    let r = 1.0 / det;
+    let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+    // We flip the bitangent to enable right-handed normal
+    // maps with wgpu texture coordinate system
+    let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+    for i in c {
+      tangents[*i as usize] += tangent;
+      bitangents[*i as usize] += bitangent;
+    }
+  }
+
+  for (i, v) in vertices.iter_mut().enumerate() {
+    let normal: Vector3<f32> = v.normal.into();
+    if normal.magnitude2() == 0. {
+      continue;
+    }
+    let normal = normal.normalize();
+    let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+    if tangent.magnitude2() < 1e-12 {
+      // no uv information, any direction perpendicular to the normal will do
+      let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+      tangent = axis - normal * normal.dot(axis);
+    }
+    let tangent = tangent.normalize();
+    let mut bitangent = normal.cross(tangent);
+    if bitangent.dot(bitangents[i]) < 0. {
+      bitangent = -bitangent;
+    }
+    v.tangent = tangent.into();
+    v.bitangent = bitangent.into();
+  }
+}
+
+// cpu side positions and indices of every mesh in an obj file, merged into
+// one buffer. used for collision geometry rather than rendering
+pub async fn load_mesh_positions(file_name: &str) -> anyhow::Result<(Vec<[f32; 3]>, Vec<u32>)> {
+  let obj_text = load_string(file_name).await?;
+  let mut obj_reader = BufReader::new(Cursor::new(obj_text));
+  let (models, _) = tobj::load_obj_buf(
+    &mut obj_reader,
+    &tobj::LoadOptions {
+      triangulate: true,
+      single_index: true,
+      ..Default::default()
+    },
+    // materials aren't needed for collision geometry
+    |_| Ok(Default::default())
+  )?;
+
+  let mut positions: Vec<[f32; 3]> = Vec::new();
+  let mut indices: Vec<u32> = Vec::new();
+  for m in models {
+    let offset = positions.len() as u32;
+    positions.extend(m.mesh.positions.chunks(3).map(|p| [p[0], p[1], p[2]]));
+    indices.extend(m.mesh.indices.iter().map(|i| i + offset));
+  }
+  Ok((positions, indices))
+}
+
 #[cfg(target_arch = "wasm32")]
 fn format_url(file_name: &str) -> reqwest::Url {
     let window = web_sys::window().unwrap();
@@ -204,6 +407,21 @@ pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
     Ok(txt)
 }
 
+// size of a resource without reading it, when the platform allows it
+pub fn resource_size(file_name: &str) -> Option<u64> {
+    cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            let _ = file_name;
+            None
+        } else {
+            let path = std::path::Path::new(env!("OUT_DIR"))
+                .join("res")
+                .join(file_name);
+            std::fs::metadata(path).ok().map(|m| m.len())
+        }
+    }
+}
+
 pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
     cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
@@ -0,0 +1,150 @@
+use wgpu::util::DeviceExt;
+
+use super::texture::Texture;
+
+// unit cube drawn around the camera, as a plain triangle list
+const CUBE_VERTICES: [[f32; 3]; 36] = [
+  // +x
+  [1., -1., -1.], [1., -1., 1.], [1., 1., 1.], [1., -1., -1.], [1., 1., 1.], [1., 1., -1.],
+  // -x
+  [-1., -1., 1.], [-1., -1., -1.], [-1., 1., -1.], [-1., -1., 1.], [-1., 1., -1.], [-1., 1., 1.],
+  // +y
+  [-1., 1., -1.], [1., 1., -1.], [1., 1., 1.], [-1., 1., -1.], [1., 1., 1.], [-1., 1., 1.],
+  // -y
+  [-1., -1., 1.], [1., -1., 1.], [1., -1., -1.], [-1., -1., 1.], [1., -1., -1.], [-1., -1., -1.],
+  // +z
+  [1., -1., 1.], [-1., -1., 1.], [-1., 1., 1.], [1., -1., 1.], [-1., 1., 1.], [1., 1., 1.],
+  // -z
+  [-1., -1., -1.], [1., -1., -1.], [1., 1., -1.], [-1., -1., -1.], [1., 1., -1.], [-1., 1., -1.],
+];
+
+// Environment background drawn before the scene's models. The cube is
+// rendered at the far plane with depth writes off so everything else
+// draws over it
+pub struct Skybox {
+  pub cubemap: Texture,
+  pipeline: wgpu::RenderPipeline,
+  bind_group: wgpu::BindGroup,
+  vertex_buffer: wgpu::Buffer,
+}
+
+impl Skybox {
+  pub fn new(
+    device: &wgpu::Device,
+    color_format: wgpu::TextureFormat,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    cubemap: Texture,
+  ) -> Skybox {
+    let bind_group_layout = device.create_bind_group_layout(
+      &wgpu::BindGroupLayoutDescriptor {
+        label: Some("Skybox bind group layout"),
+        entries: &[
+          wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+              multisampled: false,
+              view_dimension: wgpu::TextureViewDimension::Cube,
+              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+          },
+        ]
+      }
+    );
+    let bind_group = device.create_bind_group(
+      &wgpu::BindGroupDescriptor {
+        label: Some("Skybox bind group"),
+        layout: &bind_group_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&cubemap.view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&cubemap.sampler),
+          },
+        ]
+      }
+    );
+
+    let vertex_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Skybox vertex buffer"),
+        contents: bytemuck::cast_slice(&CUBE_VERTICES),
+        usage: wgpu::BufferUsages::VERTEX,
+      }
+    );
+
+    let layout = device.create_pipeline_layout(
+      &wgpu::PipelineLayoutDescriptor {
+        label: Some("Skybox pipeline layout"),
+        bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+        push_constant_ranges: &[],
+      }
+    );
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Skybox shader"),
+      source: wgpu::ShaderSource::Wgsl(include_str!("skybox.wgsl").into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Skybox pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[wgpu::VertexBufferLayout {
+          array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+        }],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format: color_format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        // the camera is inside the cube
+        cull_mode: None,
+        ..Default::default()
+      },
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    Self {
+      cubemap,
+      pipeline,
+      bind_group,
+      vertex_buffer,
+    }
+  }
+
+  pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &self.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.draw(0..CUBE_VERTICES.len() as u32, 0..1);
+  }
+}
@@ -0,0 +1,32 @@
+// Skybox shader - samples a cubemap by view direction
+struct CameraUniform {
+  view_pos: vec4<f32>,
+  view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) direction: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+  var out: VertexOutput;
+  // keep the cube centered on the camera and push it to the far plane
+  let clip = camera.view_proj * vec4<f32>(position + camera.view_pos.xyz, 1.0);
+  out.clip_position = clip.xyww;
+  out.direction = position;
+  return out;
+}
+
+@group(1) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(1) @binding(1)
+var s_sky: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  return textureSample(t_sky, s_sky, normalize(in.direction));
+}
@@ -10,6 +10,7 @@ use crate::graphics::texture;
 use super::{camera::Projection, pipeline::get_render_pipeline};
 use super::vertex;
 use super::texture::Texture;
+use super::bind_group_cache::BindGroupLayoutCache;
 use super::camera::{
   Camera,
   CameraController,
@@ -57,7 +58,8 @@ pub struct State {
   pub index_buffer: wgpu::Buffer,
   pub num_vertices: u32,
   pub num_indices: u32,
-  pub texture_bind_group_layout: wgpu::BindGroupLayout,
+  pub bind_group_layouts: BindGroupLayoutCache,
+  pub texture_bind_group_layout: std::sync::Arc<wgpu::BindGroupLayout>,
   pub texture_bind_group: wgpu::BindGroup,
   pub diffuse_texture: Texture,
   pub camera: Camera,
@@ -78,7 +80,6 @@ pub struct State {
   pub light_render_pipeline: wgpu::RenderPipeline,
   pub mouse_pressed:bool,
   clear_color: (f64, f64, f64, f64),
-  pos_shading: bool,
 }
 
 impl State {
@@ -141,72 +142,11 @@ impl State {
     let diffuse_texture = Texture::from_bytes(&device, &queue, diffuse_bytes, "stargate.jpeg", false).unwrap();
     let normal_texture = Texture::from_bytes(&device, &queue, diffuse_bytes, "stargate.jpeg", true).unwrap();
 
-    let texture_bind_group_layout = device.create_bind_group_layout(
-      &wgpu::BindGroupLayoutDescriptor { 
-        label: Some("Texture bind group layout"), 
-        entries: &[
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              multisampled: false,
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            // This should match the filterable field of the
-            // corresponding Texture entry above.
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              multisampled: false,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-              view_dimension: wgpu::TextureViewDimension::D2
-            },
-            count: None
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 3,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None
-          }
-        ] 
-      }
-    );
-    let texture_bind_group = device.create_bind_group(
-      &wgpu::BindGroupDescriptor {
-        layout: &texture_bind_group_layout,
-        entries:&[
-          wgpu::BindGroupEntry {
-            binding: 0,
-            resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-          },
-          wgpu::BindGroupEntry {
-            binding: 1,
-            resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-          },
-          // NEW!
-          wgpu::BindGroupEntry {
-            binding: 2,
-            resource: wgpu::BindingResource::TextureView(&normal_texture.view),
-          },
-          wgpu::BindGroupEntry {
-            binding: 3,
-            resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
-          },
-        ],
-        label: Some("diffuse_bind_group"),
-      }
-    );
+    let mut bind_group_layouts = BindGroupLayoutCache::new();
+    let texture_bind_group_layout = bind_group_layouts.get_or_create(&device, "Texture bind group layout", &model::Material::bind_group_layout_entries());
+    let stargate_material = model::Material::new(&device, &queue, "stargate.jpeg", diffuse_texture, normal_texture, &texture_bind_group_layout);
+    let texture_bind_group = stargate_material.bind_group;
+    let diffuse_texture = stargate_material.diffuse_texture;
 
     // buffer creation
     let vertex_buffer = device.create_buffer_init(
@@ -292,7 +232,7 @@ impl State {
         };
 
         Instance {
-          position, rotation
+          position, rotation, scale: cgmath::Vector3::new(1., 1., 1.), color: [1., 1., 1., 1.]
         }
       })
     }).collect::<Vec<_>>();
@@ -317,7 +257,7 @@ impl State {
       position: [2.0, 10.0, 2.0],
       _padding: 0,
       color: [1.0, 1.0, 1.0],
-      _padding_2: 0,
+      shading_mode: lighting::ShadingMode::Lit as u32,
     };
     let light_buffer = lighting::get_light_buffer(&device, &light_uniform);
     let (light_bind_group_layout, light_bind_group) = lighting::get_light_bind_group_info(&device, &light_buffer);
@@ -325,7 +265,7 @@ impl State {
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Render Pipeline Layout"),
       bind_group_layouts: &[
-        &texture_bind_group_layout,
+        texture_bind_group_layout.as_ref(),
         &camera_bind_group_layout,
         &light_bind_group_layout,
       ],
@@ -393,6 +333,7 @@ impl State {
       num_vertices,
       num_indices,
       texture_bind_group,
+      bind_group_layouts,
       texture_bind_group_layout,
       diffuse_texture,
       camera,
@@ -413,7 +354,6 @@ impl State {
       clear_color,
       light_render_pipeline,
       mouse_pressed: false,
-      pos_shading: false,
     }
   }
 
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// each glyph sits in a cell with a 1px empty border so nearest sampling
+// at the quad edges never picks up a neighbouring glyph
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 2;
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+const ATLAS_COLUMNS: u32 = 16;
+
+// 5x7 bitmap font, one byte per row with the leftmost pixel in bit 4.
+// lowercase letters use the uppercase glyphs and anything missing draws as '?'
+const FONT: [(char, [u8; 7]); 59] = [
+  (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+  ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+  ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+  ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+  ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+  ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+  ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+  ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+  ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+  ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+  ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+  ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+  ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+  ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+  ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+  ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+  ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+  ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+  ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+  ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+  ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+  ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+  ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+  ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+  ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+  ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+  ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+  ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+  ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+  ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+  ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+  ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+  ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+  ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+  ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+  ('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+  ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+  ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+  (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+  (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+  (';', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000]),
+  ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+  ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+  ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+  ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+  ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+  ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+  ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+  (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+  ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110]),
+  (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110]),
+  ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+  ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+  ('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+  ('"', [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000]),
+  ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+  ('<', [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010]),
+  ('>', [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000]),
+  ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+  position: [f32; 2],
+  tex_coords: [f32; 2],
+  color: [f32; 4],
+}
+
+// one glyph in screen pixels (origin top left), converted to clip space on upload
+#[derive(Clone, Copy, Debug)]
+pub struct GlyphQuad {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+  pub uv_min: [f32; 2],
+  pub uv_max: [f32; 2],
+  pub color: [f32; 4],
+}
+
+// single channel coverage texture holding every glyph in FONT
+pub struct GlyphAtlas {
+  pub width: u32,
+  pub height: u32,
+  pub pixels: Vec<u8>,
+  cells: HashMap<char, u32>,
+}
+
+impl GlyphAtlas {
+  pub fn new() -> GlyphAtlas {
+    let rows = (FONT.len() as u32 + ATLAS_COLUMNS - 1) / ATLAS_COLUMNS;
+    let width = ATLAS_COLUMNS * CELL_WIDTH;
+    let height = rows * CELL_HEIGHT;
+    let mut pixels = vec![0u8; (width * height) as usize];
+    let mut cells = HashMap::new();
+    for (idx, (ch, bitmap)) in FONT.iter().enumerate() {
+      let idx = idx as u32;
+      let origin_x = (idx % ATLAS_COLUMNS) * CELL_WIDTH + 1;
+      let origin_y = (idx / ATLAS_COLUMNS) * CELL_HEIGHT + 1;
+      for (row, bits) in bitmap.iter().enumerate() {
+        for col in 0..GLYPH_WIDTH {
+          if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+            let px = origin_x + col;
+            let py = origin_y + row as u32;
+            pixels[(py * width + px) as usize] = 255;
+          }
+        }
+      }
+      cells.insert(*ch, idx);
+    }
+    Self {
+      width,
+      height,
+      pixels,
+      cells
+    }
+  }
+
+  pub fn glyph_count(&self) -> usize {
+    self.cells.len()
+  }
+
+  pub fn has_glyph(&self, ch: char) -> bool {
+    self.cells.contains_key(&ch.to_ascii_uppercase())
+  }
+
+  // (uv_min, uv_max) of the glyph drawn for ch
+  pub fn uv_rect(&self, ch: char) -> ([f32; 2], [f32; 2]) {
+    let idx = self.cells.get(&ch.to_ascii_uppercase())
+      .or(self.cells.get(&'?'))
+      .map(|idx| *idx)
+      .unwrap_or(0);
+    let x = ((idx % ATLAS_COLUMNS) * CELL_WIDTH + 1) as f32;
+    let y = ((idx / ATLAS_COLUMNS) * CELL_HEIGHT + 1) as f32;
+    let (w, h) = (self.width as f32, self.height as f32);
+    (
+      [x / w, y / h],
+      [(x + GLYPH_WIDTH as f32) / w, (y + GLYPH_HEIGHT as f32) / h]
+    )
+  }
+}
+
+// Screen space text for debug HUDs. Strings are queued during the frame,
+// uploaded as quads and drawn over the finished scene, then cleared
+pub struct TextRenderer {
+  pub atlas: GlyphAtlas,
+  quads: Vec<GlyphQuad>,
+  pipeline: wgpu::RenderPipeline,
+  bind_group: wgpu::BindGroup,
+  vertex_buffer: wgpu::Buffer,
+  vertex_capacity: usize,
+  vertex_count: u32,
+}
+
+impl TextRenderer {
+  pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, color_format: wgpu::TextureFormat) -> TextRenderer {
+    let atlas = GlyphAtlas::new();
+    let size = wgpu::Extent3d {
+      width: atlas.width,
+      height: atlas.height,
+      depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Glyph atlas"),
+      size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::R8Unorm,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+      view_formats: &[],
+    });
+    queue.write_texture(
+      wgpu::ImageCopyTexture {
+        aspect: wgpu::TextureAspect::All,
+        texture: &texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+      },
+      &atlas.pixels,
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: Some(atlas.width),
+        rows_per_image: Some(atlas.height),
+      },
+      size,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    // nearest filtering keeps the scaled up pixels sharp
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      address_mode_w: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Nearest,
+      min_filter: wgpu::FilterMode::Nearest,
+      mipmap_filter: wgpu::FilterMode::Nearest,
+      ..Default::default()
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(
+      &wgpu::BindGroupLayoutDescriptor {
+        label: Some("Text bind group layout"),
+        entries: &[
+          wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+              multisampled: false,
+              view_dimension: wgpu::TextureViewDimension::D2,
+              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+          },
+        ]
+      }
+    );
+    let bind_group = device.create_bind_group(
+      &wgpu::BindGroupDescriptor {
+        label: Some("Text bind group"),
+        layout: &bind_group_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+          },
+        ]
+      }
+    );
+
+    let layout = device.create_pipeline_layout(
+      &wgpu::PipelineLayoutDescriptor {
+        label: Some("Text pipeline layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+      }
+    );
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Text shader"),
+      source: wgpu::ShaderSource::Wgsl(include_str!("text.wgsl").into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Text pipeline"),
+      layout: Some(&layout),
+      vertex: wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[wgpu::VertexBufferLayout {
+          array_stride: std::mem::size_of::<TextVertex>() as wgpu::BufferAddress,
+          step_mode: wgpu::VertexStepMode::Vertex,
+          attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4],
+        }],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: &shader,
+        entry_point: "fs_main",
+        targets: &[Some(wgpu::ColorTargetState {
+          format: color_format,
+          blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        cull_mode: None,
+        ..Default::default()
+      },
+      // drawn in its own pass over the finished frame
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    });
+
+    let vertex_capacity = 6 * 256;
+    let vertex_buffer = create_vertex_buffer(device, vertex_capacity);
+
+    Self {
+      atlas,
+      quads: Vec::new(),
+      pipeline,
+      bind_group,
+      vertex_buffer,
+      vertex_capacity,
+      vertex_count: 0,
+    }
+  }
+
+  // lays out text starting at (x, y) in pixels from the top left corner.
+  // size is the glyph height in pixels and '\n' starts a new line.
+  // returns the number of quads added (spaces don't get one)
+  pub fn queue_text(&mut self, text: &str, x: f32, y: f32, size: f32, color: [f32; 4]) -> usize {
+    let scale = size / GLYPH_HEIGHT as f32;
+    let advance = (GLYPH_WIDTH + 1) as f32 * scale;
+    let line_height = (GLYPH_HEIGHT + 2) as f32 * scale;
+    let mut cursor = (x, y);
+    let mut added = 0;
+    for ch in text.chars() {
+      if ch == '\n' {
+        cursor = (x, cursor.1 + line_height);
+        continue;
+      }
+      if !ch.is_whitespace() {
+        let (uv_min, uv_max) = self.atlas.uv_rect(ch);
+        self.quads.push(GlyphQuad {
+          x: cursor.0,
+          y: cursor.1,
+          width: GLYPH_WIDTH as f32 * scale,
+          height: size,
+          uv_min,
+          uv_max,
+          color
+        });
+        added += 1;
+      }
+      cursor.0 += advance;
+    }
+    added
+  }
+
+  pub fn quads(&self) -> &Vec<GlyphQuad> {
+    &self.quads
+  }
+
+  // converts the queued quads to clip space and uploads them
+  pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, screen_size: (u32, u32)) {
+    let (w, h) = (screen_size.0.max(1) as f32, screen_size.1.max(1) as f32);
+    let to_clip = |px: f32, py: f32| [px / w * 2. - 1., 1. - py / h * 2.];
+    let mut vertices: Vec<TextVertex> = Vec::with_capacity(self.quads.len() * 6);
+    for quad in self.quads.iter() {
+      let corners = [
+        (to_clip(quad.x, quad.y), [quad.uv_min[0], quad.uv_min[1]]),
+        (to_clip(quad.x, quad.y + quad.height), [quad.uv_min[0], quad.uv_max[1]]),
+        (to_clip(quad.x + quad.width, quad.y + quad.height), [quad.uv_max[0], quad.uv_max[1]]),
+        (to_clip(quad.x + quad.width, quad.y), [quad.uv_max[0], quad.uv_min[1]]),
+      ];
+      for idx in [0, 1, 2, 0, 2, 3] {
+        vertices.push(TextVertex {
+          position: corners[idx].0,
+          tex_coords: corners[idx].1,
+          color: quad.color
+        });
+      }
+    }
+    if vertices.len() > self.vertex_capacity {
+      self.vertex_capacity = vertices.len().next_power_of_two();
+      self.vertex_buffer = create_vertex_buffer(device, self.vertex_capacity);
+    }
+    if vertices.len() > 0 {
+      queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+    self.vertex_count = vertices.len() as u32;
+  }
+
+  pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+    if self.vertex_count == 0 {
+      return;
+    }
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, &self.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    render_pass.draw(0..self.vertex_count, 0..1);
+  }
+
+  pub fn clear(&mut self) {
+    self.quads.clear();
+    self.vertex_count = 0;
+  }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+  device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Text vertex buffer"),
+    size: (capacity * std::mem::size_of::<TextVertex>()) as wgpu::BufferAddress,
+    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    mapped_at_creation: false
+  })
+}
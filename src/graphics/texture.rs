@@ -4,6 +4,8 @@ use image::GenericImageView;
 use anyhow::*;
 use wgpu::util::DeviceExt;
 
+use super::resources::load_binary;
+
 #[derive(Debug)]
 pub struct Texture {
   pub texture: wgpu::Texture,
@@ -59,6 +61,51 @@ impl Texture {
     }
   }
 
+  // square depth texture rendered from the light's point of view. the
+  // sampler is a comparison sampler so the shader gets hardware pcf
+  pub fn create_shadow_map(device: &wgpu::Device, size: u32, label: &str) -> Self {
+    let texture = device.create_texture(
+      &wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+          width: size,
+          height: size,
+          depth_or_array_layers: 1
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Self::DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      }
+    );
+
+    let view = texture.create_view(
+      &wgpu::TextureViewDescriptor::default()
+    );
+    let sampler = device.create_sampler(
+      &wgpu::SamplerDescriptor {
+        label: Some("Shadow sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+        ..Default::default()
+      }
+    );
+
+    Self {
+      texture,
+      view,
+      sampler,
+    }
+  }
+
   pub fn from_bytes(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
@@ -70,12 +117,52 @@ impl Texture {
     Self::from_image(device, queue, &img, Some(label), is_normal_map)
   }
 
+  // path is resolved the same way as models: relative to the res directory
+  // on native, or fetched relative to the page origin on wasm
+  pub async fn from_path(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &str,
+    is_normal_map: bool,
+  ) -> Result<Self> {
+    let bytes = load_binary(path).await?;
+    Self::from_bytes(device, queue, &bytes, path, is_normal_map)
+  }
+
   pub fn from_image(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     img: &image::DynamicImage,
     label: Option<&str>,
     is_normal_map: bool,
+  ) -> Result<Self> {
+    Self::create_from_image(device, queue, img, label, is_normal_map, false)
+  }
+
+  // same as from_image but also generates the full mipmap chain so the
+  // texture doesn't alias when minified
+  pub fn from_image_mipmapped(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &image::DynamicImage,
+    label: Option<&str>,
+    is_normal_map: bool,
+  ) -> Result<Self> {
+    Self::create_from_image(device, queue, img, label, is_normal_map, true)
+  }
+
+  // number of levels in a full mip chain for a texture of the given size
+  pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+  }
+
+  fn create_from_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &image::DynamicImage,
+    label: Option<&str>,
+    is_normal_map: bool,
+    generate_mipmaps: bool,
   ) -> Result<Self> {
     let format = if is_normal_map {
       wgpu::TextureFormat::Rgba8Unorm
@@ -91,19 +178,30 @@ impl Texture {
       height: dimensions.1,
       depth_or_array_layers: 1
     };
+    let mip_level_count = if generate_mipmaps {
+      Self::mip_level_count(dimensions.0, dimensions.1)
+    } else {
+      1
+    };
+    // mip levels past the first are rendered into, so they need to be attachments
+    let usage = if generate_mipmaps {
+      wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::RENDER_ATTACHMENT
+    } else {
+      wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+    };
     let texture = device.create_texture(
       &wgpu::TextureDescriptor {
         // All textures are stored as 3D, we represent our 2D texture
         // by setting depth to 1.
         size: texture_size,
-        mip_level_count: 1, // We'll talk about this a little later
+        mip_level_count,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
         // Most images are stored using sRGB, so we need to reflect that here.
         format,
         // TEXTURE_BINDING tells wgpu that we want to use this texture in shaders
         // COPY_DST means that we want to copy data to this texture
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        usage,
         label: Some("diffuse_texture"),
         // This is the same as with the SurfaceConfig. It
         // specifies what texture formats can be used to
@@ -130,6 +228,9 @@ impl Texture {
       },
       texture_size
     );
+    if mip_level_count > 1 {
+      generate_mipmaps_for(device, queue, &texture, format, mip_level_count);
+    }
 
     let view = texture.create_view(
       &wgpu::TextureViewDescriptor::default()
@@ -141,6 +242,87 @@ impl Texture {
         address_mode_w: wgpu::AddressMode::ClampToEdge,
         mag_filter: wgpu::FilterMode::Linear,
         min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: if mip_level_count > 1 { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
+        lod_max_clamp: (mip_level_count - 1) as f32,
+        ..Default::default()
+      }
+    );
+
+    Ok(Self {
+      texture,
+      view,
+      sampler,
+    })
+  }
+
+  // builds a cube texture from six equally sized square faces in the
+  // order +x, -x, +y, -y, +z, -z
+  pub fn cubemap_from_faces(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    faces: [&image::DynamicImage; 6],
+    label: &str,
+  ) -> Result<Self> {
+    let dimensions = faces[0].dimensions();
+    if dimensions.0 != dimensions.1 || faces.iter().any(|f| f.dimensions() != dimensions) {
+      bail!("cubemap faces must be square and share the same dimensions");
+    }
+
+    let texture_size = wgpu::Extent3d {
+      width: dimensions.0,
+      height: dimensions.1,
+      depth_or_array_layers: 6
+    };
+    let texture = device.create_texture(
+      &wgpu::TextureDescriptor {
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: Some(label),
+        view_formats: &[],
+      }
+    );
+
+    for (layer, face) in faces.iter().enumerate() {
+      let rgba = face.to_rgba8();
+      queue.write_texture(
+        wgpu::ImageCopyTexture {
+          texture: &texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+          aspect: wgpu::TextureAspect::All,
+        },
+        &rgba,
+        wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: Some(4 * dimensions.0),
+          rows_per_image: Some(dimensions.1),
+        },
+        wgpu::Extent3d {
+          width: dimensions.0,
+          height: dimensions.1,
+          depth_or_array_layers: 1
+        }
+      );
+    }
+
+    let view = texture.create_view(
+      &wgpu::TextureViewDescriptor {
+        label: Some(label),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+      }
+    );
+    let sampler = device.create_sampler(
+      &wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
         mipmap_filter: wgpu::FilterMode::Nearest,
         ..Default::default()
       }
@@ -249,3 +431,97 @@ impl Texture {
     })
   }
 }
+
+// fills in mip levels 1..mip_level_count by repeatedly downsampling the
+// previous level with a linear filtered fullscreen blit
+fn generate_mipmaps_for(
+  device: &wgpu::Device,
+  queue: &wgpu::Queue,
+  texture: &wgpu::Texture,
+  format: wgpu::TextureFormat,
+  mip_level_count: u32,
+) {
+  let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    label: Some("Mipmap shader"),
+    source: wgpu::ShaderSource::Wgsl(include_str!("mipmap.wgsl").into()),
+  });
+  let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    label: Some("Mipmap pipeline"),
+    layout: None,
+    vertex: wgpu::VertexState {
+      module: &shader,
+      entry_point: "vs_main",
+      buffers: &[],
+    },
+    fragment: Some(wgpu::FragmentState {
+      module: &shader,
+      entry_point: "fs_main",
+      targets: &[Some(format.into())],
+    }),
+    primitive: wgpu::PrimitiveState {
+      topology: wgpu::PrimitiveTopology::TriangleList,
+      ..Default::default()
+    },
+    depth_stencil: None,
+    multisample: wgpu::MultisampleState::default(),
+    multiview: None,
+  });
+  let bind_group_layout = pipeline.get_bind_group_layout(0);
+  let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+    label: Some("Mipmap sampler"),
+    address_mode_u: wgpu::AddressMode::ClampToEdge,
+    address_mode_v: wgpu::AddressMode::ClampToEdge,
+    address_mode_w: wgpu::AddressMode::ClampToEdge,
+    mag_filter: wgpu::FilterMode::Linear,
+    min_filter: wgpu::FilterMode::Linear,
+    mipmap_filter: wgpu::FilterMode::Nearest,
+    ..Default::default()
+  });
+
+  let views = (0..mip_level_count)
+    .map(|mip| texture.create_view(&wgpu::TextureViewDescriptor {
+      label: Some("Mip view"),
+      base_mip_level: mip,
+      mip_level_count: Some(1),
+      ..Default::default()
+    }))
+    .collect::<Vec<wgpu::TextureView>>();
+
+  let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+    label: Some("Mipmap encoder")
+  });
+  for target_mip in 1..mip_level_count as usize {
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: None,
+      layout: &bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&views[target_mip - 1]),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&sampler),
+        },
+      ],
+    });
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Mipmap pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: &views[target_mip],
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          store: wgpu::StoreOp::Store,
+        },
+      })],
+      depth_stencil_attachment: None,
+      timestamp_writes: None,
+      occlusion_query_set: None,
+    });
+    render_pass.set_pipeline(&pipeline);
+    render_pass.set_bind_group(0, &bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+  queue.submit(std::iter::once(encoder.finish()));
+}
@@ -1,4 +1,3 @@
 pub mod pg_state;
-mod pg_cube;
-mod pg_vertex;
-mod pg_triangle;
\ No newline at end of file
+mod pg_mesh;
+mod pg_vertex;
\ No newline at end of file
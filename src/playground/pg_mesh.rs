@@ -0,0 +1,187 @@
+use std::f32::consts::PI;
+
+use cgmath::Point3;
+use wgpu::util::DeviceExt;
+
+use super::pg_vertex::PgVertex;
+
+// Indexed playground geometry. Every shape is built from a vertex and index
+// list, so new primitives only need to produce those (see from_geometry)
+pub struct PgMesh {
+  vertex_buffer: wgpu::Buffer,
+  index_buffer: wgpu::Buffer,
+  num_indices: u32,
+}
+
+impl PgMesh {
+  pub fn from_geometry(device: &wgpu::Device, vertices: &[PgVertex], indices: &[u32]) -> PgMesh {
+    let vertex_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Playground vertex buffer"),
+        contents: bytemuck::cast_slice(vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+      }
+    );
+    let index_buffer = device.create_buffer_init(
+      &wgpu::util::BufferInitDescriptor {
+        label: Some("Playground index buffer"),
+        contents: bytemuck::cast_slice(indices),
+        usage: wgpu::BufferUsages::INDEX,
+      }
+    );
+
+    Self {
+      vertex_buffer,
+      index_buffer,
+      num_indices: indices.len() as u32
+    }
+  }
+
+  pub fn triangle(device: &wgpu::Device, center: Point3<f32>, dim: f32) -> PgMesh {
+    let (vertices, indices) = triangle_geometry(center, dim);
+    Self::from_geometry(device, &vertices, &indices)
+  }
+
+  pub fn cube(device: &wgpu::Device, center: Point3<f32>, dim: f32) -> PgMesh {
+    let (vertices, indices) = cube_geometry(center, dim);
+    Self::from_geometry(device, &vertices, &indices)
+  }
+
+  // n x n cells in the xy plane
+  pub fn grid(device: &wgpu::Device, n: u32, center: Point3<f32>, dim: f32) -> PgMesh {
+    let (vertices, indices) = grid_geometry(n, center, dim);
+    Self::from_geometry(device, &vertices, &indices)
+  }
+
+  pub fn uv_sphere(device: &wgpu::Device, rings: u32, segments: u32, center: Point3<f32>, radius: f32) -> PgMesh {
+    let (vertices, indices) = uv_sphere_geometry(rings, segments, center, radius);
+    Self::from_geometry(device, &vertices, &indices)
+  }
+
+  pub fn num_indices(&self) -> u32 {
+    self.num_indices
+  }
+}
+
+pub fn triangle_geometry(center: Point3<f32>, dim: f32) -> (Vec<PgVertex>, Vec<u32>) {
+  let vertices = vec![
+    PgVertex { loc: [center.x, 0.5 * dim + center.y, center.z], color: [1.0, 0.0, 0.0] },
+    PgVertex { loc: [-0.5 * dim + center.x, -0.5 * dim + center.y, center.z], color: [0.0, 1.0, 0.0] },
+    PgVertex { loc: [0.5 * dim + center.x, -0.5 * dim + center.y, center.z], color: [0.0, 0.0, 1.0] },
+  ];
+  (vertices, vec![0, 1, 2])
+}
+
+pub fn cube_geometry(center: Point3<f32>, dim: f32) -> (Vec<PgVertex>, Vec<u32>) {
+  let half_dim = dim * 0.5;
+  // corner i has its x/y/z offset positive when bit 0/1/2 is set, and is
+  // colored the same way
+  let corner = |x: f32, y: f32, z: f32| PgVertex {
+    loc: [x * half_dim + center.x, y * half_dim + center.y, z * half_dim + center.z],
+    color: [(x + 1.) * 0.5, (y + 1.) * 0.5, (z + 1.) * 0.5]
+  };
+  let vertices = vec![
+    corner(-1., -1., -1.),
+    corner(1., -1., -1.),
+    corner(1., 1., -1.),
+    corner(-1., 1., -1.),
+    corner(-1., -1., 1.),
+    corner(1., -1., 1.),
+    corner(1., 1., 1.),
+    corner(-1., 1., 1.),
+  ];
+  let indices = vec![
+    0, 1, 2, 2, 3, 0,
+    4, 7, 6, 6, 5, 4,
+    3, 2, 6, 6, 7, 3,
+    4, 5, 1, 1, 0, 4,
+    1, 5, 6, 6, 2, 1,
+    4, 0, 3, 3, 7, 4
+  ];
+  (vertices, indices)
+}
+
+// (n + 1)^2 vertices and 6n^2 indices
+pub fn grid_geometry(n: u32, center: Point3<f32>, dim: f32) -> (Vec<PgVertex>, Vec<u32>) {
+  let n = n.max(1);
+  let step = dim / n as f32;
+  let origin = (center.x - dim * 0.5, center.y - dim * 0.5);
+  let mut vertices = Vec::new();
+  for row in 0..=n {
+    for col in 0..=n {
+      let (u, v) = (col as f32 / n as f32, row as f32 / n as f32);
+      vertices.push(PgVertex {
+        loc: [origin.0 + col as f32 * step, origin.1 + row as f32 * step, center.z],
+        color: [u, v, 1. - u]
+      });
+    }
+  }
+
+  let mut indices = Vec::new();
+  for row in 0..n {
+    for col in 0..n {
+      let bottom_left = row * (n + 1) + col;
+      let top_left = bottom_left + n + 1;
+      indices.extend_from_slice(&[
+        bottom_left, bottom_left + 1, top_left + 1,
+        top_left + 1, top_left, bottom_left
+      ]);
+    }
+  }
+  (vertices, indices)
+}
+
+// rings run pole to pole and segments around the y axis. the seam column is
+// duplicated, so there are (rings + 1) * (segments + 1) vertices. the first
+// and last rings are fans into the poles, giving 6 * segments * (rings - 1)
+// indices
+pub fn uv_sphere_geometry(rings: u32, segments: u32, center: Point3<f32>, radius: f32) -> (Vec<PgVertex>, Vec<u32>) {
+  let rings = rings.max(2);
+  let segments = segments.max(3);
+  let mut vertices = Vec::new();
+  for ring in 0..=rings {
+    let theta = PI * ring as f32 / rings as f32;
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    for segment in 0..=segments {
+      let phi = 2. * PI * segment as f32 / segments as f32;
+      let (sin_phi, cos_phi) = phi.sin_cos();
+      let normal = [sin_theta * cos_phi, cos_theta, sin_theta * sin_phi];
+      vertices.push(PgVertex {
+        loc: [
+          center.x + normal[0] * radius,
+          center.y + normal[1] * radius,
+          center.z + normal[2] * radius
+        ],
+        color: normal.map(|c| c * 0.5 + 0.5)
+      });
+    }
+  }
+
+  let mut indices = Vec::new();
+  for ring in 0..rings {
+    for segment in 0..segments {
+      let upper = ring * (segments + 1) + segment;
+      let lower = upper + segments + 1;
+      // the upper triangle collapses at the top pole and the lower one at the bottom
+      if ring != 0 {
+        indices.extend_from_slice(&[upper, upper + 1, lower]);
+      }
+      if ring != rings - 1 {
+        indices.extend_from_slice(&[upper + 1, lower + 1, lower]);
+      }
+    }
+  }
+  (vertices, indices)
+}
+
+pub trait DrawPgMesh<'a> {
+  fn draw_pg_mesh(&mut self, mesh: &'a PgMesh);
+}
+
+impl<'a, 'b> DrawPgMesh<'b> for wgpu::RenderPass<'a> where 'b: 'a {
+  fn draw_pg_mesh(&mut self, mesh: &'b PgMesh) {
+    self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    self.draw_indexed(0..mesh.num_indices, 0, 0..1);
+  }
+}
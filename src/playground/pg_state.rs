@@ -1,6 +1,6 @@
-use crate::{graphics::{get_render_pipeline, ModelVertex, Texture}, playground::{pg_triangle::DrawPgTriangle, pg_vertex}};
+use crate::{graphics::{get_render_pipeline, ModelVertex, Texture}, playground::pg_vertex};
 
-use super::{pg_cube::{self, PgCube}, pg_triangle::{self, PgTriangle}};
+use super::pg_mesh::{self, PgMesh};
 use cgmath::{Point3, Vector3};
 use wgpu::util::DeviceExt;
 use winit::{event::WindowEvent, window::Window};
@@ -14,8 +14,8 @@ pub struct PgState {
   pub queue: wgpu::Queue,
   pub clear_color: (f64, f64, f64, f64),
   pub depth_texture: Texture,
-  pub pg_cube: PgCube,
-  pub pg_triangle: PgTriangle,
+  pub pg_cube: PgMesh,
+  pub pg_triangle: PgMesh,
   pub render_pipeline_layout: wgpu::PipelineLayout,
   pub render_pipeline: wgpu::RenderPipeline
 }
@@ -75,8 +75,8 @@ impl PgState {
     };
     surface.configure(&device, &config);
 
-    let pg_cube = PgCube::new(&device, Point3 { x: 0., y: 0., z: 0.5 }, 0.5);
-    let pg_triangle = PgTriangle::new(&device, Point3 { x: 0., y: 0., z: 1. }, 1.);
+    let pg_cube = PgMesh::cube(&device, Point3 { x: 0., y: 0., z: 0.5 }, 0.5);
+    let pg_triangle = PgMesh::triangle(&device, Point3 { x: 0., y: 0., z: 1. }, 1.);
 
     let shader = wgpu::ShaderModuleDescriptor {
       label: Some("Playground shader"),
@@ -188,10 +188,10 @@ impl PgState {
         occlusion_query_set: None 
       });
 
-      use pg_cube::DrawPgCube;
+      use pg_mesh::DrawPgMesh;
       render_pass.set_pipeline(&self.render_pipeline);
-      render_pass.draw_cube(&self.pg_cube);
-      // render_pass.draw_triangle(&self.pg_triangle);
+      render_pass.draw_pg_mesh(&self.pg_cube);
+      // render_pass.draw_pg_mesh(&self.pg_triangle);
     }
 
     self.queue.submit(std::iter::once(encoder.finish()));
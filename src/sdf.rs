@@ -1,4 +1,5 @@
 mod sdf_shape;
+mod sdf_error;
 mod triangle;
 mod triangle_list;
 mod inferred_vertex_model;
@@ -13,10 +14,18 @@ pub struct SdfBounds {
 }
 
 pub use inferred_vertex_model::{
+  ColorFn,
   DrawIVModel,
-  InferredVertexModel
+  InferredVertexModel,
+  MeshingOptions,
+  DEFAULT_MAX_NEIGHBOR_OFFSET,
+  DEFAULT_NORMAL_TOL,
+  DEFAULT_REFINE_ITERS,
+  DEFAULT_REFINE_TOL
 };
 
+pub use sdf_error::SdfError;
+
 pub use sdf_shape::{
   SdfShape,
   Shape,
@@ -11,18 +11,23 @@ use std::cmp::{
   max,
   min
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::os::macos::raw;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use super::triangle::{
+  CornerKey,
+  Face,
+  Quad,
   TriVertex,
   Triangle,
 };
 use super::triangle_list::TriangleSet;
 use super::sdf_shape::SdfShape;
 use crate::graphics::{
+  BindGroupLayoutCache,
   Material,
   Mesh,
   ModelVertex,
@@ -34,8 +39,32 @@ use crate::util::{
   Point,
 };
 
-const MAX_NEIGHBOR_OFFSET: usize = 3;
-const NORMAL_TOL: f32 = 0.1;
+pub const DEFAULT_MAX_NEIGHBOR_OFFSET: usize = 3;
+pub const DEFAULT_NORMAL_TOL: f32 = 0.1;
+// defaults for projecting grid points onto the surface
+pub const DEFAULT_REFINE_ITERS: usize = 8;
+pub const DEFAULT_REFINE_TOL: f32 = 1e-4;
+
+// how vertices are connected into faces. larger neighbor offsets connect
+// sparser surfaces, a tighter normal tolerance rejects more faces that
+// don't line up with the sdf normal
+#[derive(Clone, Copy, Debug)]
+pub struct MeshingOptions {
+  pub max_neighbor_offset: usize, // in grid cells
+  pub normal_tol: f32,
+}
+
+impl MeshingOptions {
+  pub fn default() -> MeshingOptions {
+    Self {
+      max_neighbor_offset: DEFAULT_MAX_NEIGHBOR_OFFSET,
+      normal_tol: DEFAULT_NORMAL_TOL
+    }
+  }
+}
+
+// per vertex color from a surface position and the shape it lies on
+pub type ColorFn = fn(Point3<f32>, &SdfShape) -> [u8; 4];
 
 pub struct InferredVertexModel {
   pub sdf: SdfShape,
@@ -44,7 +73,7 @@ pub struct InferredVertexModel {
   pub inferred_mesh: Mesh,
   pub triangle_coords: Vec<[Point3<f32>; 3]>,
   pub diffuse_texture: Texture,
-  pub diffuse_bind_group_layout: wgpu::BindGroupLayout,
+  pub diffuse_bind_group_layout: Arc<wgpu::BindGroupLayout>,
   pub diffuse_bind_group: wgpu::BindGroup,
 }
 
@@ -100,14 +129,14 @@ fn get_vert_slot(x_idx: usize, y_idx: usize, z_idx: usize, x_j: usize, y_j: usiz
   }
 }
 
-fn get_vertex_neighbors<'a, 'b>(vertex_arr: &'a Vec<Vec<Vec<Option<TriVertex>>>>, vert: &'b TriVertex<'a>, x_idx: usize, y_idx: usize, z_idx: usize) -> Vec<Option<&'b TriVertex<'a>>> {
+fn get_vertex_neighbors<'a, 'b>(vertex_arr: &'a Vec<Vec<Vec<Option<TriVertex>>>>, vert: &'b TriVertex<'a>, x_idx: usize, y_idx: usize, z_idx: usize, max_neighbor_offset: usize) -> Vec<Option<&'b TriVertex<'a>>> {
   // want to get the closest vertex in each direction within a cube
   // of dims 3*granularity for each side
   let mut neighbors_slice: &mut [Option<&TriVertex>; 26] = &mut [None; 26];
-  for d in 1..MAX_NEIGHBOR_OFFSET {
+  for d in 1..max_neighbor_offset {
     for x_j in (max((x_idx as i32) - 3 * (d as i32), 0) as usize)..min(x_idx + 3 * d, vertex_arr.len()) {
       for y_j in (max((y_idx as i32) - 3 * (d as i32), 0) as usize)..min(y_idx + 3 * d, vertex_arr[x_j].len()) {
-        for z_j in (max((z_idx as i32) - 3 * (d as i32), 0) as usize)..min(z_idx + 3 * d, vertex_arr[y_j].len()) {
+        for z_j in (max((z_idx as i32) - 3 * (d as i32), 0) as usize)..min(z_idx + 3 * d, vertex_arr[x_j][y_j].len()) {
           // only want outermost vertices for the pass -> so if the x, y, z dif
           // from original indices is not equal to d -> skip
           if !((x_j as i32 - x_idx as i32).abs() as usize == d && (y_j as i32 - y_idx as i32).abs() as usize == d && (z_j as i32 - z_idx as i32).abs() as usize == d) {
@@ -129,7 +158,7 @@ fn get_vertex_neighbors<'a, 'b>(vertex_arr: &'a Vec<Vec<Vec<Option<TriVertex>>>>
   return Vec::from(neighbors_slice)
 }
 
-fn populate_all_closest_vertices<'a>(vertex_arr: &'a Vec<Vec<Vec<Option<TriVertex<'a>>>>>) -> Vec<Vec<Vec<Option<TriVertex<'a>>>>> {
+fn populate_all_closest_vertices<'a>(vertex_arr: &'a Vec<Vec<Vec<Option<TriVertex<'a>>>>>, max_neighbor_offset: usize) -> Vec<Vec<Vec<Option<TriVertex<'a>>>>> {
   // sliding 3x3x3 window
   let mut neighbors_map: PointDict<Vec<Option<&'a TriVertex<'a>>>> = PointDict::new();
   {
@@ -141,7 +170,7 @@ fn populate_all_closest_vertices<'a>(vertex_arr: &'a Vec<Vec<Vec<Option<TriVerte
           if let Some(vert) = vert_opt {
             // get the vertex's neighbors
             // add all of them as references in the triangle
-            let neighbors = get_vertex_neighbors(&vertex_arr, vert, x_idx, y_idx, z_idx);
+            let neighbors = get_vertex_neighbors(&vertex_arr, vert, x_idx, y_idx, z_idx, max_neighbor_offset);
             neighbors_map.insert(Point3{x: x_idx as f32, y: y_idx as f32, z: z_idx as f32}, neighbors);
           }
         }
@@ -170,8 +199,57 @@ fn compare_normal(sdf_shape: &SdfShape, triangle: &Triangle, tol: f32) -> bool {
   false
 }
 
-fn get_triangles_from_vertex_list<'a>(vertices: Rc<Vec<Vec<Vec<Option<TriVertex<'a>>>>>>, sdf_shape: &'a SdfShape, normal_tol: f32) -> TriangleSet<'a> {
+fn vertex_at<'v, 'a>(vertices: &'v Vec<Vec<Vec<Option<TriVertex<'a>>>>>, x: usize, y: usize, z: usize) -> Option<&'v TriVertex<'a>> {
+  vertices.get(x)?.get(y)?.get(z)?.as_ref()
+}
+
+// quads over grid cell faces whose four corners all hit the surface, wound
+// to face along the sdf normal. the pair search below skips triangles a
+// quad already covers, since those tend to be slivers
+fn get_quads_from_vertex_list<'a>(vertices: &Vec<Vec<Vec<Option<TriVertex<'a>>>>>, sdf_shape: &SdfShape, normal_tol: f32) -> Vec<Quad<'a>> {
+  // the (u, v) steps spanning the cell face in each axis plane
+  let planes: [((usize, usize, usize), (usize, usize, usize)); 3] = [
+    ((1, 0, 0), (0, 1, 0)),
+    ((0, 1, 0), (0, 0, 1)),
+    ((0, 0, 1), (1, 0, 0)),
+  ];
+  let mut quads = Vec::new();
+  for x in 0..vertices.len() {
+    for y in 0..vertices[x].len() {
+      for z in 0..vertices[x][y].len() {
+        for (u, v) in planes.iter() {
+          let corners = (
+            vertex_at(vertices, x, y, z),
+            vertex_at(vertices, x + u.0, y + u.1, z + u.2),
+            vertex_at(vertices, x + u.0 + v.0, y + u.1 + v.1, z + u.2 + v.2),
+            vertex_at(vertices, x + v.0, y + v.1, z + v.2),
+          );
+          if let (Some(a), Some(b), Some(c), Some(d)) = corners {
+            let mut quad = Quad::new(a.clone(), b.clone(), c.clone(), d.clone());
+            let quad_normal = quad.face_normal();
+            if quad_normal.magnitude2() == 0.0 {
+              continue;
+            }
+            let normal = sdf_shape.compute_normal(quad.midpoint());
+            if quad_normal.dot(normal) < 0.0 {
+              quad = Quad::new(a.clone(), d.clone(), c.clone(), b.clone());
+            }
+            if quad_normal.normalize().cross(normal).magnitude() < normal_tol {
+              quads.push(quad);
+            }
+          }
+        }
+      }
+    }
+  }
+  quads
+}
+
+fn get_triangles_from_vertex_list<'a>(vertices: Rc<Vec<Vec<Vec<Option<TriVertex<'a>>>>>>, sdf_shape: &'a SdfShape, normal_tol: f32, quads: &[Quad]) -> TriangleSet<'a> {
   let mut triangle_set = TriangleSet::new();
+  let covered: HashSet<CornerKey> = quads.iter()
+    .flat_map(|quad| quad.covered_keys())
+    .collect();
   for plane in vertices.iter() {
     for row in plane {
       for vert_opt in row {
@@ -180,6 +258,9 @@ fn get_triangles_from_vertex_list<'a>(vertices: Rc<Vec<Vec<Vec<Option<TriVertex<
             let vert1 = vert.get_neighbor_at_index(idx1).unwrap();
             let vert2 = vert.get_neighbor_at_index(idx2).unwrap();
             let triangle = Triangle::new(vert.clone(), vert1.clone(), vert2.clone());
+            if covered.contains(&triangle.corner_key()) {
+              continue;
+            }
             if compare_normal(&sdf_shape, &triangle, normal_tol) {
               triangle_set.insert(triangle);
             }
@@ -192,7 +273,7 @@ fn get_triangles_from_vertex_list<'a>(vertices: Rc<Vec<Vec<Vec<Option<TriVertex<
   triangle_set
 }
 
-fn build_mesh<'a>(device: &wgpu::Device, vertex_list_raw: &'a Vec<Vec<Vec<Option<TriVertex>>>>, active_indices: Vec<(usize, usize, usize)>, triangle_list: &TriangleSet, sdf_shape: &SdfShape) -> Mesh {
+fn build_mesh<'a>(device: &wgpu::Device, vertex_list_raw: &'a Vec<Vec<Vec<Option<TriVertex>>>>, active_indices: Vec<(usize, usize, usize)>, triangle_list: &TriangleSet, quads: &[Quad], sdf_shape: &SdfShape, color_fn: Option<ColorFn>) -> Mesh {
   // idea:
   // clone the triangle list
   // add each vertex to the vertex list
@@ -203,8 +284,20 @@ fn build_mesh<'a>(device: &wgpu::Device, vertex_list_raw: &'a Vec<Vec<Vec<Option
   let mut index_list: Vec<u32> = Vec::new();
   let cloned_triangle_list = triangle_list.clone();
   let mut point_index_map: PointDict<u32> = PointDict::new();
-
-  for triangle in cloned_triangle_list.iter() {
+  let to_model_vertex = |vert: &TriVertex| {
+    let mut v = vert.into_model_vertex(sdf_shape);
+    if let Some(color_fn) = color_fn {
+      let rgba = color_fn(vert.loc, sdf_shape);
+      v.color = [rgba[0] as f32 / 255., rgba[1] as f32 / 255., rgba[2] as f32 / 255.];
+    }
+    v
+  };
+
+  // quads are split here so they share vertices with the triangles
+  let quad_triangles = quads.iter()
+    .flat_map(|quad| quad.to_triangles())
+    .collect::<Vec<Triangle>>();
+  for triangle in cloned_triangle_list.iter().chain(quad_triangles.iter()) {
     let vert1 = triangle.a.clone();
     let vert2 = triangle.b.clone();
     let vert3 = triangle.c.clone();
@@ -213,21 +306,21 @@ fn build_mesh<'a>(device: &wgpu::Device, vertex_list_raw: &'a Vec<Vec<Vec<Option
     if point_index_map.contains_key(&vert1.loc) {
       idx1 = point_index_map.get(&vert1.loc).unwrap().clone();
     } else {
-      vertices.push(vert1.clone().into_model_vertex(sdf_shape));
+      vertices.push(to_model_vertex(&vert1));
       point_index_map.insert(vert1.loc.clone(), idx1);
     }
     let mut idx2: u32 = vertices.len() as u32;
     if point_index_map.contains_key(&vert2.loc) {
       idx2 = point_index_map.get(&vert2.loc).unwrap().clone();
     } else {
-      vertices.push(vert2.clone().into_model_vertex(sdf_shape));
+      vertices.push(to_model_vertex(&vert2));
       point_index_map.insert(vert2.loc.clone(), idx2);
     }
     let mut idx3: u32 = vertices.len() as u32;
     if point_index_map.contains_key(&vert3.loc) {
       idx3 = point_index_map.get(&vert3.loc).unwrap().clone();
     } else {
-      vertices.push(vert3.clone().into_model_vertex(sdf_shape));
+      vertices.push(to_model_vertex(&vert3));
       point_index_map.insert(vert3.loc.clone(), idx3);
     }
 
@@ -238,13 +331,7 @@ fn build_mesh<'a>(device: &wgpu::Device, vertex_list_raw: &'a Vec<Vec<Vec<Option
 
   // index buffer
   let index_slice: &[u32] = &index_list[..];
-  let index_buffer = device.create_buffer_init(
-    &wgpu::util::BufferInitDescriptor {
-      label: Some("Index buffer"),
-      contents: bytemuck::cast_slice(index_slice),
-      usage: wgpu::BufferUsages::INDEX
-    }
-  );
+  let (index_buffer, index_format) = Mesh::create_index_buffer(device, "Index buffer", index_slice, vertices.len());
 
   // vertex buffer
   let vertex_buffer = device.create_buffer_init(
@@ -262,12 +349,24 @@ fn build_mesh<'a>(device: &wgpu::Device, vertex_list_raw: &'a Vec<Vec<Vec<Option
     index_buffer,
     vertex_buffer,
     num_elements: index_list.len() as u32,
-    material: 0
+    material: 0,
+    index_format,
+    vertices,
+    indices: index_list
   }
 }
 
 impl InferredVertexModel {
-  fn construct_mesh(sdf_shape: &SdfShape, bounds: &SdfBounds, granularity: f32, device: &wgpu::Device) -> (Mesh, Vec<[Point3<f32>; 3]>) {
+  fn construct_mesh(
+    sdf_shape: &SdfShape,
+    bounds: &SdfBounds,
+    granularity: f32,
+    refine_iters: usize,
+    refine_tol: f32,
+    options: MeshingOptions,
+    color_fn: Option<ColorFn>,
+    device: &wgpu::Device
+  ) -> (Mesh, Vec<[Point3<f32>; 3]>) {
     // this should basically subdivide the bounds into tiny regions of size granularity,
     // then, if the sdf tolerance is within some fraction of the granularity value from the current point, it should generate a new vertex at the nearest point where the sdf function is zero (or just the current point maybe
     // then we want to store the vertices at the granularity index corresponding to its location lol
@@ -314,9 +413,9 @@ impl InferredVertexModel {
           let tol = granularity / 2.0;
           if sdf_shape.hit(p, tol) {
             // if the point is within the tol distance from the sdf boundary,
-            // -> ideally we would evaluate the point on the sdf boundary where the point is zero? -> 
+            // move it onto the zero level set
             let mut sdf_loc = p.clone();
-            sdf_shape.gradient_trace(p, &mut sdf_loc, None, None);
+            sdf_shape.gradient_trace(p, &mut sdf_loc, Some(refine_iters), Some(refine_tol));
             let vert = TriVertex::new(sdf_loc, curr_idx, None);
             // points.push(sdf_loc.clone());
             add_vert(&mut vec_3d, vert, x_idx, y_idx, z_idx);
@@ -327,18 +426,22 @@ impl InferredVertexModel {
       }
     }
 
-    let completed_arr =  populate_all_closest_vertices(&vec_3d);
+    let completed_arr =  populate_all_closest_vertices(&vec_3d, options.max_neighbor_offset);
     let completed_rc = Rc::new(completed_arr);
     // convert the vertices into a list of triangles
-    let triangle_set = get_triangles_from_vertex_list(completed_rc.clone(), &sdf_shape, NORMAL_TOL);
-    for triangle in triangle_set.iter() {
+    let quads = get_quads_from_vertex_list(&vec_3d, &sdf_shape, options.normal_tol);
+    let triangle_set = get_triangles_from_vertex_list(completed_rc.clone(), &sdf_shape, options.normal_tol, &quads);
+    let quad_triangles = quads.iter()
+      .flat_map(|quad| quad.to_triangles())
+      .collect::<Vec<Triangle>>();
+    for triangle in triangle_set.iter().chain(quad_triangles.iter()) {
       points.push([
         triangle.a.loc.clone(),
         triangle.b.loc.clone(),
         triangle.b.loc.clone(),
       ])
     }
-    let mesh = build_mesh(device, &vec_3d, active_indices, &triangle_set, &sdf_shape.clone());
+    let mesh = build_mesh(device, &vec_3d, active_indices, &triangle_set, &quads, &sdf_shape.clone(), color_fn);
     (mesh, points)
   }
 
@@ -361,35 +464,51 @@ impl InferredVertexModel {
     tex
   }
 
-  pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, sdf_shape: SdfShape, sdf_bounds: SdfBounds, granularity: f32, color: &[u8; 4]) -> InferredVertexModel {
-    let (mesh, points) = Self::construct_mesh(&sdf_shape, &sdf_bounds, granularity, device);
-    let tex = Self::construct_texture(color, (200, 200), device, queue);
-
-    let layout = device.create_bind_group_layout(
-      &wgpu::BindGroupLayoutDescriptor { 
-        label: Some("Texture bind group layout"), 
-        entries: &[
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              multisampled: false,
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            // This should match the filterable field of the
-            // corresponding Texture entry above.
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None,
-          },
-        ] 
-      }
-    );
+  // refine_iters/refine_tol control how closely vertices are projected
+  // onto the surface (see DEFAULT_REFINE_ITERS and DEFAULT_REFINE_TOL),
+  // and options how they're connected (MeshingOptions::default). without
+  // a color_fn the model is the flat color, with one each vertex takes its
+  // rgb from color_fn and color only supplies the alpha
+  pub fn new(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sdf_shape: SdfShape,
+    sdf_bounds: SdfBounds,
+    granularity: f32,
+    refine_iters: usize,
+    refine_tol: f32,
+    options: MeshingOptions,
+    color: &[u8; 4],
+    color_fn: Option<ColorFn>,
+    layout_cache: &mut BindGroupLayoutCache
+  ) -> InferredVertexModel {
+    let (mesh, points) = Self::construct_mesh(&sdf_shape, &sdf_bounds, granularity, refine_iters, refine_tol, options, color_fn, device);
+    let tex_color = match color_fn {
+      Some(_) => [255, 255, 255, color[3]],
+      None => *color
+    };
+    let tex = Self::construct_texture(&tex_color, (200, 200), device, queue);
+
+    let layout = layout_cache.get_or_create(device, "Texture bind group layout", &[
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          multisampled: false,
+          view_dimension: wgpu::TextureViewDimension::D2,
+          sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        // This should match the filterable field of the
+        // corresponding Texture entry above.
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+      },
+    ]);
     let bind_group = device.create_bind_group(
       &wgpu::BindGroupDescriptor {
         layout: &layout,
@@ -446,7 +565,7 @@ impl<'a, 'b> DrawIVModel<'b> for wgpu::RenderPass<'a> where 'b: 'a {
     light_bind_group: &'b wgpu::BindGroup
   ) {
     self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-    self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
     self.set_bind_group(0, diffuse_bind_group, &[]);
     self.set_bind_group(1, &camera_bind_group, &[]);
     self.set_bind_group(2, light_bind_group, &[]);
@@ -463,3 +582,28 @@ impl<'a, 'b> DrawIVModel<'b> for wgpu::RenderPass<'a> where 'b: 'a {
     self.draw_iv_mesh(&model.inferred_mesh, bind_group, camera_bind_group, light_bind_group);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // every grid point filled, dims points along x, y and z
+  fn grid<'a>(dims: (usize, usize, usize)) -> Vec<Vec<Vec<Option<TriVertex<'a>>>>> {
+    (0..dims.0).map(|x| (0..dims.1).map(|y| (0..dims.2).map(|z| {
+      Some(TriVertex::new(Point3::new(x as f32, y as f32, z as f32), 0, None))
+    }).collect()).collect()).collect()
+  }
+
+  #[test]
+  fn neighbor_search_handles_non_cubic_grids() {
+    // longer in x than y and in y than z, which used to index past the
+    // shorter axes
+    let vertices = grid((6, 3, 2));
+    let populated = populate_all_closest_vertices(&vertices, DEFAULT_MAX_NEIGHBOR_OFFSET);
+    assert_eq!(populated.len(), 6);
+    assert!(populated.iter().all(|plane| plane.len() == 3 && plane.iter().all(|row| row.len() == 2)));
+    let corner = populated[0][0][0].as_ref().unwrap();
+    // the diagonal neighbor one cell along every axis sits in the last slot
+    assert_eq!(corner.get_neighbor_at_index(25).map(|v| v.loc), Some(Point3::new(1., 1., 1.)));
+  }
+}
@@ -0,0 +1,25 @@
+use core::fmt;
+
+#[derive(Debug)]
+pub enum SdfError {
+  // the distance function doesn't handle the shape it was paired with
+  ShapeMismatch {
+    shape: String
+  },
+}
+
+impl fmt::Display for SdfError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::ShapeMismatch { shape } => write!(f, "Distance function does not support shape {}", shape),
+    }
+  }
+}
+
+impl std::error::Error for SdfError {
+  fn description(&self) -> &str {
+    match self {
+      Self::ShapeMismatch { .. } => "Mismatched shape and distance function",
+    }
+  }
+}
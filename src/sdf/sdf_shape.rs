@@ -1,10 +1,12 @@
 use std::cmp::max;
 
 use cgmath::{
-  num_traits::abs, InnerSpace, MetricSpace, Point3, Vector2, Vector3
+  num_traits::abs, EuclideanSpace, InnerSpace, MetricSpace, Point3, Vector2, Vector3
 };
 
-const EPSILON: f32 = 1e4;
+use super::sdf_error::SdfError;
+
+const EPSILON: f32 = 1e-4;
 // the assumption is, that in this use case, the sphere trace guess should be almost correct
 const DEFAULT_TRACE_ITERS: usize = 1; 
 
@@ -36,18 +38,63 @@ pub enum Shape {
   Custom(Vec<f32>),
 }
 
+impl Shape {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Shape::Sphere { .. } => "Sphere",
+      Shape::Line { .. } => "Line",
+      Shape::Cube { .. } => "Cube",
+      Shape::Cylinder { .. } => "Cylinder",
+      Shape::Cone { .. } => "Cone",
+      Shape::Custom(_) => "Custom",
+    }
+  }
+
+  // local space center of the shape, if it has a meaningful one
+  pub fn center(&self) -> Option<Point3<f32>> {
+    match self {
+      Shape::Sphere { center, .. } => Some(*center),
+      Shape::Cube { center, .. } => Some(*center),
+      Shape::Line { a, b } => Some(a.midpoint(*b)),
+      Shape::Cylinder { a, b, .. } => Some(a.midpoint(*b)),
+      Shape::Cone { a, b, .. } => Some(a.midpoint(*b)),
+      Shape::Custom(_) => None,
+    }
+  }
+
+  // local space (min, max) box containing the shape, if it's known
+  pub fn bounds(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    let segment_bounds = |a: &Point3<f32>, b: &Point3<f32>, pad: f32| (
+      Point3::new(a.x.min(b.x) - pad, a.y.min(b.y) - pad, a.z.min(b.z) - pad),
+      Point3::new(a.x.max(b.x) + pad, a.y.max(b.y) + pad, a.z.max(b.z) + pad),
+    );
+    match self {
+      Shape::Sphere { center, rad } => Some(segment_bounds(center, center, *rad)),
+      Shape::Cube { center, half_bounds } => Some((*center - *half_bounds, *center + *half_bounds)),
+      Shape::Line { a, b } => Some(segment_bounds(a, b, 0.)),
+      Shape::Cylinder { a, b, rad } => Some(segment_bounds(a, b, *rad)),
+      Shape::Cone { a, b, rad_a, rad_b } => Some(segment_bounds(a, b, rad_a.max(*rad_b))),
+      Shape::Custom(_) => None,
+    }
+  }
+}
+
 pub fn SphereSdf(shape: &Shape, point: Point3<f32>) -> f32 {
   match shape {
     Shape::Sphere { center, rad } => {
       point.distance(center.clone()) - rad
     }
-    _ => 0.
+    // not a distance, SdfShape::new rejects the pairing
+    _ => f32::NAN
   }
 }
 
 pub fn CubeSdf(shape: &Shape, p: Point3<f32>) -> f32 {
   match shape {
     Shape::Cube { center, half_bounds } => {
+      // measured from the cube's own center. cubes used to be measured from
+      // the origin whatever their center, so an off center cube moves
+      let p = p - center.to_vec();
       let mut d = 0.;
       if abs(p.x) < half_bounds.x && abs(p.y) < half_bounds.y && abs(p.z) < half_bounds.z {
           return f32::max(f32::max(abs(p.x) - half_bounds.x, abs(p.y) - half_bounds.y) as f32, abs(p.z) - half_bounds.z as f32);
@@ -76,7 +123,8 @@ pub fn CubeSdf(shape: &Shape, p: Point3<f32>) -> f32 {
       }
       d
     }
-    _ => 0.
+    // not a distance, SdfShape::new rejects the pairing
+    _ => f32::NAN
   }
 }
 
@@ -87,11 +135,17 @@ pub struct SdfShape {
 }
 
 impl SdfShape {
-  pub fn new(shape: Shape, sdf_fn: fn(&Shape, Point3<f32>) -> f32) -> Self {
-    SdfShape {
+  // the built in distance functions return NaN for shapes they don't
+  // handle, so the pairing is checked once here rather than on every query
+  pub fn new(shape: Shape, sdf_fn: fn(&Shape, Point3<f32>) -> f32) -> Result<Self, SdfError> {
+    let probe = shape.center().unwrap_or(Point3::new(0., 0., 0.));
+    if sdf_fn(&shape, probe).is_nan() {
+      return Err(SdfError::ShapeMismatch { shape: shape.name().into() });
+    }
+    Ok(SdfShape {
       shape,
       sdf_fn,
-    }
+    })
   }
 
   pub fn compute_normal(&self, p: Point3<f32>) -> Vector3<f32> {
@@ -100,7 +154,21 @@ impl SdfShape {
     let dx = (self.sdf_fn)(&self.shape, p + Vector3::new(h, 0.0, 0.0)) - d0;
     let dy = (self.sdf_fn)(&self.shape, p + Vector3::new(0.0, h, 0.0)) - d0;
     let dz = (self.sdf_fn)(&self.shape, p + Vector3::new(0.0, 0.0, h)) - d0;
-    Vector3::new(dx, dy, dz).normalize()
+    let gradient = Vector3::new(dx, dy, dz);
+    // flat spots (e.g. the exact center of a sphere) have no direction,
+    // fall back to up rather than normalizing into NaN
+    if !(gradient.magnitude2() > f32::EPSILON * f32::EPSILON) {
+      return Vector3::unit_y();
+    }
+    gradient.normalize()
+  }
+
+  pub fn center(&self) -> Option<Point3<f32>> {
+    self.shape.center()
+  }
+
+  pub fn bounds(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+    self.shape.bounds()
   }
 
   pub fn dist(&self, p: Point3<f32>) -> f32 {
@@ -114,24 +182,55 @@ impl SdfShape {
     false
   }
 
+  // newton style projection onto the zero level set: repeatedly step
+  // against the gradient by the signed distance until |dist| < tol.
+  // hit_loc is always set to the last estimate, the return value says
+  // whether it converged within max_iters
   pub fn gradient_trace(&self, p: Point3<f32>, hit_loc: &mut Point3<f32>, caller_max_iters: Option<usize>, caller_tol: Option<f32>) -> bool {
-    // idea -> while the distance to the sdf is less than tol (or default tol),
-    // move along the gradient of the sdf towards the sdf boundary by a length
-    // self.dist()
     let max_iters = caller_max_iters.unwrap_or(DEFAULT_TRACE_ITERS);
     let tol = caller_tol.unwrap_or(EPSILON);
-    let mut iter: usize = 0;
     let mut loc = p.clone();
-    let mut hit = false;
-    while (!hit && iter < max_iters) {
-      let dist = self.dist(p);
-      loc = loc + (self.compute_normal(loc) * dist);
-      if (dist < tol) {
-        hit = true;
-        *hit_loc = loc;
-        return true;
-      }
+    let mut dist = self.dist(loc);
+    let mut iter: usize = 0;
+    while dist.abs() >= tol && iter < max_iters {
+      loc = loc - (self.compute_normal(loc) * dist);
+      dist = self.dist(loc);
+      iter += 1;
     }
-    false
+    *hit_loc = loc;
+    dist.abs() < tol
   }
-}
\ No newline at end of file
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn flat(_: &Shape, _: Point3<f32>) -> f32 {
+    1.
+  }
+
+  #[test]
+  fn mismatched_distance_functions_are_rejected() {
+    let sphere = Shape::Sphere { center: Point3::new(0., 0., 0.), rad: 1. };
+    assert!(matches!(SdfShape::new(sphere.clone(), CubeSdf), Err(SdfError::ShapeMismatch { .. })));
+    assert!(SdfShape::new(sphere, SphereSdf).is_ok());
+  }
+
+  #[test]
+  fn normals_at_degenerate_points_are_not_nan() {
+    // a constant field has no gradient anywhere
+    let shape = SdfShape::new(Shape::Custom(Vec::new()), flat).unwrap();
+    let normal = shape.compute_normal(Point3::new(0., 0., 0.));
+    assert!(!normal.x.is_nan() && !normal.y.is_nan() && !normal.z.is_nan());
+    assert!((normal.magnitude() - 1.).abs() < 1e-4);
+  }
+
+  #[test]
+  fn sphere_normals_point_outwards() {
+    let shape = SdfShape::new(Shape::Sphere { center: Point3::new(0., 0., 0.), rad: 1. }, SphereSdf).unwrap();
+    let normal = shape.compute_normal(Point3::new(2., 0., 0.));
+    assert!((normal - Vector3::unit_x()).magnitude() < 1e-3);
+  }
+}
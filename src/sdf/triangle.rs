@@ -1,4 +1,5 @@
 use cgmath::{
+  MetricSpace,
   Point3,
   Vector3
 };
@@ -70,7 +71,8 @@ impl<'a> TriVertex<'a> {
       tex_coords,
       normal: normal.into(),
       tangent,
-      bitangent
+      bitangent,
+      color: [1.0; 3]
     }
   }
 
@@ -153,6 +155,11 @@ impl<'a> Triangle<'a> {
     }
   }
 
+  // order independent key for the triangle's vertex locations
+  pub fn corner_key(&self) -> CornerKey {
+    corner_key(&self.a.loc, &self.b.loc, &self.c.loc)
+  }
+
   pub fn midpoint(&self) -> Point3<f32> {
     Point3 { 
       x: (self.a.loc.x + self.b.loc.x + self.c.loc.x) / 3.0, 
@@ -161,9 +168,20 @@ impl<'a> Triangle<'a> {
     }
   }
 
-  pub fn hash_str(&self) -> String {
-    let midpoint = self.midpoint();
-    format!("x: {}, y: {}, z: {}", midpoint.x, midpoint.y, midpoint.z)
+  // vertex locations as bits, starting from the smallest vertex so every
+  // rotation of the same triangle gives the same key (matching PartialEq).
+  // the midpoint used to be hashed instead, but summing the vertices in a
+  // different order can round differently and split equal triangles
+  pub fn hash_key(&self) -> [u32; 9] {
+    // + 0.0 folds -0.0 into 0.0 since they compare equal
+    let bits = |v: &TriVertex| [(v.loc.x + 0.0).to_bits(), (v.loc.y + 0.0).to_bits(), (v.loc.z + 0.0).to_bits()];
+    let verts = [bits(&self.a), bits(&self.b), bits(&self.c)];
+    let start = (0..3).min_by_key(|i| verts[*i]).unwrap_or(0);
+    let mut key = [0u32; 9];
+    for i in 0..3 {
+      key[i * 3..i * 3 + 3].copy_from_slice(&verts[(start + i) % 3]);
+    }
+    key
   }
 
   pub fn exact_eq(&self, other: &Self) -> bool {
@@ -204,6 +222,7 @@ impl<'a, 'b> PartialEq for Triangle<'a> {
 
 impl<'a, 'b> Eq for Triangle<'a> {}
 
+#[derive(Clone)]
 pub struct Quad<'a> {
   a: TriVertex<'a>,
   b: TriVertex<'a>,
@@ -211,27 +230,136 @@ pub struct Quad<'a> {
   d: TriVertex<'a>,
 }
 
-trait Face {
+impl<'a> Quad<'a> {
+  // corners in winding order, so a-c and b-d are the diagonals
+  pub fn new(a: TriVertex<'a>, b: TriVertex<'a>, c: TriVertex<'a>, d: TriVertex<'a>) -> Quad<'a> {
+    Quad {
+      a,
+      b,
+      c,
+      d
+    }
+  }
+
+  // splits along the shorter diagonal, which gives the less slivered pair.
+  // both triangles keep the quad's winding
+  pub fn to_triangles(&self) -> [Triangle<'a>; 2] {
+    if self.a.loc.distance2(self.c.loc) <= self.b.loc.distance2(self.d.loc) {
+      [
+        Triangle::new(self.a.clone(), self.b.clone(), self.c.clone()),
+        Triangle::new(self.a.clone(), self.c.clone(), self.d.clone()),
+      ]
+    } else {
+      [
+        Triangle::new(self.a.clone(), self.b.clone(), self.d.clone()),
+        Triangle::new(self.b.clone(), self.c.clone(), self.d.clone()),
+      ]
+    }
+  }
+
+  // keys for every triangle whose vertices are all corners of the quad,
+  // comparable against Triangle::corner_key
+  pub fn covered_keys(&self) -> [CornerKey; 4] {
+    let (a, b, c, d) = (&self.a.loc, &self.b.loc, &self.c.loc, &self.d.loc);
+    [
+      corner_key(a, b, c),
+      corner_key(a, b, d),
+      corner_key(a, c, d),
+      corner_key(b, c, d),
+    ]
+  }
+}
+
+// order independent key for a set of three vertex locations
+pub type CornerKey = [[u32; 3]; 3];
+
+fn loc_bits(loc: &Point3<f32>) -> [u32; 3] {
+  // adding zero folds -0.0 into 0.0 so bit equality matches float equality
+  [(loc.x + 0.0).to_bits(), (loc.y + 0.0).to_bits(), (loc.z + 0.0).to_bits()]
+}
+
+fn corner_key(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>) -> CornerKey {
+  let mut key = [loc_bits(a), loc_bits(b), loc_bits(c)];
+  key.sort();
+  key
+}
+
+pub trait Face {
   fn face_normal(&self) -> Vector3<f32>;
   fn midpoint(&self) -> Point3<f32>;
 }
 
 impl<'a> Face for Triangle<'a> {
   fn face_normal(&self) -> Vector3<f32> {
-    todo!();
+    Triangle::face_normal(self)
   }
 
   fn midpoint(&self) -> Point3<f32> {
-    todo!();
+    Triangle::midpoint(self)
   }
 }
 
 impl<'a> Face for Quad<'a> {
+  // sum of the (unnormalized) normals of the abc and acd triangles, so it
+  // scales with area like the triangle normal and handles non planar quads
   fn face_normal(&self) -> Vector3<f32> {
-    todo!();
+    let ac = self.c.loc - self.a.loc;
+    (self.b.loc - self.a.loc).cross(ac) + ac.cross(self.d.loc - self.a.loc)
   }
 
   fn midpoint(&self) -> Point3<f32> {
-    todo!();
+    Point3 {
+      x: (self.a.loc.x + self.b.loc.x + self.c.loc.x + self.d.loc.x) / 4.0,
+      y: (self.a.loc.y + self.b.loc.y + self.c.loc.y + self.d.loc.y) / 4.0,
+      z: (self.a.loc.z + self.b.loc.z + self.c.loc.z + self.d.loc.z) / 4.0
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::InnerSpace;
+
+  fn vertex(x: f32, z: f32) -> TriVertex<'static> {
+    TriVertex::new(Point3::new(x, 0., z), 0, None)
+  }
+
+  fn locs(triangle: &Triangle) -> [Point3<f32>; 3] {
+    [triangle.a.loc, triangle.b.loc, triangle.c.loc]
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn quads_split_along_the_shorter_diagonal() {
+    // a-c is the short diagonal
+    let quad = Quad::new(vertex(0., 0.), vertex(2., 0.), vertex(1., 1.), vertex(0., 2.));
+    let [first, second] = quad.to_triangles();
+    assert_eq!(locs(&first), [quad.a.loc, quad.b.loc, quad.c.loc]);
+    assert_eq!(locs(&second), [quad.a.loc, quad.c.loc, quad.d.loc]);
+
+    // b-d is the short diagonal
+    let quad = Quad::new(vertex(0., 0.), vertex(1., 0.), vertex(2., 2.), vertex(0., 1.));
+    let [first, second] = quad.to_triangles();
+    assert_eq!(locs(&first), [quad.a.loc, quad.b.loc, quad.d.loc]);
+    assert_eq!(locs(&second), [quad.b.loc, quad.c.loc, quad.d.loc]);
+  }
+
+  #[test]
+  fn split_triangles_keep_the_quad_winding() {
+    let quad = Quad::new(vertex(0., 0.), vertex(0., 1.), vertex(1., 1.), vertex(1., 0.));
+    let quad_normal = quad.face_normal();
+    for triangle in quad.to_triangles() {
+      assert!(triangle.face_normal().dot(quad_normal) > 0.);
+    }
+  }
+
+  #[test]
+  fn covered_keys_match_triangles_in_any_order() {
+    let quad = Quad::new(vertex(0., 0.), vertex(0., 1.), vertex(1., 1.), vertex(1., 0.));
+    let keys = quad.covered_keys();
+    let triangle = Triangle::new(vertex(1., 0.), vertex(0., 0.), vertex(1., 1.));
+    assert!(keys.contains(&triangle.corner_key()));
+    let outside = Triangle::new(vertex(1., 0.), vertex(0., 0.), vertex(2., 2.));
+    assert!(!keys.contains(&outside.corner_key()));
+  }
+}
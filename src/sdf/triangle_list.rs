@@ -11,7 +11,7 @@ use std::path::Iter;
 
 impl<'a> Hash for Triangle<'a> {
   fn hash<H: Hasher>(&self, state: &mut H) {
-    self.hash_str().hash(state)
+    self.hash_key().hash(state)
   }
 }
 
@@ -58,3 +58,39 @@ impl<'a> TriangleSet<'a> {
     return out;
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::Point3;
+  use crate::sdf::triangle::TriVertex;
+
+  fn triangle(locs: [(f32, f32, f32); 3]) -> Triangle<'static> {
+    let [a, b, c] = locs.map(|(x, y, z)| TriVertex::new(Point3::new(x, y, z), 0, None));
+    Triangle::new(a, b, c)
+  }
+
+  #[test]
+  fn rotations_of_a_triangle_are_one_member() {
+    let (a, b, c) = ((0., 0., 0.), (1., 0., 0.), (0., 1., 0.));
+    let mut set = TriangleSet::new();
+    set.insert(triangle([a, b, c]));
+    set.insert(triangle([b, c, a]));
+    set.insert(triangle([c, a, b]));
+    assert_eq!(set.iter().count(), 1);
+    assert!(set.has(&triangle([c, a, b])));
+
+    assert!(set.remove(&triangle([b, c, a])));
+    assert_eq!(set.iter().count(), 0);
+    assert!(!set.has(&triangle([a, b, c])));
+  }
+
+  #[test]
+  fn triangles_sharing_a_midpoint_stay_distinct() {
+    // same centroid, different corners
+    let mut set = TriangleSet::new();
+    set.insert(triangle([(0., 0., 0.), (3., 0., 0.), (0., 3., 0.)]));
+    set.insert(triangle([(1., 1., 0.), (2., 0., 0.), (0., 2., 0.)]));
+    assert_eq!(set.iter().count(), 2);
+  }
+}
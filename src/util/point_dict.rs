@@ -1,5 +1,5 @@
 use std::{any::Any, collections::{hash_map, HashMap}, fmt::format};
-use cgmath::Point3;
+use cgmath::{MetricSpace, Point3};
 use std::hash::{
   Hash, Hasher
 };
@@ -23,24 +23,89 @@ impl Hash for Point {
   }
 }
 
+const DEFAULT_CELL_SIZE: f32 = 1.;
+
+type Cell = (i32, i32, i32);
+
+// Hash map keyed by points. Keys are also bucketed into a uniform grid of
+// cells so nearest() only has to look at the cells overlapping the query
 pub struct PointDict<T> {
   map: HashMap<Point, T>,
+  cells: HashMap<Cell, Vec<Point3<f32>>>,
+  cell_size: f32,
 }
 
 impl<T> PointDict<T> {
   pub fn new() -> PointDict<T> {
+    Self::with_cell_size(DEFAULT_CELL_SIZE)
+  }
+
+  // cell_size should be around the typical query radius
+  pub fn with_cell_size(cell_size: f32) -> PointDict<T> {
     let map: HashMap<Point, T> = HashMap::new();
     PointDict {
-      map
+      map,
+      cells: HashMap::new(),
+      cell_size: if cell_size > 0. { cell_size } else { DEFAULT_CELL_SIZE }
     }
   }
 
+  fn cell_of(&self, pt: &Point3<f32>) -> Cell {
+    (
+      (pt.x / self.cell_size).floor() as i32,
+      (pt.y / self.cell_size).floor() as i32,
+      (pt.z / self.cell_size).floor() as i32,
+    )
+  }
+
   pub fn insert(&mut self, key: Point3<f32>, val: T) -> Option<T> {
-    self.map.insert(Point(key), val)
+    let prev = self.map.insert(Point(key), val);
+    if prev.is_none() {
+      let cell = self.cell_of(&key);
+      self.cells.entry(cell).or_insert(Vec::new()).push(key);
+    }
+    prev
   }
 
   pub fn remove(&mut self, key: &Point3<f32>) -> Option<T> {
-    self.map.remove(&Point(key.clone()))
+    let removed = self.map.remove(&Point(key.clone()));
+    if removed.is_some() {
+      let cell = self.cell_of(key);
+      if let Some(points) = self.cells.get_mut(&cell) {
+        points.retain(|p| Point(*p) != Point(*key));
+        if points.len() == 0 {
+          self.cells.remove(&cell);
+        }
+      }
+    }
+    removed
+  }
+
+  // every entry within radius of point, nearest first
+  pub fn nearest(&self, point: &Point3<f32>, radius: f32) -> Vec<(&Point, &T)> {
+    let min = self.cell_of(&Point3::new(point.x - radius, point.y - radius, point.z - radius));
+    let max = self.cell_of(&Point3::new(point.x + radius, point.y + radius, point.z + radius));
+    let mut found: Vec<(f32, (&Point, &T))> = Vec::new();
+    for x in min.0..=max.0 {
+      for y in min.1..=max.1 {
+        for z in min.2..=max.2 {
+          let Some(points) = self.cells.get(&(x, y, z)) else {
+            continue;
+          };
+          for pt in points.iter() {
+            let dist = pt.distance(*point);
+            if dist > radius {
+              continue;
+            }
+            if let Some(entry) = self.map.get_key_value(&Point(*pt)) {
+              found.push((dist, entry));
+            }
+          }
+        }
+      }
+    }
+    found.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    found.into_iter().map(|(_, entry)| entry).collect()
   }
 
   pub fn get(&self, key: &Point3<f32>) -> Option<&T> {
@@ -54,4 +119,62 @@ impl<T> PointDict<T> {
   pub fn contains_key(&self, key: &Point3<f32>) -> bool {
     self.map.contains_key(&Point(key.clone()))
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // points on an n^3 integer grid, each storing its own coordinates
+  fn grid(n: i32) -> PointDict<(i32, i32, i32)> {
+    let mut dict = PointDict::new();
+    for x in 0..n {
+      for y in 0..n {
+        for z in 0..n {
+          dict.insert(Point3::new(x as f32, y as f32, z as f32), (x, y, z));
+        }
+      }
+    }
+    dict
+  }
+
+  #[test]
+  fn insert_and_lookup_by_exact_point() {
+    let mut dict = grid(3);
+    assert_eq!(dict.get(&Point3::new(1., 2., 0.)), Some(&(1, 2, 0)));
+    assert!(!dict.contains_key(&Point3::new(0.5, 0., 0.)));
+    assert_eq!(dict.insert(Point3::new(1., 2., 0.), (9, 9, 9)), Some((1, 2, 0)));
+    assert_eq!(dict.remove(&Point3::new(1., 2., 0.)), Some((9, 9, 9)));
+    assert!(dict.get(&Point3::new(1., 2., 0.)).is_none());
+    assert!(dict.nearest(&Point3::new(1., 2., 0.), 0.1).is_empty());
+  }
+
+  #[test]
+  fn nearest_returns_exactly_the_points_in_radius() {
+    let dict = grid(5);
+    let center = Point3::new(2., 2., 2.);
+    // the center and its six face neighbors, but not the edge diagonals at sqrt(2)
+    let found = dict.nearest(&center, 1.2);
+    assert_eq!(found.len(), 7);
+    assert_eq!(*found[0].1, (2, 2, 2));
+    assert!(found.iter().all(|(pt, _)| pt.0.distance(center) <= 1.2));
+
+    // a radius spanning several cells matches a brute force scan
+    let radius = 2.5;
+    let expected = dict.iter().filter(|(pt, _)| pt.0.distance(center) <= radius).count();
+    let found = dict.nearest(&center, radius);
+    assert_eq!(found.len(), expected);
+    let dists = found.iter().map(|(pt, _)| pt.0.distance(center)).collect::<Vec<f32>>();
+    assert!(dists.windows(2).all(|pair| pair[0] <= pair[1]));
+  }
+
+  #[test]
+  fn nearest_works_across_negative_cells() {
+    let mut dict = PointDict::with_cell_size(0.5);
+    dict.insert(Point3::new(-0.2, 0., 0.), "left");
+    dict.insert(Point3::new(0.2, 0., 0.), "right");
+    dict.insert(Point3::new(-2., 0., 0.), "far");
+    let found = dict.nearest(&Point3::new(0., 0., 0.), 0.5);
+    assert_eq!(found.len(), 2);
+  }
+}